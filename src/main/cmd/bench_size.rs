@@ -0,0 +1,90 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::build::{dest_wasm_path, execute_build, BuildOptions, OptimizationLevel, Variant};
+use crate::{workspace::ManifestPath, AnalysisBehavior, VerbosityBehavior};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+/// Optimization passes which the contract is built with, `None` skips wasm-opt.
+const PASSES: [(&str, Option<OptimizationLevel>); 3] = [
+    ("no-wasm-opt", None),
+    ("O3", Some(OptimizationLevel::Three)),
+    ("Oz", Some(OptimizationLevel::SizeAggressive)),
+];
+
+/// The final size of the Wasm bytecode built with an optimization pass, and how long it took.
+pub(crate) struct SizeMeasurement {
+    pub pass: &'static str,
+    pub size: u64,
+    pub duration: Duration,
+}
+
+pub(crate) struct SizeBenchmark {
+    pub measurements: Vec<SizeMeasurement>,
+}
+
+/// Builds the contract without wasm-opt, with `-O3` and with `-Oz`, and measures the size of the
+/// Wasm bytecode and the duration of every build.
+///
+/// Artifacts of every pass are written to `<name>-<pass>.wasm`, so that the artifacts of normal
+/// builds are left untouched. Only the first build compiles the contract, the following ones
+/// reuse it, so their durations are dominated by wasm-opt.
+pub(crate) fn execute_bench_size(
+    manifest_path: ManifestPath,
+    verbosity: VerbosityBehavior,
+) -> Result<SizeBenchmark> {
+    let mut measurements = Vec::new();
+    for (pass, optimization_level) in PASSES.iter() {
+        eprintln!(
+            "{} {}",
+            "Building with".bright_green().bold(),
+            format!("`{}`", pass).bold()
+        );
+        let build_options = BuildOptions {
+            optimization_level: *optimization_level,
+            skip_wasm_opt: optimization_level.is_none(),
+            variant: Some(Variant {
+                name: (*pass).to_owned(),
+                features: Vec::new(),
+            }),
+            no_cache: true,
+            ..Default::default()
+        };
+        let started = Instant::now();
+        execute_build(
+            manifest_path.clone(),
+            false,
+            verbosity,
+            AnalysisBehavior::Skip,
+            &None,
+            build_options,
+        )
+        .context(format!("Building with `{}`", pass))?;
+        let duration = started.elapsed();
+
+        let dest_wasm = dest_wasm_path(&manifest_path, Some(pass))?;
+        let size = fs::metadata(&dest_wasm)
+            .context(format!("Reading metadata of '{}'", dest_wasm.display()))?
+            .len();
+        measurements.push(SizeMeasurement {
+            pass,
+            size,
+            duration,
+        });
+    }
+    Ok(SizeBenchmark { measurements })
+}
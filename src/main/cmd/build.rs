@@ -10,25 +10,44 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::{
+    build_cache,
+    build_inputs::{write_build_inputs, write_build_lock},
+    build_report::BuildReport,
+    call_graph::{analyze_stack_usage, strip_unreachable_exports, unused_exports},
+    client_crate::write_client_crate,
+    diff,
+    process_tree::{kill_child_processes, MemorySampler, CAN_KILL_CHILD_PROCESSES},
+    report_floats::execute_report_floats,
+    semver_check::{execute_semver_check, Compatibility},
+};
 use crate::{
+    config::GlobalConfig,
     utils,
-    workspace::{ManifestPath, Workspace},
+    workspace::{ManifestOverride, ManifestPath, Workspace, WorkspaceDependencyOverride},
     AnalysisBehavior, VerbosityBehavior,
 };
-use anyhow::{Context, Result};
+use anyhow::{Context, Error, Result};
 use colored::Colorize;
 use console::Emoji;
 use indicatif::HumanDuration;
 use itertools::Itertools;
-use parity_wasm::elements::{Module, Section};
+use parity_wasm::elements::{
+    CustomSection, DataSegment, Deserialize, ImportCountType, InitExpr, Instruction, Internal,
+    Module, Section, Serialize as _, VarUint32, VarUint7,
+};
+use serde::Serialize;
 use serde_json::{Map, Value};
 use std::{
-    collections::HashMap,
-    env, fs,
-    io::{self, Write},
+    collections::{BTreeMap, BTreeSet, HashMap},
+    env, fmt, fs,
+    io::{self, BufRead, BufReader, Write},
     path::{Path, PathBuf},
-    process::Command,
-    time::Instant,
+    process::{Command, ExitStatus, Stdio},
+    str::FromStr,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tiny_keccak::Hasher;
 
@@ -39,21 +58,757 @@ struct CrateMetadata {
     original_wasm: PathBuf,
     dest_wasm: PathBuf,
     dest_abi: PathBuf,
+    dest_hash: PathBuf,
+    dest_manifest: PathBuf,
+    /// Records the latest mtime of input files and the fingerprint of other inputs when the ABI
+    /// was generated.
+    abi_stamp: PathBuf,
     is_collaboration: bool,
+    entry_points: Vec<String>,
+    matrix: BTreeMap<String, Vec<String>>,
+    optimization_level: Option<OptimizationLevel>,
+    wasm_opt_path: Option<PathBuf>,
+    /// The limit of the size of the Wasm bytecode declared by `liquid.max-size`.
+    max_size: Option<usize>,
 }
 
 impl CrateMetadata {
     pub fn target_dir(&self) -> &Path {
         self.cargo_meta.target_directory.as_path()
     }
+
+    /// The sidecar file holding debug info split from the Wasm bytecode by `--split-debug`.
+    pub fn dest_debug(&self) -> PathBuf {
+        self.dest_wasm.with_extension("debug.wasm")
+    }
+
+    /// The root source file of the library target, i.e. `src/lib.rs` unless `[lib] path` is
+    /// specified. Falls back to `src/lib.rs` if the package has no library target.
+    pub fn lib_rs_path(&self) -> PathBuf {
+        self.root_package
+            .targets
+            .iter()
+            .find(|target| {
+                target
+                    .kind
+                    .iter()
+                    .any(|kind| ["lib", "cdylib", "rlib"].contains(&kind.as_str()))
+            })
+            .map(|target| target.src_path.clone())
+            .unwrap_or_else(|| {
+                self.root_package
+                    .manifest_path
+                    .with_file_name("src")
+                    .join("lib.rs")
+            })
+    }
+
+    /// Renames the Wasm bytecode and the artifacts named after it, i.e. the hash and the
+    /// manifest, to `name`, which is suffixed by the variant and `_gm` as the package name is.
+    fn set_wasm_name(&mut self, name: &str, variant: Option<&str>, use_gm: bool) {
+        let mut file_name = match variant {
+            Some(variant) => format!("{}-{}", name, variant),
+            None => name.to_owned(),
+        };
+        if use_gm {
+            file_name.push_str("_gm");
+        }
+        self.dest_wasm = self.target_dir().join(file_name + ".wasm");
+        self.dest_hash = self.dest_wasm.with_extension("hash");
+        self.dest_manifest = self.dest_wasm.with_extension("json");
+    }
+}
+
+/// A named set of features which the contract is built with in matrix mode.
+#[derive(Clone, Debug)]
+pub(crate) struct Variant {
+    pub name: String,
+    pub features: Vec<String>,
+}
+
+/// Options which tweak the behavior of `execute_build`.
+#[derive(Default, Clone)]
+pub(crate) struct BuildOptions {
+    /// The `Cargo.lock` used to resolve dependencies of the contract.
+    pub lockfile: Option<PathBuf>,
+    /// The local `liquid-lang` package to be patched into the temporary workspace.
+    pub liquid_path: Option<PathBuf>,
+    /// Patches applied on top of the manifest in the temporary workspace.
+    pub manifest_overrides: Vec<ManifestOverride>,
+    /// Versions of `[workspace.dependencies]` overridden in the temporary workspace.
+    pub workspace_dependency_overrides: Vec<WorkspaceDependencyOverride>,
+    /// Overrides of the cargo configuration forwarded to `cargo build`.
+    pub cargo_configs: Vec<CargoConfig>,
+    /// Raw arguments appended to the `xargo build` invocation.
+    pub xargo_args: Option<XargoArgs>,
+    /// Treats all warnings as errors, including warnings reported by Clippy.
+    pub pedantic: bool,
+    /// Artifacts to be produced, the default set is used if it's empty.
+    pub emit: Vec<Artifact>,
+    /// Removes data segments rewriting the same bytes at the same offset and trims zero bytes at
+    /// both ends of data segments.
+    pub trim_data: bool,
+    /// Removes exports unreachable from the entry points, along with the functions only they
+    /// use, before wasm-opt.
+    pub strip_unreachable: bool,
+    /// Prints what changed in the Wasm bytecode since the last build.
+    pub diff_artifacts: bool,
+    /// Writes a Markdown report of the build to the path.
+    pub report: Option<PathBuf>,
+    /// Writes metrics of the final Wasm bytecode, e.g. the number of functions, in JSON to the
+    /// path.
+    pub metrics: Option<PathBuf>,
+    /// Records SHA-256 hashes of all source files the contract is built from in
+    /// `<package>.inputs.json`, and ties them to the Wasm bytecode in `<package>.build.lock`.
+    pub report_build_inputs: bool,
+    /// Writes the Wasm bytecode to stdout, all other messages are written to stderr.
+    pub stdout: bool,
+    /// The version recorded in the artifact instead of the version of the package.
+    pub version_override: Option<String>,
+    /// Prints the `BuildResult` in JSON format instead of a human readable message.
+    pub output_json: bool,
+    /// Collects compiler messages into the `BuildResult`.
+    pub json_diagnostics: bool,
+    /// Writes compiler messages to stdout as a JSON array, all other messages are written to
+    /// stderr.
+    pub emit_json_diagnostics: bool,
+    /// The ABI file which the newly generated ABI is checked against.
+    pub abi_baseline: Option<PathBuf>,
+    /// Don't fail even if the ABI is incompatible with the baseline.
+    pub allow_abi_break: bool,
+    /// The layout of the output directory.
+    pub output_format: OutputFormat,
+    /// Leaves temporary workspaces used for building on disk.
+    pub keep_temp: bool,
+    /// The optimization level passed to `wasm-opt`, the configured one is used if it's not set.
+    pub optimization_level: Option<OptimizationLevel>,
+    /// Skips optimizing the Wasm bytecode with `wasm-opt`.
+    pub skip_wasm_opt: bool,
+    /// The maximum size of the final Wasm bytecode in bytes.
+    pub max_size: Option<usize>,
+    /// The minimum size of the final Wasm bytecode in bytes.
+    pub min_size: Option<usize>,
+    /// Prints the offset, size and content of every data segment.
+    pub report_data_segments: bool,
+    /// Fails if the final Wasm bytecode contains any floating-point instruction.
+    pub deny_floats: bool,
+    /// Only warns instead of failing if the Wasm module has a start function.
+    pub allow_start_function: bool,
+    /// Moves custom sections to `<name>.debug.wasm` instead of dropping them.
+    pub split_debug: bool,
+    /// How panics of the contract are handled.
+    pub panic: PanicStrategy,
+    /// Prints plain output for environments without a terminal, e.g. Docker build steps: no
+    /// colors, emojis or progress bars, neither by cargo-liquid nor by cargo.
+    pub non_interactive: bool,
+    /// Generates a client crate with bindings of the contract from its ABI instead of building
+    /// the Wasm bytecode.
+    pub interface_mode: bool,
+    /// Silences messages of the build process, only warnings, errors and the result are printed.
+    /// This is also set by `execute_build` if the verbosity is quiet.
+    pub quiet: bool,
+    /// Exports kept by tree-shaking in addition to the entry points.
+    pub keep_exports: Vec<String>,
+    /// Removes sections which are rejected by some older runtimes, e.g. the data count section.
+    pub legacy_wasm: bool,
+    /// Inlines constant values of globals which are never written before running wasm-opt.
+    pub const_propagation: bool,
+    /// Strips source file paths of panic locations from the data section.
+    pub strip_panic_messages: bool,
+    /// The wasm-opt binary to use instead of the configured one or the one found in `PATH`.
+    pub wasm_opt_path: Option<PathBuf>,
+    /// Reports the deepest call chains and the estimated peak stack usage.
+    pub emit_stack_usage: bool,
+    /// Where to write the artifact manifest instead of `<name>.json` in the target directory.
+    pub manifest_out: Option<PathBuf>,
+    /// Describes the purpose of every phase of the build.
+    pub explain: bool,
+    /// Reports exports which are neither entry points nor required by the chain.
+    pub report_unused_exports: bool,
+    /// Skips tree-shaking by `pwasm_utils`.
+    pub no_optimize_internal: bool,
+    /// Copies the Wasm bytecode produced by cargo without any processing before `wasm-opt`.
+    pub skip_post_process: bool,
+    /// Keeps the name section and other debug info in the Wasm bytecode.
+    pub preserve_names: bool,
+    /// Keeps all custom sections in the Wasm bytecode, not only debug info.
+    pub no_strip_sections: bool,
+    /// Injects gas metering into the Wasm bytecode.
+    pub inject_gas: bool,
+    /// Injects a limiter of the stack height into the Wasm bytecode.
+    pub stack_limit: Option<u32>,
+    /// Builds the contract once for every variant in `[package.metadata.liquid.matrix]`.
+    pub matrix: bool,
+    /// Builds every member of the workspace which depends on `liquid_lang`.
+    pub workspace: bool,
+    /// Continues building remaining members of the workspace after a failure.
+    pub keep_going: bool,
+    /// Kills the build of the cargo project if it takes longer than this.
+    pub build_timeout: Option<Duration>,
+    /// Reports the peak resident memory of the build, including child processes.
+    pub report_memory: bool,
+    /// Prints the `RUSTFLAGS` used to build the contract and exits without building.
+    pub print_rustflags: bool,
+    /// The variant being built in matrix mode, its name is appended to names of the artifacts.
+    pub variant: Option<Variant>,
+    /// Builds the contract even if nothing changed since the last build.
+    pub no_cache: bool,
+    /// The template of the name of the Wasm bytecode, which replaces the package name.
+    pub name_template: Option<NameTemplate>,
+}
+
+/// Optimization levels supported by `wasm-opt`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub(crate) enum OptimizationLevel {
+    Zero,
+    One,
+    Two,
+    #[default]
+    Three,
+    Four,
+    /// Optimizes for size.
+    Size,
+    /// Optimizes aggressively for size.
+    SizeAggressive,
+}
+
+impl FromStr for OptimizationLevel {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        // Also accept flags of wasm-opt, e.g. `-Oz` or `Oz`.
+        let level = s
+            .strip_prefix("-O")
+            .or_else(|| s.strip_prefix('O'))
+            .unwrap_or(s);
+        match level {
+            "0" => Ok(OptimizationLevel::Zero),
+            "1" => Ok(OptimizationLevel::One),
+            "2" => Ok(OptimizationLevel::Two),
+            "3" => Ok(OptimizationLevel::Three),
+            "4" => Ok(OptimizationLevel::Four),
+            "s" => Ok(OptimizationLevel::Size),
+            "z" => Ok(OptimizationLevel::SizeAggressive),
+            _ => anyhow::bail!(
+                "unknown optimization level `{}`, must be one of `0`, `1`, `2`, `3`, `4`, `s` \
+                 or `z`",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for OptimizationLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self {
+            OptimizationLevel::Zero => "0",
+            OptimizationLevel::One => "1",
+            OptimizationLevel::Two => "2",
+            OptimizationLevel::Three => "3",
+            OptimizationLevel::Four => "4",
+            OptimizationLevel::Size => "s",
+            OptimizationLevel::SizeAggressive => "z",
+        };
+        write!(f, "{}", level)
+    }
+}
+
+/// Layouts of the output directory.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub(crate) enum OutputFormat {
+    /// Artifacts are placed directly in the target directory.
+    #[default]
+    Liquid,
+    /// Additionally places the Wasm bytecode and a `metadata.json` in ink! format in
+    /// `target/ink/<package>/`, as `cargo-contract` does.
+    CargoContract,
+}
+
+impl FromStr for OutputFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "liquid" => Ok(OutputFormat::Liquid),
+            "cargo-contract" => Ok(OutputFormat::CargoContract),
+            _ => anyhow::bail!(
+                "unknown output format `{}`, must be `liquid` or `cargo-contract`",
+                s
+            ),
+        }
+    }
+}
+
+/// How panics of the contract are handled.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Default)]
+pub(crate) enum PanicStrategy {
+    /// Aborts with the panic runtime of the core library, which keeps panic messages.
+    Abort,
+    /// Aborts immediately without formatting panic messages, which is the smallest.
+    #[default]
+    ImmediateAbort,
+    /// Unwinds the stack, which is the largest and requires runtime support of exceptions.
+    Unwind,
+}
+
+impl FromStr for PanicStrategy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "abort" => Ok(PanicStrategy::Abort),
+            "immediate-abort" => Ok(PanicStrategy::ImmediateAbort),
+            "unwind" => Ok(PanicStrategy::Unwind),
+            _ => anyhow::bail!(
+                "unknown panic strategy `{}`, must be one of `abort`, `immediate-abort` or \
+                 `unwind`",
+                s
+            ),
+        }
+    }
+}
+
+/// A `KEY=VALUE` override of the cargo configuration, passed to cargo as `--config KEY=VALUE`.
+#[derive(Clone, Debug)]
+pub(crate) struct CargoConfig(String);
+
+impl FromStr for CargoConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (key, value) = s.split_once('=').ok_or_else(|| {
+            anyhow::anyhow!(
+                "`{}` is not a valid cargo config override, it should be like \
+                 `profile.release.lto=true`",
+                s
+            )
+        })?;
+        if key.trim().is_empty() || value.trim().is_empty() {
+            anyhow::bail!("both the key and the value of `{}` must not be empty", s);
+        }
+        // Cargo interprets the override as a TOML key-value pair, e.g. strings must be quoted.
+        s.parse::<toml::Value>().context(format!(
+            "`{}` is not a valid cargo config override, the key must be a dotted TOML key and \
+             the value must be a TOML value, e.g. strings must be quoted",
+            s
+        ))?;
+        Ok(CargoConfig(s.to_owned()))
+    }
+}
+
+/// Raw arguments appended to the `xargo build` invocation, split at whitespaces.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct XargoArgs(Vec<String>);
+
+impl FromStr for XargoArgs {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let args = s.split_whitespace().map(str::to_owned).collect::<Vec<_>>();
+        if let Some(arg) = args
+            .iter()
+            .find(|arg| *arg == "--target-dir" || arg.starts_with("--target-dir="))
+        {
+            anyhow::bail!(
+                "`{}` can't be passed to xargo, since the target directory is set by cargo-liquid",
+                arg
+            );
+        }
+        Ok(XargoArgs(args))
+    }
+}
+
+/// Placeholders supported in templates of artifact names.
+const NAME_PLACEHOLDERS: [&str; 4] = ["package", "version", "hash", "profile"];
+
+/// The length of the hash of the Wasm bytecode in hex digits substituted for `{hash}`.
+const NAME_HASH_LEN: usize = 8;
+
+/// A template of the name of the Wasm bytecode, e.g. `{package}-{version}`.
+#[derive(Clone, Debug)]
+pub(crate) struct NameTemplate(String);
+
+impl FromStr for NameTemplate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            anyhow::bail!("the name template must not be empty");
+        }
+        if s.contains(['/', '\\'].as_ref()) {
+            anyhow::bail!("the name template `{}` must not contain path separators", s);
+        }
+        let mut rest = s;
+        while let Some(start) = rest.find(['{', '}'].as_ref()) {
+            if rest[start..].starts_with('}') {
+                anyhow::bail!("unmatched `}}` in the name template `{}`", s);
+            }
+            let len = rest[start..]
+                .find('}')
+                .ok_or_else(|| anyhow::anyhow!("unclosed `{{` in the name template `{}`", s))?;
+            let placeholder = &rest[start + 1..start + len];
+            if !NAME_PLACEHOLDERS.contains(&placeholder) {
+                anyhow::bail!(
+                    "unknown placeholder `{{{}}}` in the name template `{}`, supported ones are {}",
+                    placeholder,
+                    s,
+                    NAME_PLACEHOLDERS
+                        .iter()
+                        .map(|placeholder| format!("`{{{}}}`", placeholder))
+                        .join(", ")
+                );
+            }
+            rest = &rest[start + len + 1..];
+        }
+        Ok(NameTemplate(s.to_owned()))
+    }
+}
+
+impl NameTemplate {
+    /// Whether the name depends on the hash of the Wasm bytecode, which is only known after the
+    /// bytecode is post-processed.
+    fn needs_hash(&self) -> bool {
+        self.0.contains("{hash}")
+    }
+
+    /// Substitutes all placeholders except `{hash}`, which is kept as it is.
+    fn render(&self, package: &str, version: &str) -> String {
+        self.0
+            .replace("{package}", package)
+            .replace("{version}", version)
+            .replace("{profile}", "release")
+    }
+}
+
+/// Artifacts and information about the contract produced by the build.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BuildResult {
+    /// The semantic version of the contract.
+    pub version: String,
+    /// Whether cargo reused the cached Wasm bytecode instead of recompiling the contract.
+    pub cached: bool,
+    pub dest_wasm: Option<PathBuf>,
+    pub dest_abi: Option<PathBuf>,
+    pub dest_hash: Option<PathBuf>,
+    pub dest_manifest: Option<PathBuf>,
+    /// The size of the Wasm bytecode in bytes before being optimized by wasm-opt.
+    pub original_size: Option<u64>,
+    /// The size of the Wasm bytecode in bytes after being optimized by wasm-opt.
+    pub optimized_size: Option<u64>,
+    /// Compiler messages reported by cargo, in the JSON format of `--message-format=json`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<Vec<Value>>,
+}
+
+/// Describes the artifacts of the contract for deployment tools, written as `<name>.json`.
+///
+/// Unlike the `liquid-build-info` section embedded in the Wasm bytecode, this is an external index
+/// of the artifacts.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ArtifactManifest<'a> {
+    wasm: Option<&'a Path>,
+    /// SHA-256 digest of the Wasm bytecode in hex.
+    sha256: String,
+    /// Size of the Wasm bytecode in bytes.
+    size: usize,
+    abi: Option<&'a Path>,
+    version: &'a str,
+    cargo_liquid_version: &'a str,
+}
+
+/// Metrics of the final Wasm bytecode written by `--metrics`, for tracking the complexity of the
+/// contract over time.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct WasmMetrics {
+    /// Functions defined by the module, imported ones are not included.
+    functions: usize,
+    types: usize,
+    imports: usize,
+    exports: usize,
+    globals: usize,
+    data_segments: usize,
+    /// Size of the Wasm bytecode in bytes.
+    size: usize,
+}
+
+impl WasmMetrics {
+    fn new(wasm: &[u8]) -> Result<Self> {
+        let module = Module::from_bytes(wasm)
+            .map_err(|e| anyhow::anyhow!("unable to parse the final Wasm bytecode: {}", e))?;
+        Ok(WasmMetrics {
+            functions: module
+                .function_section()
+                .map_or(0, |section| section.entries().len()),
+            types: module
+                .type_section()
+                .map_or(0, |section| section.types().len()),
+            imports: module
+                .import_section()
+                .map_or(0, |section| section.entries().len()),
+            exports: module
+                .export_section()
+                .map_or(0, |section| section.entries().len()),
+            globals: module
+                .global_section()
+                .map_or(0, |section| section.entries().len()),
+            data_segments: module
+                .data_section()
+                .map_or(0, |section| section.entries().len()),
+            size: wasm.len(),
+        })
+    }
+}
+
+/// Prints a message of the build process unless `--quiet` is given. If stdout is reserved for the
+/// Wasm bytecode or the JSON result, the message will be written to stderr instead.
+fn print_message(build_options: &BuildOptions, message: impl fmt::Display) {
+    if build_options.quiet {
+        return;
+    }
+    if build_options.reserves_stdout() {
+        eprintln!("{}", message);
+    } else {
+        println!("{}", message);
+    }
+}
+
+/// Numbers the steps of the build process, the total is the number of phases enabled by the build
+/// options.
+struct Progress {
+    current: usize,
+    total: usize,
+}
+
+impl Progress {
+    fn new(build_options: &BuildOptions) -> Self {
+        if build_options.interface_mode {
+            return Progress {
+                current: 0,
+                total: 3,
+            };
+        }
+        let optional_phases = [!build_options.skip_wasm_opt];
+        Progress {
+            current: 0,
+            total: 3 + optional_phases.iter().filter(|enabled| **enabled).count(),
+        }
+    }
+
+    fn step(&mut self, build_options: &BuildOptions, emoji: &Emoji<'_, '_>, description: &str) {
+        self.current += 1;
+        // Both emojis and their fallbacks are non-ASCII, which garbles plain logs.
+        if build_options.non_interactive {
+            print_message(
+                build_options,
+                format_args!("[{}/{}] {}", self.current, self.total, description),
+            );
+            return;
+        }
+        print_message(
+            build_options,
+            format_args!(
+                "[{}/{}] {} {}",
+                self.current, self.total, emoji, description
+            ),
+        );
+    }
+}
+
+/// Prints what a phase of the build does if `--explain` is given.
+fn explain(build_options: &BuildOptions, explanation: &str) {
+    if build_options.explain {
+        print_message(
+            build_options,
+            format_args!("      {}", explanation.bright_black()),
+        );
+    }
+}
+
+impl BuildOptions {
+    fn reserves_stdout(&self) -> bool {
+        self.stdout || self.output_json || self.emit_json_diagnostics
+    }
+
+    /// Whether custom sections must survive post-processing, to be split, restored or kept.
+    fn keeps_custom_sections(&self) -> bool {
+        self.split_debug || self.preserve_names || self.no_strip_sections
+    }
+
+    fn emits(&self, artifact: Artifact) -> bool {
+        if artifact == Artifact::Manifest && self.manifest_out.is_some() {
+            true
+        } else if self.emit.is_empty() {
+            DEFAULT_ARTIFACTS.contains(&artifact)
+        } else {
+            self.emit.contains(&artifact)
+        }
+    }
+}
+
+/// Artifacts which can be produced by the build.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Artifact {
+    /// The Wasm bytecode of the contract.
+    Wasm,
+    /// The ABI file of the contract.
+    Abi,
+    /// The hash of the Wasm bytecode, SM3 is used in GM mode, otherwise Keccak-256.
+    Hash,
+    /// A JSON descriptor of the other artifacts for deployment tools.
+    Manifest,
+}
+
+const DEFAULT_ARTIFACTS: [Artifact; 2] = [Artifact::Wasm, Artifact::Abi];
+
+impl FromStr for Artifact {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "wasm" => Ok(Artifact::Wasm),
+            "abi" => Ok(Artifact::Abi),
+            "hash" => Ok(Artifact::Hash),
+            "manifest" => Ok(Artifact::Manifest),
+            _ => anyhow::bail!(
+                "unknown artifact `{}`, must be one of `wasm`, `abi`, `hash` or `manifest`",
+                s
+            ),
+        }
+    }
 }
 
 const BUILD_TARGET_ARCH: &str = "wasm32-unknown-unknown";
 const LOCAL_SCOPE: &str = "$local";
+pub(crate) const DEFAULT_ENTRY_POINTS: [&str; 2] = ["main", "deploy"];
+/// Name of the stamp file which records the start time of the last successful build.
+const BUILD_STAMP: &str = ".liquid-build-time";
+/// Name of the custom section which records information about the build.
+const BUILD_INFO_SECTION: &str = "liquid-build-info";
+/// Exports which are not entry points but still required by the chain, they should never be
+/// removed by tree-shaking.
+pub(crate) const REQUIRED_EXPORTS: [&str; 2] = ["memory", "hash_type"];
+
+/// Reads the entry points of the contract from `[workspace.metadata.liquid]`, falling back to
+/// `[package.metadata.liquid]` if the workspace doesn't specify them.
+fn parse_entry_points(workspace_metadata: &Value, package_metadata: &Value) -> Result<Vec<String>> {
+    [workspace_metadata, package_metadata]
+        .iter()
+        .find_map(|metadata| metadata.pointer("/liquid/entry-points"))
+        .map_or_else(
+            || Ok(DEFAULT_ENTRY_POINTS.iter().map(|s| s.to_string()).collect()),
+            |entry_points| {
+                serde_json::from_value(entry_points.clone())
+                    .context("`liquid.entry-points` should be an array of strings")
+            },
+        )
+}
+
+/// Reads the feature sets of matrix builds from `[package.metadata.liquid.matrix]`, which maps
+/// names of variants to features enabled by them.
+fn parse_matrix(package_metadata: &Value) -> Result<BTreeMap<String, Vec<String>>> {
+    package_metadata.pointer("/liquid/matrix").map_or_else(
+        || Ok(BTreeMap::new()),
+        |matrix| {
+            serde_json::from_value(matrix.clone())
+                .context("`liquid.matrix` should be a table which maps names to arrays of features")
+        },
+    )
+}
+
+/// Reads settings of wasm-opt from `[package.metadata.liquid]`, a relative `wasm-opt-path` is
+/// resolved against the directory of the manifest.
+fn parse_wasm_opt_settings(
+    package_metadata: &Value,
+    manifest_dir: &Path,
+) -> Result<(Option<OptimizationLevel>, Option<PathBuf>)> {
+    let optimization_level = match package_metadata.pointer("/liquid/optimization-level") {
+        Some(level) => Some(
+            level
+                .as_str()
+                .map(str::to_owned)
+                .unwrap_or_else(|| level.to_string())
+                .parse()
+                .context("Parsing `liquid.optimization-level`")?,
+        ),
+        None => None,
+    };
+    let wasm_opt_path = match package_metadata.pointer("/liquid/wasm-opt-path") {
+        Some(path) => Some(
+            manifest_dir.join(
+                path.as_str()
+                    .context("`liquid.wasm-opt-path` should be a string")?,
+            ),
+        ),
+        None => None,
+    };
+    Ok((optimization_level, wasm_opt_path))
+}
+
+/// Parses a size like `24KB`, `24K`, `1.5 MB` or `24576 bytes`, units are powers of 1024 and a
+/// size without unit is in bytes.
+fn parse_size(size: &str) -> Result<usize> {
+    let size = size.trim();
+    let split = size
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(size.len());
+    let (number, unit) = size.split_at(split);
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" | "byte" | "bytes" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        unit => anyhow::bail!(
+            "unknown unit `{}` of size `{}`, must be one of `bytes`, `KB` or `MB`",
+            unit,
+            size
+        ),
+    };
+    let number = number
+        .parse::<f64>()
+        .ok()
+        .filter(|number| number.is_finite())
+        .ok_or_else(|| anyhow::anyhow!("`{}` is not a valid size", size))?;
+    Ok((number * multiplier as f64) as usize)
+}
+
+/// Reads `liquid.max-size` of the package metadata, which is either a number of bytes or a string
+/// with a unit, e.g. `"24KB"`.
+fn parse_max_size(package_metadata: &Value) -> Result<Option<usize>> {
+    let max_size = match package_metadata.pointer("/liquid/max-size") {
+        Some(max_size) => max_size,
+        None => return Ok(None),
+    };
+    let max_size = match max_size {
+        Value::Number(number) => number
+            .as_u64()
+            .map(|number| number as usize)
+            .ok_or_else(|| anyhow::anyhow!("`{}` is not a valid size", number)),
+        Value::String(size) => parse_size(size),
+        _ => Err(anyhow::anyhow!(
+            "the size should be a number of bytes or a string like \"24KB\""
+        )),
+    }
+    .context("Parsing `liquid.max-size`")?;
+    Ok(Some(max_size))
+}
 
-/// Parses the manifest and returns relevant metadata.
-fn collect_crate_metadata(manifest_path: &ManifestPath, use_gm: bool) -> Result<CrateMetadata> {
-    let (metadata, root_package_id) = utils::get_cargo_metadata(manifest_path)?;
+/// Parses the manifest and returns relevant metadata. If `variant` is set, its name is appended
+/// to names of the artifacts, e.g. `<name>-<variant>.wasm`.
+fn collect_crate_metadata(
+    manifest_path: &ManifestPath,
+    use_gm: bool,
+    variant: Option<&str>,
+) -> Result<CrateMetadata> {
+    // Resolve symlinks first, otherwise cargo may derive the target directory from the directory
+    // of the link instead of the one of the package, where the Wasm bytecode is actually written.
+    let manifest_path = manifest_path
+        .as_ref()
+        .canonicalize()
+        .context(format!("Resolving '{}'", manifest_path))
+        .and_then(ManifestPath::new)?;
+    let (metadata, root_package_id) = utils::get_cargo_metadata(&manifest_path)?;
 
     // Find the root package by id in the list of packages. It is logical error if the root
     // package is not found in the list.
@@ -65,6 +820,17 @@ fn collect_crate_metadata(manifest_path: &ManifestPath, use_gm: bool) -> Result<
         .clone();
     // Normalize the package name.
     let package_name = root_package.name.replace("-", "_");
+    let artifact_name = match variant {
+        Some(variant) => format!("{}-{}", package_name, variant),
+        None => package_name.clone(),
+    };
+
+    // The target directory may have been removed by `cargo clean`, create it upfront so that
+    // steps writing artifacts into it directly don't fail.
+    fs::create_dir_all(&metadata.target_directory).context(format!(
+        "Creating target directory '{}'",
+        metadata.target_directory.display()
+    ))?;
 
     let mut original_wasm = metadata.target_directory.clone();
     original_wasm.push(BUILD_TARGET_ARCH);
@@ -75,17 +841,21 @@ fn collect_crate_metadata(manifest_path: &ManifestPath, use_gm: bool) -> Result<
 
     let mut dest_wasm = metadata.target_directory.clone();
     if use_gm {
-        dest_wasm.push(package_name.clone() + "_gm");
+        dest_wasm.push(artifact_name.clone() + "_gm");
     } else {
-        dest_wasm.push(package_name.clone());
+        dest_wasm.push(artifact_name.clone());
     }
     dest_wasm.set_extension("wasm");
 
     let mut dest_abi = metadata.target_directory.clone();
-    dest_abi.push(package_name.clone());
+    dest_abi.push(artifact_name);
 
     dest_abi.set_extension("abi");
 
+    let dest_hash = dest_wasm.with_extension("hash");
+    let dest_manifest = dest_wasm.with_extension("json");
+    let abi_stamp = dest_abi.with_extension("abi-stamp");
+
     let lang_dep = root_package
         .dependencies
         .iter()
@@ -97,6 +867,17 @@ fn collect_crate_metadata(manifest_path: &ManifestPath, use_gm: bool) -> Result<
         .iter()
         .any(|feature| feature == "collaboration");
 
+    let entry_points = parse_entry_points(&metadata.workspace_metadata, &root_package.metadata)?;
+    let matrix = parse_matrix(&root_package.metadata)?;
+    let (optimization_level, wasm_opt_path) = parse_wasm_opt_settings(
+        &root_package.metadata,
+        root_package
+            .manifest_path
+            .parent()
+            .expect("the manifest path is a file path so has a parent"),
+    )?;
+    let max_size = parse_max_size(&root_package.metadata)?;
+
     let crate_metadata = CrateMetadata {
         cargo_meta: metadata,
         root_package,
@@ -104,101 +885,558 @@ fn collect_crate_metadata(manifest_path: &ManifestPath, use_gm: bool) -> Result<
         original_wasm,
         dest_wasm,
         dest_abi,
+        dest_hash,
+        dest_manifest,
+        abi_stamp,
         is_collaboration,
+        entry_points,
+        matrix,
+        optimization_level,
+        wasm_opt_path,
+        max_size,
     };
 
     Ok(crate_metadata)
 }
 
+/// Formats the `cargo build` invocation performed by xargo, so that it can be run directly to
+/// reproduce a failed build.
+///
+/// The build is actually performed in a temporary copy of the workspace, the original manifest is
+/// shown here instead as the copy has been removed when the command is printed.
+fn equivalent_cargo_command(
+    crate_metadata: &CrateMetadata,
+    sysroot_path: &Path,
+    other_args: &[&str],
+) -> String {
+    let rustflags = env::var("RUSTFLAGS").unwrap_or_default();
+    let rustflags = format!("{} --sysroot {}", rustflags, sysroot_path.display());
+    let mut command = format!("RUSTFLAGS={}", utils::shell_quote(rustflags.trim()));
+    if let Ok(wrapper) = env::var("RUSTC_WRAPPER") {
+        if !wrapper.is_empty() {
+            command.push_str(&format!(" RUSTC_WRAPPER={}", utils::shell_quote(&wrapper)));
+        }
+    }
+    let manifest_path = format!(
+        "--manifest-path={}",
+        crate_metadata.root_package.manifest_path.display()
+    );
+    let args = [
+        "cargo",
+        "build",
+        "--target",
+        BUILD_TARGET_ARCH,
+        &manifest_path,
+    ]
+    .iter()
+    .chain(other_args)
+    .map(|arg| utils::shell_quote(arg))
+    .join(" ");
+    format!("{} {}", command, args)
+}
+
+/// What `build_cargo_project` produces besides the Wasm bytecode.
+struct CargoBuildOutput {
+    /// The result of the conflict fields analysis in JSON, empty if the analysis is skipped.
+    analysis: String,
+    /// Compiler messages reported by cargo, only collected if `json_diagnostics` is set.
+    diagnostics: Vec<Value>,
+}
+
+/// Runs the build with `--message-format=json` to collect compiler messages, which cargo prints
+/// to stdout, shared with other output of `cargo-liquid` otherwise. Messages are rendered to
+/// stderr as well, like in a plain build.
+///
+/// xargo takes care of the sysroot only, which must be built at `sysroot_path` already.
+fn build_with_diagnostics(
+    manifest_path: &Path,
+    sysroot_path: &Path,
+    other_args: &[String],
+) -> Result<(ExitStatus, Vec<Value>)> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let rustflags = env::var("RUSTFLAGS").unwrap_or_default();
+    let mut cmd = Command::new(cargo);
+    cmd.arg("build")
+        .arg("--target")
+        .arg(BUILD_TARGET_ARCH)
+        .arg(format!(
+            "--manifest-path={}",
+            manifest_path.to_string_lossy()
+        ))
+        .args(other_args)
+        .arg("--message-format=json")
+        .env(
+            "RUSTFLAGS",
+            format!("{} --sysroot {}", rustflags, sysroot_path.display()).trim(),
+        )
+        .stdout(Stdio::piped());
+    let mut child = cmd
+        .spawn()
+        .context(format!("Error executing `{:?}`", cmd))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let mut diagnostics = Vec::new();
+    for line in BufReader::new(stdout).lines() {
+        let line = line.context("Reading output of cargo")?;
+        let mut message = match serde_json::from_str::<Value>(&line) {
+            Ok(message) if message["reason"] == "compiler-message" => message,
+            _ => continue,
+        };
+        if let Some(rendered) = message["message"]["rendered"].as_str() {
+            eprint!("{}", rendered);
+        }
+        diagnostics.push(message["message"].take());
+    }
+    let exit_status = child
+        .wait()
+        .context(format!("Error executing `{:?}`", cmd))?;
+    Ok((exit_status, diagnostics))
+}
+
 fn run_xargo_build(
     crate_metadata: &CrateMetadata,
     use_gm: bool,
     verbosity_behavior: VerbosityBehavior,
     skip_analysis: bool,
-) -> Result<String> {
+    build_options: &BuildOptions,
+) -> Result<CargoBuildOutput> {
     utils::check_channel()?;
 
     let xbuild = |manifest_path: &ManifestPath| {
+        if let VerbosityBehavior::Verbose = verbosity_behavior {
+            print_message(
+                build_options,
+                format_args!("manifest_path: {:?}", manifest_path),
+            );
+        }
         let manifest_dir = manifest_path.as_ref().parent().unwrap();
         if !skip_analysis {
             env::set_var("LIQUID_ANALYSIS_TARGET_DIR", manifest_dir);
         }
 
-        let manifest_path = Some(manifest_path);
         let target = Some(BUILD_TARGET_ARCH);
         let target_dir = crate_metadata.target_dir();
         let target_dir_arg = format!("--target-dir={}", target_dir.to_string_lossy());
         let mut other_args = ["--no-default-features", "--release", &target_dir_arg].to_vec();
+        let mut features = build_options
+            .variant
+            .iter()
+            .flat_map(|variant| variant.features.iter().map(String::as_str))
+            .collect::<Vec<_>>();
         if use_gm {
-            other_args.push("--features=gm");
+            features.push("gm");
+        }
+        let features_arg = format!("--features={}", features.join(","));
+        if !features.is_empty() {
+            other_args.push(&features_arg);
+        }
+        if build_options.lockfile.is_some() {
+            other_args.push("--locked");
+        }
+        for cargo_config in &build_options.cargo_configs {
+            other_args.push("--config");
+            other_args.push(&cargo_config.0);
+        }
+        if let Some(xargo_args) = &build_options.xargo_args {
+            other_args.extend(xargo_args.0.iter().map(String::as_str));
         }
 
-        let args = xargo_lib::Args::new(
-            target,
-            manifest_path,
-            Some(verbosity_behavior.into()),
-            other_args,
-        )
-        .map_err(|e| anyhow::anyhow!("{}", e))
-        .context("Creating xargo args")?;
-        let config = xargo_lib::Config {
-            sysroot_path: target_dir.join("sysroot"),
-            memcpy: false,
-            panic_immediate_abort: true,
-        };
-        if let VerbosityBehavior::Verbose = verbosity_behavior {
-            println!("manifest_path: {:#?}", manifest_path);
+        let sysroot_path = target_dir.join("sysroot");
+        // If compiler messages are collected, xargo only builds the sysroot, along with fetching
+        // dependencies which is required anyway, and the build itself is run by
+        // `build_with_diagnostics`.
+        let collect_diagnostics =
+            build_options.json_diagnostics || build_options.emit_json_diagnostics;
+        let mut fetch_args = Vec::new();
+        if build_options.lockfile.is_some() {
+            fetch_args.push("--locked");
+        }
+        for cargo_config in &build_options.cargo_configs {
+            fetch_args.push("--config");
+            fetch_args.push(&cargo_config.0);
+        }
+        if let Some(xargo_args) = &build_options.xargo_args {
+            fetch_args.extend(
+                xargo_args
+                    .0
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|arg| matches!(*arg, "--frozen" | "--offline")),
+            );
         }
-        let exit_status = xargo_lib::build(args, "build", Some(config))
+        let mut retried = false;
+        let (exit_status, diagnostics) = loop {
+            let (command, xargo_args) = if collect_diagnostics {
+                ("fetch", fetch_args.clone())
+            } else {
+                ("build", other_args.clone())
+            };
+            let args = xargo_lib::Args::new(
+                target,
+                Some(manifest_path),
+                Some(verbosity_behavior.into()),
+                xargo_args,
+            )
             .map_err(|e| anyhow::anyhow!("{}", e))
-            .context("Building with xargo")?;
+            .context("Creating xargo args")?;
+            let config = xargo_lib::Config {
+                sysroot_path: sysroot_path.clone(),
+                memcpy: false,
+                panic_immediate_abort: build_options.panic == PanicStrategy::ImmediateAbort,
+            };
+            let manifest_path = manifest_path.as_ref().to_path_buf();
+            let build_sysroot_path = sysroot_path.clone();
+            let build_args = other_args
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+            let build = run_with_timeout(build_options.build_timeout, move || {
+                let exit_status = xargo_lib::build(args, command, Some(config))?;
+                if !collect_diagnostics || !exit_status.success() {
+                    return Ok((exit_status, Vec::new()));
+                }
+                build_with_diagnostics(&manifest_path, &build_sysroot_path, &build_args)
+            })?;
+            match build {
+                Ok(output) => break output,
+                // Building the sysroot fails occasionally due to races of the filesystem, e.g.
+                // on network filesystems, a fresh sysroot usually fixes it.
+                Err(e) if !retried && is_sysroot_failure(&format!("{:#}", e)) => {
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "building the sysroot failed due to: {:#}, retrying with a fresh \
+                             sysroot",
+                            e
+                        )
+                        .bright_yellow()
+                    );
+                    if sysroot_path.exists() {
+                        fs::remove_dir_all(&sysroot_path)
+                            .context(format!("Removing sysroot '{}'", sysroot_path.display()))?;
+                    }
+                    retried = true;
+                }
+                Err(e) => {
+                    return Err(anyhow::anyhow!("{}", e)).context("Building with xargo");
+                }
+            }
+        };
+        if build_options.emit_json_diagnostics {
+            println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+        }
         if !exit_status.success() {
+            eprintln!(
+                "{}\n{}",
+                "The failed build is equivalent to running:".bright_yellow(),
+                equivalent_cargo_command(crate_metadata, &sysroot_path, &other_args)
+            );
+            // The build result is never printed, so that diagnostics are printed alone.
+            if build_options.json_diagnostics {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(
+                        &serde_json::json!({ "diagnostics": diagnostics })
+                    )?
+                );
+            }
             anyhow::bail!("xbuild failed with status {}", exit_status);
         }
 
-        if !skip_analysis {
+        let analysis = if !skip_analysis {
             fs::read_to_string(manifest_dir.join("conflict_fields.analysis")).map_err(|e| {
                 anyhow::anyhow!(
                     "unable to read results file of conflict fields analysis due to: {}",
                     e
                 )
-            })
+            })?
         } else {
-            Ok(String::new())
-        }
+            String::new()
+        };
+        Ok(CargoBuildOutput {
+            analysis,
+            diagnostics,
+        })
     };
 
-    Workspace::new(&crate_metadata.cargo_meta, &crate_metadata.root_package.id)?
+    let mut workspace =
+        Workspace::new(&crate_metadata.cargo_meta, &crate_metadata.root_package.id)?;
+    workspace.with_keep_temp(build_options.keep_temp);
+    if let Some(lockfile) = &build_options.lockfile {
+        workspace.with_lockfile(lockfile)?;
+    }
+    for dependency in &build_options.workspace_dependency_overrides {
+        workspace.with_workspace_dependency_override(&dependency.name, &dependency.version)?;
+    }
+    let liquid_patch = match &build_options.liquid_path {
+        Some(liquid_path) => Some(liquid_patch(crate_metadata, liquid_path)?),
+        None => None,
+    };
+    workspace
         .with_root_package_manifest(|manifest| {
             manifest
                 .with_removed_crate_type("rlib")?
-                .with_profile_release_lto(false)?;
+                .with_profile_release_lto(false)?
+                .with_profile_release_panic(match build_options.panic {
+                    PanicStrategy::Abort | PanicStrategy::ImmediateAbort => "abort",
+                    PanicStrategy::Unwind => "unwind",
+                })?;
+            if let Some((source, package_name, path)) = &liquid_patch {
+                manifest.with_patch(source, package_name, path)?;
+            }
+            for manifest_override in &build_options.manifest_overrides {
+                manifest.with_override(manifest_override)?;
+            }
             Ok(())
         })?
         .using_temp(xbuild)
 }
 
+/// Checks whether an error reported by xargo happened while building the sysroot, rather than
+/// while building the contract, which is reported by the exit status instead.
+///
+/// Every crate of the sysroot is built by `cargo rustc -p <crate>` in a temporary directory
+/// prefixed with `cargo-xbuild`, then its artifacts are copied into the sysroot. xargo reports
+/// the failed command or the failed copy, without the output of the compiler.
+fn is_sysroot_failure(message: &str) -> bool {
+    let build_failed = message.starts_with('`')
+        && message.contains(r#""rustc" "-p" "#)
+        && message.contains("cargo-xbuild")
+        && message.contains("` failed with exit code: ");
+    let copy_failed =
+        message.starts_with("copying files from `") && message.contains("cargo-xbuild");
+    build_failed || copy_failed
+}
+
+/// How long to wait for the build to return after its child processes are killed, before
+/// killing again, e.g. processes spawned in the meantime.
+const KILL_INTERVAL: Duration = Duration::from_secs(1);
+/// How many times child processes are killed before giving up on the build returning.
+const KILL_ATTEMPTS: usize = 10;
+
+/// Runs `build` on another thread and waits at most `timeout` for it to finish. Once the timeout
+/// expires, all child processes are killed, the thread is joined and an error is returned.
+fn run_with_timeout<T, F>(timeout: Option<Duration>, build: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None => return Ok(build()),
+    };
+    if !CAN_KILL_CHILD_PROCESSES {
+        anyhow::bail!("`--build-timeout` is only supported on Linux and Windows");
+    }
+    let (sender, receiver) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        // The receiver is gone if the build timed out, so the result can be discarded.
+        let _ = sender.send(build());
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(result) => {
+            let _ = handle.join();
+            Ok(result)
+        }
+        Err(_) => {
+            let stopped = (0..KILL_ATTEMPTS).any(|_| {
+                kill_child_processes();
+                receiver.recv_timeout(KILL_INTERVAL).is_ok()
+            });
+            if !stopped {
+                anyhow::bail!(
+                    "the build didn't finish within {} seconds, and it's still running after \
+                     killing its child processes",
+                    timeout.as_secs()
+                );
+            }
+            let _ = handle.join();
+            anyhow::bail!(
+                "the build didn't finish within {} seconds and was killed, run with `--verbose` \
+                 to find out where it hangs",
+                timeout.as_secs()
+            )
+        }
+    }
+}
+
+/// Checks that `liquid_path` is a directory containing the `liquid-lang` package, returns the
+/// source to be patched, the package name and the absolute path of the package.
+///
+/// The source is the git repository if the contract depends on `liquid-lang` by git, otherwise
+/// `crates-io`.
+fn liquid_patch(
+    crate_metadata: &CrateMetadata,
+    liquid_path: &Path,
+) -> Result<(String, String, PathBuf)> {
+    let manifest = liquid_path.join("Cargo.toml");
+    let content = fs::read_to_string(&manifest).context(format!(
+        "Reading '{}', `--liquid-path` must be a directory containing Cargo.toml",
+        manifest.display()
+    ))?;
+    let toml: toml::value::Table =
+        toml::from_str(&content).context(format!("Parsing '{}'", manifest.display()))?;
+    let package_name = toml
+        .get("package")
+        .and_then(|package| package.get("name"))
+        .and_then(toml::Value::as_str)
+        .unwrap_or_default();
+    // The package is published as `liquid_lang`, while Cargo regards `-` and `_` as equivalent.
+    if package_name.replace('_', "-") != "liquid-lang" {
+        anyhow::bail!(
+            "'{}' is not the manifest of `liquid-lang`, the package name is `{}`",
+            manifest.display(),
+            package_name
+        );
+    }
+
+    let source = crate_metadata
+        .root_package
+        .dependencies
+        .iter()
+        .find(|dependency| dependency.name.replace('_', "-") == "liquid-lang")
+        .and_then(|dependency| dependency.source.as_deref())
+        .and_then(|source| source.strip_prefix("git+"))
+        .map(|url| url.split(['?', '#']).next().unwrap_or(url))
+        .unwrap_or("crates-io")
+        .to_owned();
+    let path = liquid_path
+        .canonicalize()
+        .context(format!("Resolving '{}'", liquid_path.display()))?;
+    Ok((source, package_name.to_owned(), path))
+}
+
+/// Runs Clippy with its default lints against the contract and fails if any warning is reported,
+/// lints of the `pedantic` and `nursery` groups are not enabled.
+fn run_clippy(crate_metadata: &CrateMetadata, verbosity_behavior: VerbosityBehavior) -> Result<()> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut cmd = Command::new(cargo);
+    cmd.arg("clippy")
+        .arg(format!(
+            "--manifest-path={}",
+            crate_metadata.root_package.manifest_path.to_string_lossy()
+        ))
+        .arg(format!(
+            "--target-dir={}",
+            crate_metadata.target_dir().to_string_lossy()
+        ))
+        .arg(match verbosity_behavior {
+            VerbosityBehavior::Quiet | VerbosityBehavior::Normal => "--quiet",
+            VerbosityBehavior::Verbose => "--verbose",
+        })
+        .args(["--", "-D", "warnings"]);
+
+    let status = cmd
+        .status()
+        .context(format!("Error executing `{:?}`", cmd))?;
+    if !status.success() {
+        anyhow::bail!("Clippy reported warnings in pedantic mode, please fix them first");
+    }
+    Ok(())
+}
+
+/// Appends flags required by Wasm bytecode for the chain to `old_flags`.
+fn wasm_rustflags(old_flags: &str) -> String {
+    [
+        old_flags,
+        // add -C target-cpu=mvp try to fix https://github.com/rust-lang/rust/issues/109807
+        &format!(
+            "-C target-feature=-sign-ext -C target-cpu=mvp -C link-arg=-z -C link-arg=stack-size={}",
+            STACK_SIZE
+        ),
+    ]
+    .join(" ")
+}
+
+/// The `RUSTFLAGS` which `build_cargo_project` sets on top of `old_flags`, `None` if it's left
+/// unset.
+///
+/// Flags for Wasm are only appended if `RUSTFLAGS` is already set.
+fn build_rustflags(old_flags: Option<&str>, pedantic: bool) -> Option<String> {
+    let flags = old_flags.map(wasm_rustflags);
+    if pedantic {
+        Some([flags.as_deref().unwrap_or_default(), "-D warnings"].join(" "))
+    } else {
+        flags
+    }
+}
+
+/// The `RUSTFLAGS` which rustc finally receives when building the contract, including the
+/// `--sysroot` added by xargo.
+fn effective_rustflags(crate_metadata: &CrateMetadata, pedantic: bool) -> String {
+    let flags = build_rustflags(env::var("RUSTFLAGS").ok().as_deref(), pedantic);
+    format!(
+        "{} --sysroot {}",
+        flags.unwrap_or_default(),
+        crate_metadata.target_dir().join("sysroot").display()
+    )
+    .trim()
+    .to_owned()
+}
+
+/// The path of the final Wasm bytecode of the contract at `manifest_path`, built as `variant`.
+pub(crate) fn dest_wasm_path(
+    manifest_path: &ManifestPath,
+    variant: Option<&str>,
+) -> Result<PathBuf> {
+    Ok(collect_crate_metadata(manifest_path, false, variant)?.dest_wasm)
+}
+
+/// Lists environment variables which `build_cargo_project` and xargo set for building the
+/// contract at `manifest_path`, in the order they are set.
+///
+/// `LIQUID_ANALYSIS_TARGET_DIR` points to the directory of the original manifest, since the
+/// temporary workspace used by the build is removed afterwards.
+pub(crate) fn build_environment(
+    manifest_path: &ManifestPath,
+    analysis_behavior: AnalysisBehavior,
+) -> Result<Vec<(&'static str, String)>> {
+    let crate_metadata = collect_crate_metadata(manifest_path, false, None)?;
+    let mut vars = vec![("RUSTFLAGS", effective_rustflags(&crate_metadata, false))];
+    if analysis_behavior != AnalysisBehavior::Skip && !crate_metadata.is_collaboration {
+        vars.push(("RUSTC_WRAPPER", "liquid-analy".to_owned()));
+        vars.push((
+            "LIQUID_ANALYSIS_PROJECT",
+            crate_metadata.package_name.clone(),
+        ));
+        let manifest_dir = crate_metadata
+            .root_package
+            .manifest_path
+            .parent()
+            .expect("the manifest path is a file path so has a parent");
+        vars.push((
+            "LIQUID_ANALYSIS_TARGET_DIR",
+            manifest_dir.display().to_string(),
+        ));
+    }
+    vars.push((
+        "CARGO_TARGET_DIR",
+        crate_metadata.target_dir().display().to_string(),
+    ));
+    Ok(vars)
+}
+
 fn build_cargo_project(
     crate_metadata: &CrateMetadata,
     use_gm: bool,
     verbosity_behavior: VerbosityBehavior,
     analysis_behavior: AnalysisBehavior,
     cfg_path: &Option<PathBuf>,
-) -> Result<String> {
+    build_options: &BuildOptions,
+) -> Result<CargoBuildOutput> {
     const RUSTFLAGS_ENV_VAR: &str = "RUSTFLAGS";
     const RUSTC_WRAPPER_ENV_VAR: &str = "RUSTC_WRAPPER";
 
+    if build_options.pedantic {
+        // Clippy checks the project for the host, so it must be run before the flags used
+        // for building wasm are set.
+        run_clippy(crate_metadata, verbosity_behavior)?;
+    }
+
     let old_flags = env::var(RUSTFLAGS_ENV_VAR);
-    if let Ok(ref old_flags) = old_flags {
-        env::set_var(
-            RUSTFLAGS_ENV_VAR,
-            [
-                old_flags,
-                // add -C target-cpu=mvp try to fix https://github.com/rust-lang/rust/issues/109807
-                "-C target-feature=-sign-ext -C target-cpu=mvp -C link-arg=-z -C link-arg=stack-size=65536",
-            ]
-            .join(" "),
-        );
+    let flags = build_rustflags(old_flags.as_deref().ok(), build_options.pedantic);
+    let flags_changed = flags.is_some();
+    if let Some(flags) = flags {
+        env::set_var(RUSTFLAGS_ENV_VAR, flags);
     }
 
     if analysis_behavior == AnalysisBehavior::Enforce {
@@ -207,7 +1445,7 @@ fn build_cargo_project(
         drop(fs::remove_file(&crate_metadata.original_wasm));
     }
 
-    let mut old_wrapper = Err(env::VarError::NotPresent);
+    let mut old_wrapper = None;
     let skip_analysis =
         if analysis_behavior != AnalysisBehavior::Skip && !crate_metadata.is_collaboration {
             // Sets `RUSTC_WRAPPER` environment variable for current process, which leads
@@ -220,7 +1458,7 @@ fn build_cargo_project(
             // this project is significant. Via setting different `RUSTC` value we can
             // achieve this aim easily. But if we use `RUSTC` directly here, then it
             // becomes difficult to decide which version of rustc to use in liquid-analy.
-            old_wrapper = env::var(RUSTC_WRAPPER_ENV_VAR);
+            old_wrapper = Some(env::var(RUSTC_WRAPPER_ENV_VAR));
             env::set_var(RUSTC_WRAPPER_ENV_VAR, "liquid-analy");
 
             // The `LIQUID_ANALYSIS_PROJECT` environment variable is used to tell
@@ -244,30 +1482,34 @@ fn build_cargo_project(
             true
         };
 
-    let build_result = run_xargo_build(crate_metadata, use_gm, verbosity_behavior, skip_analysis);
+    let build_result = run_xargo_build(
+        crate_metadata,
+        use_gm,
+        verbosity_behavior,
+        skip_analysis,
+        build_options,
+    );
 
-    if analysis_behavior != AnalysisBehavior::Skip {
-        env::set_var(
-            RUSTFLAGS_ENV_VAR,
-            if let Ok(old_flags) = old_flags {
-                old_flags
-            } else {
-                "".into()
-            },
-        );
-        env::set_var(
-            RUSTC_WRAPPER_ENV_VAR,
-            if let Ok(old_wrapper) = old_wrapper {
-                old_wrapper
-            } else {
-                "".into()
-            },
-        );
+    // Only the variables changed above are restored, so that e.g. a `RUSTC_WRAPPER` set by
+    // the user is still in effect for the builds following this one.
+    if flags_changed {
+        restore_env_var(RUSTFLAGS_ENV_VAR, old_flags);
+    }
+    if let Some(old_wrapper) = old_wrapper {
+        restore_env_var(RUSTC_WRAPPER_ENV_VAR, old_wrapper);
     }
 
     build_result
 }
 
+/// Sets `key` back to `old_value`, or removes it if it wasn't set before.
+fn restore_env_var(key: &str, old_value: std::result::Result<String, env::VarError>) {
+    match old_value {
+        Ok(old_value) => env::set_var(key, old_value),
+        Err(_) => env::remove_var(key),
+    }
+}
+
 /// Strips all custom sections.
 ///
 /// Presently all custom sections are not required so they can be stripped safely.
@@ -280,374 +1522,2941 @@ fn strip_custom_sections(module: &mut Module) {
     });
 }
 
-/// Attempts to perform optional wasm optimization using `wasm-opt`.
+/// Removes the data count section, which is rejected by some older runtimes, returns whether it
+/// was present.
 ///
-/// The intention is to reduce the size of bloated wasm binaries as a result of missing
-/// optimizations (or bugs?) between Rust and Wasm.
+/// The section is only required by `memory.init` and `data.drop` of the bulk memory proposal.
+/// `parity_wasm` is built without support of these instructions, so a module which has been
+/// deserialized never uses them and the section can always be removed.
+fn strip_data_count_section(module: &mut Module) -> bool {
+    let sections = module.sections().len();
+    module
+        .sections_mut()
+        .retain(|section| !matches!(section, Section::DataCount(_)));
+    module.sections().len() != sections
+}
+
+/// Replaces reads of globals which are never written with their constant initial values, returns
+/// the number of reads replaced, which gives wasm-opt more opportunities to fold constants.
 ///
-/// This step depends on the `wasm-opt` tool being installed. If it is not the build will still
-/// succeed, and the user will be encouraged to install it for further optimizations.
-fn optimize_wasm(crate_metadata: &CrateMetadata) -> Result<()> {
-    // Deserialize wasm module from a file.
-    // print!("crate_metadata.original_wasm: {:?}", crate_metadata.original_wasm);
-    // print!("crate_metadata.dest_wasm: {:?}", crate_metadata.dest_wasm);
-    // let mut module =
-    //     parity_wasm::deserialize_file(&crate_metadata.original_wasm).context(format!(
-    //         "Loading original wasm file '{}'",
-    //         crate_metadata.original_wasm.display()
-    //     ))?;
-
-    // // Perform optimization.
-    // //
-    // // In practice only tree-shaking is performed, i.e transitively removing all symbols that are
-    // // NOT used by the specified entry points.
-    // if pwasm_utils::optimize(
-    //     &mut module,
-    //     ["main", "deploy", "memory", "hash_type"].to_vec(),
-    // )
-    // .is_err()
-    // {
-    //     anyhow::bail!("Optimizer failed");
-    // }
-    // strip_custom_sections(&mut module);
-
-    // parity_wasm::serialize_to_file(&crate_metadata.dest_wasm, module)?;
-
-    fs::copy(&crate_metadata.original_wasm, &crate_metadata.dest_wasm)?;
-    // check `wasm-opt` installed
-    if which::which("wasm-opt").is_err() {
-        eprintln!(
-            "{}",
-            "wasm-opt is not installed. Install this tool on your system in order to \n\
-             reduce the size of your Wasm binary. \n\
-             See https://github.com/WebAssembly/binaryen#tools"
-                .bright_yellow()
-        );
-        return Ok(());
+/// Mutable globals which are exported are left untouched, since the host may write them.
+fn propagate_constants(module: &mut Module) -> usize {
+    let imported_globals = module.import_count(ImportCountType::Global) as u32;
+    let exported_globals = module
+        .export_section()
+        .map(|section| {
+            section
+                .entries()
+                .iter()
+                .filter_map(|entry| match entry.internal() {
+                    Internal::Global(idx) => Some(*idx),
+                    _ => None,
+                })
+                .collect::<BTreeSet<_>>()
+        })
+        .unwrap_or_default();
+    let written_globals = module
+        .code_section()
+        .map(|section| {
+            section
+                .bodies()
+                .iter()
+                .flat_map(|body| body.code().elements())
+                .filter_map(|instruction| match instruction {
+                    Instruction::SetGlobal(idx) => Some(*idx),
+                    _ => None,
+                })
+                .collect::<BTreeSet<_>>()
+        })
+        .unwrap_or_default();
+
+    let is_constant = |instruction: &Instruction| {
+        matches!(
+            instruction,
+            Instruction::I32Const(_)
+                | Instruction::I64Const(_)
+                | Instruction::F32Const(_)
+                | Instruction::F64Const(_)
+        )
+    };
+    let constants = module
+        .global_section()
+        .map(|section| {
+            section
+                .entries()
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, global)| {
+                    let idx = imported_globals + idx as u32;
+                    let mutable = global.global_type().is_mutable();
+                    if written_globals.contains(&idx) || mutable && exported_globals.contains(&idx)
+                    {
+                        return None;
+                    }
+                    match global.init_expr().code() {
+                        [constant, Instruction::End] if is_constant(constant) => {
+                            Some((idx, constant.clone()))
+                        }
+                        _ => None,
+                    }
+                })
+                .collect::<BTreeMap<_, _>>()
+        })
+        .unwrap_or_default();
+    if constants.is_empty() {
+        return 0;
     }
 
-    let mut optimized = crate_metadata.dest_wasm.clone();
-    optimized.set_file_name(format!("{}-opt.wasm", crate_metadata.package_name));
+    let mut replaced = 0;
+    if let Some(section) = module.code_section_mut() {
+        for body in section.bodies_mut() {
+            for instruction in body.code_mut().elements_mut() {
+                if let Instruction::GetGlobal(idx) = instruction {
+                    if let Some(constant) = constants.get(idx) {
+                        *instruction = constant.clone();
+                        replaced += 1;
+                    }
+                }
+            }
+        }
+    }
+    replaced
+}
 
-    let output = Command::new("wasm-opt")
-        .arg(crate_metadata.dest_wasm.as_os_str())
-        .arg("--signext-lowering")
-        .arg("-g")
-        .arg("-O3") // execute -O3 optimization passes (spends potentially a lot of time optimizing)
-        .arg("-o")
-        .arg(optimized.as_os_str())
-        .output()?;
+/// The header of a data segment placed at a constant offset takes at most this many bytes, i.e.
+/// the memory index, the offset expression and the length.
+const DATA_SEGMENT_HEADER_LIMIT: usize = 13;
+/// The id of the subsection of the name section which names data segments.
+const DATA_NAMES_SUBSECTION: u8 = 9;
 
-    if !output.status.success() {
-        // Dump the output streams produced by wasm-opt into the stdout/stderr.
-        io::stdout().write_all(&output.stdout)?;
-        io::stderr().write_all(&output.stderr)?;
-        anyhow::bail!("wasm-opt optimization failed");
-    }
+/// Offsets of data segments in the memory, `None` if the layout of the memory is unknown, i.e.
+/// any segment isn't placed at a constant offset of the first memory.
+fn data_segment_offsets(segments: &[DataSegment]) -> Option<Vec<u32>> {
+    segments
+        .iter()
+        .map(
+            |segment| match segment.offset().as_ref().map(InitExpr::code) {
+                Some([Instruction::I32Const(offset), Instruction::End]) if segment.index() == 0 => {
+                    Some(*offset as u32)
+                }
+                _ => None,
+            },
+        )
+        .collect()
+}
 
-    // overwrite existing destination wasm file with the optimized version
-    fs::rename(&optimized, &crate_metadata.dest_wasm)?;
-    Ok(())
+/// Finds the data segment initializing `len` bytes at `address`, returns its index and the
+/// position of the bytes in it. Later segments override earlier ones, as they do when the
+/// memory is initialized.
+fn find_in_data(
+    segments: &[DataSegment],
+    offsets: &[u32],
+    address: u32,
+    len: u32,
+) -> Option<(usize, usize)> {
+    let end = address.checked_add(len)?;
+    (0..segments.len()).rev().find_map(|idx| {
+        let offset = offsets[idx];
+        let segment_end = offset as u64 + segments[idx].value().len() as u64;
+        if offset <= address && end as u64 <= segment_end {
+            Some((idx, (address - offset) as usize))
+        } else {
+            None
+        }
+    })
 }
 
-fn parse_ty(ty_info: &Map<String, Value>) -> String {
-    const TUPLE_TY: &str = "tuple";
+fn read_u32(bytes: &[u8], pos: usize) -> u32 {
+    let mut word = [0; 4];
+    word.copy_from_slice(&bytes[pos..pos + 4]);
+    u32::from_le_bytes(word)
+}
 
-    let ty = ty_info.get("type").unwrap().as_str().unwrap();
-    if ty.starts_with(TUPLE_TY) {
-        let components = ty_info.get("components").unwrap().as_array().unwrap();
-        let component_types = components
-            .iter()
-            .map(|component| parse_ty(component.as_object().unwrap()))
-            .join(",");
-        format!("({}){}", component_types, &ty[TUPLE_TY.len()..])
-    } else {
-        String::from(ty)
+/// Whether `bytes` look like the path of a source file embedded in a panic location, e.g.
+/// `src/lib.rs` or `/rustc/.../library/core/src/option.rs`.
+fn is_source_path(bytes: &[u8]) -> bool {
+    let contains = |needle: &[u8]| bytes.windows(needle.len()).any(|window| window == needle);
+    match std::str::from_utf8(bytes) {
+        Ok(path) => {
+            !path.chars().any(char::is_control)
+                && path.ends_with(".rs")
+                && (contains(b"src/") || contains(b"src\\"))
+        }
+        Err(_) => false,
     }
 }
 
-fn calc_selector(source: &[u8], use_gm: bool) -> u32 {
-    let hash_result = if !use_gm {
-        let mut hash_result = [0u8; 32];
-        let mut keccak_hasher = tiny_keccak::Keccak::v256();
-        keccak_hasher.update(source);
-        keccak_hasher.finalize(&mut hash_result);
-        hash_result
-    } else {
-        let mut sm3_hash = libsm::sm3::hash::Sm3Hash::new(source);
-        sm3_hash.get_hash()
-    };
+/// A `core::panic::Location` found in the data section.
+struct PanicLocation {
+    /// The data segment of the location and its position in the segment.
+    segment: usize,
+    pos: usize,
+    /// The address and the length of the file path.
+    path: (u32, u32),
+}
 
-    u32::from_le_bytes([
-        hash_result[0],
-        hash_result[1],
-        hash_result[2],
-        hash_result[3],
-    ])
+/// Panic locations stripped by `strip_panic_messages`.
+#[derive(Default)]
+struct StrippedPanicMessages {
+    /// The number of panic locations whose file paths were stripped.
+    locations: usize,
+    /// Bytes of file paths zeroed.
+    zeroed: usize,
+    /// Bytes removed from the data section by splitting segments around zeroed file paths.
+    removed: usize,
 }
 
-fn generate_abi(
-    crate_meta: &CrateMetadata,
-    verbosity_behavior: VerbosityBehavior,
-    use_gm: bool,
-) -> Result<()> {
-    utils::check_channel()?;
+/// Strips source file paths of panic locations from the data section.
+///
+/// A `core::panic::Location` is laid out as the address and the length of its file path followed
+/// by the line and the column, so the exact ranges of file paths are known from locations found
+/// in the data section. Paths referenced by anything else than locations, i.e. constants in code
+/// or other words in the data section, are left untouched. The length of the path of every
+/// location is set to 0 and the bytes of the path are zeroed. Segments are split around runs of
+/// zero bytes covering the paths if that is shorter, as the memory is zero-initialized, and the
+/// data count section and names of data segments in the name section are updated accordingly.
+fn strip_panic_messages(module: &mut Module) -> StrippedPanicMessages {
+    let mut stripped = StrippedPanicMessages::default();
+    let segments = match module.data_section() {
+        Some(data_section) => data_section.entries(),
+        None => return stripped,
+    };
+    let offsets = match data_segment_offsets(segments) {
+        Some(offsets) => offsets,
+        None => return stripped,
+    };
+    let original_size = data_section_size(module);
 
-    let build = |manifest_path: &ManifestPath| -> Result<()> {
-        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
-        let mut cmd = Command::new(cargo);
+    let mut locations = Vec::new();
+    for (segment, value) in segments.iter().map(DataSegment::value).enumerate() {
+        let first = (4 - offsets[segment] % 4) as usize % 4;
+        for pos in (first..value.len().saturating_sub(15)).step_by(4) {
+            let (address, len) = (read_u32(value, pos), read_u32(value, pos + 4));
+            let (line, column) = (read_u32(value, pos + 8), read_u32(value, pos + 12));
+            if len == 0 || line == 0 || column == 0 {
+                continue;
+            }
+            let is_path = find_in_data(segments, &offsets, address, len)
+                .map(|(idx, start)| &segments[idx].value()[start..start + len as usize])
+                .is_some_and(is_source_path);
+            if is_path {
+                locations.push(PanicLocation {
+                    segment,
+                    pos,
+                    path: (address, len),
+                });
+            }
+        }
+    }
 
-        let work_dir = crate_meta
-            .dest_abi
-            .parent()
-            .expect("the ABI file is a file path so has a parent");
-        cmd.current_dir(work_dir);
+    // Any other word in the data section or constant in code pointing into a path may be a
+    // reference to it, so such paths are kept, and so are paths overlapping them.
+    let location_fields = locations
+        .iter()
+        .map(|location| (location.segment, location.pos))
+        .collect::<BTreeSet<_>>();
+    let mut references = Vec::new();
+    for (segment, value) in segments.iter().map(DataSegment::value).enumerate() {
+        let first = (4 - offsets[segment] % 4) as usize % 4;
+        for pos in (first..value.len().saturating_sub(3)).step_by(4) {
+            if !location_fields.contains(&(segment, pos)) {
+                references.push(read_u32(value, pos));
+            }
+        }
+    }
+    let constants = module
+        .code_section()
+        .into_iter()
+        .flat_map(|section| section.bodies())
+        .flat_map(|body| body.code().elements())
+        .chain(
+            module
+                .global_section()
+                .into_iter()
+                .flat_map(|section| section.entries())
+                .flat_map(|global| global.init_expr().code()),
+        );
+    for instruction in constants {
+        if let Instruction::I32Const(value) = instruction {
+            references.push(*value as u32);
+        }
+    }
+    let overlaps = |a: (u32, u32), b: (u32, u32)| {
+        (a.0 as u64) < b.0 as u64 + b.1 as u64 && (b.0 as u64) < a.0 as u64 + a.1 as u64
+    };
+    let paths = locations
+        .iter()
+        .map(|location| location.path)
+        .collect::<BTreeSet<_>>();
+    let referenced = paths
+        .iter()
+        .copied()
+        .filter(|path| {
+            references
+                .iter()
+                .any(|address| overlaps(*path, (*address, 1)))
+        })
+        .collect::<Vec<_>>();
+    let paths = paths
+        .into_iter()
+        .filter(|path| !referenced.iter().any(|kept| overlaps(*path, *kept)))
+        .collect::<BTreeSet<_>>();
 
-        [
-            "run",
-            "--package",
-            "abi-gen",
-            format!(
-                "--manifest-path={}",
-                manifest_path.as_ref().to_string_lossy()
-            )
-            .as_str(),
-            format!("--target-dir={}", crate_meta.target_dir().to_string_lossy()).as_str(),
-            match verbosity_behavior {
-                VerbosityBehavior::Quiet => "--quiet",
-                VerbosityBehavior::Verbose => "--verbose",
-            },
-        ]
+    let segments = module
+        .data_section_mut()
+        .expect("the data section has been found above")
+        .entries_mut();
+    let mut zeroed = vec![Vec::new(); segments.len()];
+    for location in locations
         .iter()
-        .for_each(|arg| {
-            cmd.arg(arg);
-        });
+        .filter(|location| paths.contains(&location.path))
+    {
+        let pos = location.pos + 4;
+        segments[location.segment].value_mut()[pos..pos + 4].copy_from_slice(&[0; 4]);
+        stripped.locations += 1;
+    }
+    for (address, len) in paths.iter().copied() {
+        let (segment, start) = find_in_data(segments, &offsets, address, len)
+            .expect("paths have been found in the data section");
+        let end = start + len as usize;
+        let bytes = &mut segments[segment].value_mut()[start..end];
+        stripped.zeroed += bytes.iter().filter(|byte| **byte != 0).count();
+        bytes.iter_mut().for_each(|byte| *byte = 0);
+        zeroed[segment].push((start, end));
+    }
 
-        let status = cmd
-            .status()
-            .context(format!("Error executing `{:?}`", cmd))?;
-        // println!("execute {:?}, status {:?}", cmd, status);
-        if status.success() {
-            if !crate_meta.is_collaboration {
-                let dest_abi = &crate_meta.dest_abi;
-                let abi_content = fs::read_to_string(dest_abi).expect(&format!(
-                    "Failed to read the ABI file '{}'",
-                    dest_abi.display()
-                ));
-                let abi: Map<String, Value> = serde_json::from_str(&abi_content)?;
-
-                let mut sel_replacements: HashMap<String, HashMap<String, _, _>> = HashMap::new();
-                for (scope, fns) in &abi {
-                    let is_iface = scope != LOCAL_SCOPE;
-                    let fns = fns.as_array().unwrap();
-                    for f in fns {
-                        let fn_info = f.as_object().unwrap();
-                        let ty = fn_info.get("type").unwrap().as_str().unwrap();
-                        if ty == "function" {
-                            let (fn_name, new_sel) = get_name_and_selector(fn_info, use_gm);
-                            let new_sel = format!("i32.const {}", new_sel);
-                            let old_sel = if is_iface {
-                                format!(
-                                    "i32.const {}",
-                                    calc_selector((scope.to_owned() + &fn_name).as_bytes(), use_gm)
-                                        as i32
-                                )
-                            } else {
-                                format!(
-                                    "i32.const {}",
-                                    calc_selector(fn_name.as_bytes(), use_gm) as i32
-                                )
-                            };
-
-                            let entry = sel_replacements
-                                .entry(scope.to_owned())
-                                .or_insert(HashMap::new());
-                            assert!(!entry.contains_key(&fn_name));
-                            entry.insert(fn_name, (old_sel, new_sel, is_iface));
-                        }
-                    }
-                }
+    // Leaving bytes uninitialized keeps them zero unless another segment initializes them.
+    let ranges = offsets
+        .iter()
+        .zip(segments.iter())
+        .map(|(offset, segment)| (*offset, segment.value().len() as u32))
+        .collect::<Vec<_>>();
+    let mut new_segments = Vec::with_capacity(segments.len());
+    let mut new_indices = Vec::with_capacity(segments.len());
+    for (idx, segment) in segments.drain(..).enumerate() {
+        let value = segment.value();
+        let mut gaps = Vec::new();
+        for (start, end) in zeroed[idx].iter().copied() {
+            let start = start - value[..start].iter().rev().take_while(|b| **b == 0).count();
+            let end = end + value[end..].iter().take_while(|b| **b == 0).count();
+            let at_edge = start == 0 || end == value.len();
+            let shared = ranges.iter().enumerate().any(|(other, range)| {
+                other != idx
+                    && overlaps(*range, (offsets[idx] + start as u32, (end - start) as u32))
+            });
+            if (at_edge || end - start > DATA_SEGMENT_HEADER_LIMIT) && !shared {
+                gaps.push((start, end));
+            }
+        }
+        if gaps.is_empty() {
+            new_indices.push(Some(new_segments.len() as u32));
+            new_segments.push(segment);
+            continue;
+        }
+        // Paths in the same run of zero bytes result in the same gap.
+        gaps.sort_unstable();
+        gaps.dedup();
 
-                let dest_wasm = &crate_meta.dest_wasm;
-                let mut wasm_content = wabt::wasm2wat(fs::read(dest_wasm).unwrap()).unwrap();
-
-                for (scope, replacements) in sel_replacements {
-                    for (fn_name, (old_sel, new_sel, is_iface)) in replacements {
-                        let match_indices =
-                            wasm_content.match_indices(&old_sel).collect::<Vec<_>>();
-
-                        // It's legal that length of match_indices <= 1. For example, if an interface contains
-                        // a method but the method is never used, then this method will be optimized out
-                        // in optimization phase, which causes `match_indices` is empty.
-                        //
-                        // ## Caution
-                        // For now, we can't handle the situation that the method is optimized out but in
-                        // rest of bytecode there is another occurrence with same sequence of bytes.
-                        if match_indices.len() > 1 {
-                            let err_msg = format!(
-                                "method `{}` in {} cannot be invoked correctly, please rename this method",
-                                fn_name,
-                                if scope == LOCAL_SCOPE {
-                                    "contract"
-                                } else {
-                                    &scope
-                                }
-                            );
-                            anyhow::bail!(err_msg);
-                        }
+        let mut pieces = Vec::new();
+        let mut piece_start = 0;
+        for (start, end) in gaps.into_iter().chain(Some((value.len(), value.len()))) {
+            if start > piece_start {
+                pieces.push((piece_start, start));
+            }
+            piece_start = end;
+        }
+        new_indices.push(if pieces.is_empty() {
+            None
+        } else {
+            Some(new_segments.len() as u32)
+        });
+        for (start, end) in pieces {
+            let offset = offsets[idx] + start as u32;
+            let offset =
+                InitExpr::new(vec![Instruction::I32Const(offset as i32), Instruction::End]);
+            new_segments.push(DataSegment::new(
+                segment.index(),
+                Some(offset),
+                value[start..end].to_vec(),
+            ));
+        }
+    }
+    *segments = new_segments;
+    reindex_data_segments(module, &new_indices);
+    stripped.removed = original_size - data_section_size(module);
+    stripped
+}
 
-                        if match_indices.len() == 1 {
-                            wasm_content = wasm_content.replace(&old_sel, &new_sel);
+/// The size of the data section in bytes, excluding its header.
+fn data_section_size(module: &Module) -> usize {
+    let mut bytes = Vec::new();
+    match module.data_section() {
+        Some(section) if section.clone().serialize(&mut bytes).is_ok() => bytes.len(),
+        _ => 0,
+    }
+}
 
-                            #[cfg(debug_assertions)]
-                            {
-                                eprintln!(
-                                    "rewrite selector for {}::{}: {} -> {}",
-                                    scope, fn_name, old_sel, new_sel,
-                                );
-                            }
-                        } else {
-                            if !is_iface {
-                                let err_msg = format!(
-                                    "unable to find selector for method `{}` in contract",
-                                    fn_name,
-                                );
-                                anyhow::bail!(err_msg);
-                            }
-                        }
-                    }
+/// Updates the data count section and names of data segments in the name section after data
+/// segments have been removed or split. `new_indices` maps every original segment to the index
+/// of the first segment replacing it, `None` if it was removed.
+fn reindex_data_segments(module: &mut Module, new_indices: &[Option<u32>]) {
+    let count = module
+        .data_section()
+        .map_or(0, |section| section.entries().len() as u32);
+    for section in module.sections_mut() {
+        match section {
+            Section::DataCount(data_count) => *data_count = count,
+            Section::Custom(custom) if custom.name() == "name" => {
+                // A malformed name section is left as is, it's only used by debuggers.
+                if let Some(payload) = reindex_data_names(custom.payload(), new_indices) {
+                    *custom.payload_mut() = payload;
                 }
-
-                let mut wasm_file = fs::File::create(dest_wasm)?;
-                wasm_file.write_all(&wabt::wat2wasm(wasm_content).unwrap())?;
-
-                let local_abi = abi.get("$local").unwrap();
-                let mut abi_file = fs::File::create(dest_abi)?;
-                abi_file.write_all(serde_json::to_string(local_abi)?.as_bytes())?;
             }
-            Ok(())
-        } else {
-            anyhow::bail!("`{:?}` failed with exit code: {:?}", cmd, status.code())
+            _ => (),
         }
-    };
+    }
+}
 
-    Workspace::new(&crate_meta.cargo_meta, &crate_meta.root_package.id)?
-        .with_root_package_manifest(|manifest| {
-            manifest
-                .with_added_crate_type("rlib")?
-                .with_profile_release_lto(false)?;
-            Ok(())
-        })?
-        .using_temp(build)?;
+/// Rewrites the subsection naming data segments of a name section for `reindex_data_segments`,
+/// names of removed segments are dropped.
+fn reindex_data_names(payload: &[u8], new_indices: &[Option<u32>]) -> Option<Vec<u8>> {
+    let mut reader = io::Cursor::new(payload);
+    let mut result = Vec::with_capacity(payload.len());
+    while (reader.position() as usize) < payload.len() {
+        let id = u8::from(VarUint7::deserialize(&mut reader).ok()?);
+        let size = u32::from(VarUint32::deserialize(&mut reader).ok()?) as usize;
+        let start = reader.position() as usize;
+        let mut content = payload.get(start..start.checked_add(size)?)?.to_vec();
+        reader.set_position((start + size) as u64);
 
-    Ok(())
+        if id == DATA_NAMES_SUBSECTION {
+            let mut names = io::Cursor::new(&content);
+            let count = u32::from(VarUint32::deserialize(&mut names).ok()?);
+            let mut entries = Vec::new();
+            for _ in 0..count {
+                let idx = u32::from(VarUint32::deserialize(&mut names).ok()?);
+                let name = String::deserialize(&mut names).ok()?;
+                if let Some(Some(new_idx)) = new_indices.get(idx as usize) {
+                    entries.push((*new_idx, name));
+                }
+            }
+            content.clear();
+            VarUint32::from(entries.len())
+                .serialize(&mut content)
+                .ok()?;
+            for (idx, name) in entries {
+                VarUint32::from(idx).serialize(&mut content).ok()?;
+                name.serialize(&mut content).ok()?;
+            }
+        }
+        VarUint7::from(id).serialize(&mut result).ok()?;
+        VarUint32::from(content.len()).serialize(&mut result).ok()?;
+        result.extend(content);
+    }
+    Some(result)
 }
 
-fn get_name_and_selector(fn_info: &Map<String, Value>, use_gm: bool) -> (String, u32) {
-    let fn_name = fn_info.get("name").unwrap().as_str().unwrap().to_string();
-    let inputs = fn_info.get("inputs").unwrap().as_array().unwrap();
-    let sig = inputs
+/// Removes redundant bytes from the data section without changing the initial content of the
+/// linear memory, returns the number of bytes removed.
+///
+/// Addresses of constants are baked into the code, so segments are never moved. Identical
+/// constants at different addresses aren't merged either, since an address can't be told apart
+/// from any other integer in the code to be rewritten. Instead, segments rewriting the same bytes
+/// at the same offset are removed, and zero bytes at both ends of a segment are trimmed as the
+/// memory is zero-initialized, unless a previous segment is initializing the same bytes.
+fn trim_data_segments(module: &mut Module) -> usize {
+    let segments = match module.data_section_mut() {
+        Some(data_section) => data_section.entries_mut(),
+        None => return 0,
+    };
+    let offsets = match data_segment_offsets(segments) {
+        Some(offsets) => offsets,
+        None => return 0,
+    };
+
+    let overlaps = |a: (u32, u32, usize), b: (u32, u32, usize)| {
+        a.0 == b.0 && (a.1 as usize) < b.1 as usize + b.2 && (b.1 as usize) < a.1 as usize + a.2
+    };
+    let mut ranges = offsets
         .iter()
-        .map(|input| parse_ty(input.as_object().unwrap()))
-        .join(",");
-    let sig = format!("{}({})", fn_name, sig);
-    let new_sel = calc_selector(sig.as_bytes(), use_gm);
-    (fn_name, new_sel)
-}
+        .zip(segments.iter())
+        .map(|(offset, segment)| (segment.index(), *offset, segment.value().len()))
+        .collect::<Vec<_>>();
+    let original_size = ranges.iter().map(|(.., len)| len).sum::<usize>();
 
-static LOOKING_GLASS: Emoji<'_, '_> = Emoji("🔍 ", "d(・ω・d)");
-static TRUCK: Emoji<'_, '_> = Emoji("🚚 ", "(∫・ω・)∫");
-static CLIP: Emoji<'_, '_> = Emoji("🔗 ", "∇(・ω・∇)");
-static PAPER: Emoji<'_, '_> = Emoji("📃 ", "∂(・ω・∂)");
-static SPARKLE: Emoji<'_, '_> = Emoji("✨ ", "(˘•ω•˘)ง ");
+    let mut removed = vec![false; segments.len()];
+    for later in 0..segments.len() {
+        // A later segment rewriting exactly the same bytes is redundant if nothing between them
+        // modified these bytes.
+        let duplicated = (0..later)
+            .rev()
+            .find(|&earlier| !removed[earlier] && overlaps(ranges[earlier], ranges[later]));
+        if let Some(earlier) = duplicated {
+            if ranges[earlier] == ranges[later]
+                && segments[earlier].value() == segments[later].value()
+            {
+                removed[later] = true;
+            }
+        }
+    }
 
-pub(crate) fn execute_build(
-    manifest_path: ManifestPath,
-    use_gm: bool,
-    verbosity_behavior: VerbosityBehavior,
-    analysis_behavior: AnalysisBehavior,
-    cfg_path: &Option<PathBuf>,
-) -> Result<String> {
-    let started = Instant::now();
+    for current in 0..segments.len() {
+        if removed[current] {
+            continue;
+        }
+        let overlapped = (0..current)
+            .any(|earlier| !removed[earlier] && overlaps(ranges[earlier], ranges[current]));
+        if overlapped {
+            continue;
+        }
 
-    println!("[1/4] {} Collecting crate metadata", LOOKING_GLASS);
-    let crate_metadata = collect_crate_metadata(&manifest_path, use_gm)?;
+        let value = segments[current].value_mut();
+        let trailing = value.iter().rev().take_while(|byte| **byte == 0).count();
+        value.truncate(value.len() - trailing);
+        let leading = value.iter().take_while(|byte| **byte == 0).count();
+        value.drain(..leading);
+        if value.is_empty() {
+            removed[current] = true;
+        }
+        ranges[current].1 += leading as u32;
+        ranges[current].2 = value.len();
+    }
 
-    println!("[2/4] {} Building cargo project", TRUCK);
-    let build_result = build_cargo_project(
-        &crate_metadata,
-        use_gm,
-        verbosity_behavior,
-        analysis_behavior,
-        cfg_path,
-    )?;
+    let mut kept = 0;
+    let new_indices = removed
+        .iter()
+        .map(|removed| {
+            kept += !removed as u32;
+            if *removed {
+                None
+            } else {
+                Some(kept - 1)
+            }
+        })
+        .collect::<Vec<_>>();
+    *segments = segments
+        .drain(..)
+        .zip(ranges)
+        .zip(removed)
+        .filter(|(_, removed)| !removed)
+        .map(|((segment, (index, offset, _)), _)| {
+            let offset =
+                InitExpr::new(vec![Instruction::I32Const(offset as i32), Instruction::End]);
+            DataSegment::new(index, Some(offset), segment.value().to_vec())
+        })
+        .collect();
+    let remaining_size = segments
+        .iter()
+        .map(|segment| segment.value().len())
+        .sum::<usize>();
+    reindex_data_segments(module, &new_indices);
 
-    println!("[3/4] {} Optimizing Wasm bytecode", CLIP);
-    optimize_wasm(&crate_metadata)?;
+    original_size - remaining_size
+}
 
-    println!("[4/4] {} Generating ABI file", PAPER);
-    generate_abi(&crate_metadata, verbosity_behavior, use_gm)?;
+/// Checks that the module doesn't have a start function, which is rejected by the chain when
+/// deploying the contract.
+fn check_start_function(module: &Module, build_options: &BuildOptions) -> Result<()> {
+    if let Some(start) = module.start_section() {
+        let message = format!(
+            "the Wasm module has a start function (func[{}]), which will be rejected when \
+             deploying the contract. Remove the `#[start]` function and any code running \
+             before entry points are invoked",
+            start
+        );
+        if !build_options.allow_start_function {
+            anyhow::bail!(message);
+        }
+        eprintln!("{}", message.bright_yellow());
+    }
+    Ok(())
+}
 
-    if analysis_behavior != AnalysisBehavior::Skip && !crate_metadata.is_collaboration {
-        if let Ok(cfa_result) = serde_json::from_str::<'_, Value>(&build_result) {
-            let cfa_result = cfa_result.as_object().unwrap();
-            let abi_content = fs::read_to_string(&crate_metadata.dest_abi).unwrap();
-            let mut origin_abi: Value = serde_json::from_str(&abi_content).unwrap();
-            origin_abi
-                .as_array_mut()
-                .unwrap()
-                .iter_mut()
-                .for_each(|method| {
-                    let method = method.as_object_mut().unwrap();
-                    if method.contains_key("name")
-                        && method.contains_key("type")
-                        && method["type"] == Value::String("function".into())
-                    {
-                        // calculate selector of method
-                        let (method_name, selector) = get_name_and_selector(method, use_gm);
-                        if cfa_result.contains_key(&method_name) {
-                            method
-                                .insert("conflictFields".into(), cfa_result[&method_name].clone());
-                        }
-                        if use_gm {
-                            let (_, normal_selector) = get_name_and_selector(method, false);
-                            method.insert(
-                                "selector".into(),
-                                serde_json::to_value(vec![normal_selector, selector]).unwrap(),
-                            );
-                        } else {
-                            let (_, gm_selector) = get_name_and_selector(method, true);
-                            method.insert(
-                                "selector".into(),
-                                serde_json::to_value(vec![selector, gm_selector]).unwrap(),
-                            );
-                        }
+/// The module from which the gas counter injected by `--inject-gas` is imported, which follows
+/// the convention of `pwasm_utils`.
+const INJECTED_GAS_MODULE: &str = "env";
+/// The stack size of the contract passed to the linker.
+const STACK_SIZE: u64 = 65536;
+/// How many bytes of every data segment are dumped by `report_data_segments`.
+const DATA_SEGMENT_DUMP_LIMIT: usize = 64;
+
+/// Prints the offset, size and a hex dump of every data segment, so that large constants
+/// embedded in the bytecode can be found easily.
+fn report_data_segments(module: &Module, build_options: &BuildOptions) {
+    let segments = module
+        .data_section()
+        .map_or(&[][..], |data_section| data_section.entries());
+    for (idx, segment) in segments.iter().enumerate() {
+        let offset = match segment.offset().as_ref().map(InitExpr::code) {
+            Some([Instruction::I32Const(offset), Instruction::End]) => offset.to_string(),
+            Some(code) => code.iter().join(" "),
+            None => "passive".to_owned(),
+        };
+        let value = segment.value();
+        print_message(
+            build_options,
+            format_args!(
+                "      data[{}]: offset {}, {} bytes",
+                idx,
+                offset,
+                value.len()
+            ),
+        );
+        for (line, chunk) in value[..value.len().min(DATA_SEGMENT_DUMP_LIMIT)]
+            .chunks(16)
+            .enumerate()
+        {
+            let hex = chunk.iter().map(|byte| format!("{:02x}", byte)).join(" ");
+            let ascii = chunk
+                .iter()
+                .map(|byte| {
+                    if byte.is_ascii_graphic() || *byte == b' ' {
+                        *byte as char
+                    } else {
+                        '.'
                     }
-                });
-            let new_abi = serde_json::to_string(&origin_abi).unwrap();
-            fs::write(&crate_metadata.dest_abi, new_abi).unwrap();
-        } else {
-            eprintln!(
-                "{}\n{}",
-                "unable to parse the result of conflict fields analysis:".yellow(),
-                build_result
+                })
+                .collect::<String>();
+            print_message(
+                build_options,
+                format_args!("        {:04x}  {:<47}  |{}|", line * 16, hex, ascii),
+            );
+        }
+        if value.len() > DATA_SEGMENT_DUMP_LIMIT {
+            print_message(
+                build_options,
+                format_args!(
+                    "        ... {} more bytes",
+                    value.len() - DATA_SEGMENT_DUMP_LIMIT
+                ),
             );
         }
     }
 
-    let mut dest_wasm = crate_metadata.dest_wasm.display().to_string();
-    let mut dest_abi = crate_metadata.dest_abi.display().to_string();
-    if cfg!(target_os = "windows") {
-        dest_wasm = dest_wasm.replace("\\", "\\\\");
-        dest_abi = dest_abi.replace("\\", "\\\\");
+    let total_size = segments
+        .iter()
+        .map(|segment| segment.value().len())
+        .sum::<usize>();
+    print_message(
+        build_options,
+        format_args!(
+            "      {} data segments, {} bytes in total",
+            segments.len(),
+            total_size
+        ),
+    );
+}
+
+/// Options of [`process_module`].
+pub(crate) struct ProcessOptions {
+    /// Exports which are kept by tree-shaking, everything not reachable from them is removed.
+    pub used_exports: Vec<String>,
+    pub tree_shaking: bool,
+    /// Keeps custom sections, e.g. the name section, instead of stripping them.
+    pub keep_custom_sections: bool,
+    /// Strips the data count section, which is rejected by some older runtimes.
+    pub strip_data_count: bool,
+}
+
+/// Tree-shakes the module and strips sections not required by the chain, without any file I/O
+/// or output, so that it can be exercised with arbitrary modules.
+pub(crate) fn process_module(mut module: Module, opts: ProcessOptions) -> Result<Module> {
+    // In practice only tree-shaking is performed, i.e transitively removing all symbols that are
+    // NOT used by the specified entry points.
+    if opts.tree_shaking {
+        // `pwasm_utils` drops all custom sections, so the name section is parsed beforehand to
+        // be kept and updated for removed functions, and other ones are restored afterwards.
+        let mut custom_sections = Vec::new();
+        if opts.keep_custom_sections {
+            module = module.parse_names().unwrap_or_else(|(_, module)| module);
+            custom_sections = module.custom_sections().cloned().collect();
+        }
+        let used_exports = opts.used_exports.iter().map(String::as_str).collect();
+        if pwasm_utils::optimize(&mut module, used_exports).is_err() {
+            anyhow::bail!("Optimizer failed");
+        }
+        module
+            .sections_mut()
+            .extend(custom_sections.into_iter().map(Section::Custom));
+    }
+    // Custom sections are kept for `wasm-opt` to update them, they will be split or restored
+    // afterwards.
+    if !opts.keep_custom_sections {
+        strip_custom_sections(&mut module);
+    }
+    if opts.strip_data_count {
+        strip_data_count_section(&mut module);
+    }
+    Ok(module)
+}
+
+/// Performs required post-processing steps on the wasm artifact.
+fn post_process_wasm(
+    crate_metadata: &CrateMetadata,
+    verbosity_behavior: VerbosityBehavior,
+    build_options: &BuildOptions,
+) -> Result<()> {
+    if !crate_metadata.original_wasm.exists() {
+        anyhow::bail!(
+            "cargo finished successfully but the Wasm bytecode is not found at '{}', likely \
+             because:\n  \
+             - `[lib] crate-type` of the contract doesn't include `cdylib`\n  \
+             - `[lib] name` differs from the package name `{}`\n  \
+             - the package has no library target, e.g. only `src/main.rs` exists",
+            crate_metadata.original_wasm.display(),
+            crate_metadata.package_name
+        );
+    }
+
+    if build_options.skip_post_process {
+        fs::copy(&crate_metadata.original_wasm, &crate_metadata.dest_wasm)?;
+        return Ok(());
+    }
+
+    // Deserialize wasm module from a file.
+    //
+    // `parity_wasm` doesn't understand some newer instructions (e.g. sign-extension operators)
+    // which may be emitted by rustc, these modules are left to `wasm-opt` untouched.
+    let mut module = match parity_wasm::deserialize_file(&crate_metadata.original_wasm) {
+        Ok(module) => module,
+        Err(e) if build_options.inject_gas || build_options.stack_limit.is_some() => {
+            anyhow::bail!(
+                "unable to load original wasm file '{}' due to: {}, so gas metering or the \
+                 stack limit can't be injected",
+                crate_metadata.original_wasm.display(),
+                e
+            );
+        }
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "unable to load original wasm file '{}' due to: {}, tree-shaking is skipped",
+                    crate_metadata.original_wasm.display(),
+                    e
+                )
+                .bright_yellow()
+            );
+            fs::copy(&crate_metadata.original_wasm, &crate_metadata.dest_wasm)?;
+            return Ok(());
+        }
+    };
+
+    let kept_exports = crate_metadata
+        .entry_points
+        .iter()
+        .chain(&build_options.keep_exports)
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    // `pwasm_utils` silently ignores exports to keep which don't exist, e.g. misspelled ones.
+    for name in missing_exports(&module, &kept_exports) {
+        eprintln!(
+            "{}",
+            format!(
+                "export `{}` to be kept is not found in the Wasm module, it's ignored",
+                name
+            )
+            .bright_yellow()
+        );
+    }
+    // Tree-shaking drops unused exports, so they must be found beforehand.
+    if build_options.report_unused_exports {
+        report_unused_exports(&module, &kept_exports, build_options);
+    }
+    let counts = |module: &Module| {
+        (
+            module
+                .function_section()
+                .map_or(0, |section| section.entries().len()),
+            module
+                .export_section()
+                .map_or(0, |section| section.entries().len()),
+        )
+    };
+    let (functions, exports) = counts(&module);
+    let had_data_count = module
+        .sections()
+        .iter()
+        .any(|section| matches!(section, Section::DataCount(_)));
+    module = process_module(
+        module,
+        ProcessOptions {
+            used_exports: kept_exports
+                .iter()
+                .copied()
+                .chain(REQUIRED_EXPORTS.iter().copied())
+                .map(str::to_owned)
+                .collect(),
+            tree_shaking: !build_options.no_optimize_internal,
+            keep_custom_sections: build_options.keeps_custom_sections(),
+            strip_data_count: build_options.legacy_wasm,
+        },
+    )?;
+    if let VerbosityBehavior::Verbose = verbosity_behavior {
+        if !build_options.no_optimize_internal {
+            let (remaining_functions, remaining_exports) = counts(&module);
+            print_message(
+                build_options,
+                format_args!(
+                    "      tree-shaking removed {} of {} functions and {} of {} exports",
+                    functions - remaining_functions,
+                    functions,
+                    exports - remaining_exports,
+                    exports
+                ),
+            );
+        }
+    }
+    if build_options.strip_unreachable {
+        let entry_points = kept_exports
+            .iter()
+            .copied()
+            .chain(REQUIRED_EXPORTS.iter().copied())
+            .collect::<Vec<_>>();
+        let stripped = strip_unreachable_exports(&mut module, &entry_points);
+        print_message(
+            build_options,
+            format_args!(
+                "      {} unreachable exports and {} functions removed",
+                stripped.exports.len(),
+                stripped.functions
+            ),
+        );
+    }
+    if build_options.legacy_wasm && had_data_count {
+        print_message(build_options, "      data count section removed");
+    }
+    check_start_function(&module, build_options)?;
+    if build_options.const_propagation {
+        let replaced = propagate_constants(&mut module);
+        print_message(
+            build_options,
+            format_args!("{} reads of constant globals inlined", replaced),
+        );
+    }
+    if build_options.emit_stack_usage {
+        report_stack_usage(&module, build_options);
+    }
+    if build_options.strip_panic_messages {
+        let stripped = strip_panic_messages(&mut module);
+        print_message(
+            build_options,
+            format_args!(
+                "      file paths of {} panic locations stripped, {} bytes zeroed and {} bytes \
+                 removed from the data section",
+                stripped.locations, stripped.zeroed, stripped.removed
+            ),
+        );
+    }
+    if build_options.trim_data {
+        let removed_bytes = trim_data_segments(&mut module);
+        print_message(
+            build_options,
+            format_args!("{} bytes removed from the data section", removed_bytes),
+        );
+    }
+    if build_options.report_data_segments {
+        report_data_segments(&module, build_options);
+    }
+    if build_options.inject_gas {
+        module = pwasm_utils::inject_gas_counter(
+            module,
+            &pwasm_utils::rules::Set::default(),
+            INJECTED_GAS_MODULE,
+        )
+        .map_err(|_| anyhow::anyhow!("unable to inject gas counter into the Wasm module"))?;
+        print_message(
+            build_options,
+            format_args!(
+                "      gas metering injected, importing `{}.gas`",
+                INJECTED_GAS_MODULE
+            ),
+        );
+    }
+    if let Some(stack_limit) = build_options.stack_limit {
+        module = pwasm_utils::stack_height::inject_limiter(module, stack_limit)
+            .map_err(|e| anyhow::anyhow!("unable to limit the stack height: {:?}", e))?;
+        print_message(
+            build_options,
+            format_args!("      stack height limited to {}", stack_limit),
+        );
+    }
+
+    write_wasm(module, &crate_metadata.dest_wasm)?;
+    Ok(())
+}
+
+/// Returns the names in `names` which are not exported by the module.
+pub(crate) fn missing_exports<'a>(module: &Module, names: &[&'a str]) -> Vec<&'a str> {
+    let exports = module
+        .export_section()
+        .map_or(&[][..], |section| section.entries());
+    names
+        .iter()
+        .copied()
+        .filter(|name| !exports.iter().any(|export| export.field() == *name))
+        .collect()
+}
+
+/// Prints exports which are neither entry points nor required by the chain, these are candidates
+/// to be removed from the source, e.g. by `#[cfg(not(target_arch = "wasm32"))]`.
+fn report_unused_exports(module: &Module, entry_points: &[&str], build_options: &BuildOptions) {
+    let unused_exports = unused_exports(module, entry_points, &REQUIRED_EXPORTS);
+    if unused_exports.is_empty() {
+        print_message(build_options, "      No unused export found");
+        return;
+    }
+    print_message(
+        build_options,
+        format_args!(
+            "      {} export(s) not used by {}:",
+            unused_exports.len(),
+            entry_points
+                .iter()
+                .map(|name| format!("`{}`", name))
+                .join(", ")
+        ),
+    );
+    for export in unused_exports {
+        print_message(
+            build_options,
+            format_args!(
+                "        {}{}",
+                export.name,
+                if export.reachable {
+                    " (also called by the entry points)"
+                } else {
+                    ""
+                }
+            ),
+        );
+    }
+}
+
+/// Prints the deepest call chains and the estimated peak stack usage of the module, warns if the
+/// estimation is close to the stack size.
+fn report_stack_usage(module: &Module, build_options: &BuildOptions) {
+    // Names are only used in the report, so a malformed name section is ignored.
+    let module = module
+        .clone()
+        .parse_names()
+        .unwrap_or_else(|(_, module)| module);
+    let stack_usage = analyze_stack_usage(&module);
+
+    print_message(build_options, "      Deepest call chains:");
+    for chain in &stack_usage.deepest_chains {
+        print_message(
+            build_options,
+            format_args!(
+                "        depth {: >3}, ~{: >6} bytes: {}",
+                chain.functions.len(),
+                chain.stack_usage,
+                chain.functions.join(" -> ")
+            ),
+        );
+    }
+    if !stack_usage.recursive.is_empty() {
+        print_message(
+            build_options,
+            format_args!(
+                "      Recursion found in {}, the stack usage is unbounded",
+                stack_usage.recursive.join(", ")
+            ),
+        );
+    }
+    print_message(
+        build_options,
+        format_args!(
+            "      Estimated peak stack usage: {} of {} bytes",
+            stack_usage.peak, STACK_SIZE
+        ),
+    );
+    if stack_usage.peak > STACK_SIZE * 4 / 5 {
+        eprintln!(
+            "{}",
+            format!(
+                "the estimated peak stack usage exceeds 80% of the stack size ({} bytes)",
+                STACK_SIZE
+            )
+            .bright_yellow()
+        );
+    }
+}
+
+/// Collects custom sections of the Wasm bytecode, e.g. the name section and DWARF sections, so
+/// that they can be restored after `generate_abi`.
+fn custom_sections(crate_metadata: &CrateMetadata) -> Vec<CustomSection> {
+    match parity_wasm::deserialize_file(&crate_metadata.dest_wasm) {
+        Ok(module) => module.custom_sections().cloned().collect(),
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "unable to load wasm file '{}' due to: {}, names are not preserved",
+                    crate_metadata.dest_wasm.display(),
+                    e
+                )
+                .bright_yellow()
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Embeds the `liquid-build-info` custom section into the final Wasm bytecode, along with the
+/// `preserved` custom sections.
+///
+/// This must be the last step which modifies the bytecode, because `generate_abi` rewrites the
+/// whole module and drops all custom sections.
+fn embed_build_info(
+    crate_metadata: &CrateMetadata,
+    version: &str,
+    preserved: Vec<CustomSection>,
+) -> Result<()> {
+    let mut module = parity_wasm::deserialize_file(&crate_metadata.dest_wasm).map_err(|e| {
+        anyhow::anyhow!(
+            "unable to load wasm file '{}' due to: {}",
+            crate_metadata.dest_wasm.display(),
+            e
+        )
+    })?;
+    let build_info = serde_json::json!({ "version": version });
+    module
+        .sections_mut()
+        .extend(preserved.into_iter().map(Section::Custom));
+    module
+        .sections_mut()
+        .push(Section::Custom(CustomSection::new(
+            BUILD_INFO_SECTION.to_owned(),
+            serde_json::to_vec(&build_info)?,
+        )));
+    write_wasm(module, &crate_metadata.dest_wasm)?;
+    Ok(())
+}
+
+/// Saves the Wasm bytecode with all custom sections to `<name>.debug.wasm`, and strips the custom
+/// sections from the bytecode to be deployed. Returns the path of the debug file.
+///
+/// This is performed after `wasm-opt`, so that function indices in the name section match the
+/// deployed bytecode.
+fn split_debug_info(crate_metadata: &CrateMetadata) -> Result<Option<PathBuf>> {
+    let mut module = match parity_wasm::deserialize_file(&crate_metadata.dest_wasm) {
+        Ok(module) => module,
+        Err(e) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "unable to load wasm file '{}' due to: {}, debug info is not split",
+                    crate_metadata.dest_wasm.display(),
+                    e
+                )
+                .bright_yellow()
+            );
+            return Ok(None);
+        }
+    };
+
+    let dest_debug = crate_metadata.dest_debug();
+    fs::copy(&crate_metadata.dest_wasm, &dest_debug)?;
+    strip_custom_sections(&mut module);
+    write_wasm(module, &crate_metadata.dest_wasm)?;
+    Ok(Some(dest_debug))
+}
+
+/// Checks whether `path` is an executable file.
+fn check_executable(path: &Path) -> Result<()> {
+    let metadata =
+        fs::metadata(path).context(format!("Reading metadata of '{}'", path.display()))?;
+    if !metadata.is_file() {
+        anyhow::bail!("'{}' is not a file", path.display());
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if metadata.permissions().mode() & 0o111 == 0 {
+            anyhow::bail!("'{}' is not executable", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Appends the platform specific extension of executables to `path` if it has none and doesn't
+/// exist, e.g. `C:\binaryen\bin\wasm-opt` is resolved to `wasm-opt.exe` on Windows.
+fn resolve_executable(path: &Path) -> PathBuf {
+    if path.exists() || path.extension().is_some() || env::consts::EXE_EXTENSION.is_empty() {
+        return path.to_path_buf();
+    }
+    path.with_extension(env::consts::EXE_EXTENSION)
+}
+
+/// Moves `from` to `to`, overwriting `to` if it exists.
+///
+/// Renaming onto an existing file fails on Windows if the file is e.g. read-only or being
+/// scanned, so the destination is removed before retrying.
+fn replace_file(from: &Path, to: &Path) -> Result<()> {
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    if to.exists() {
+        fs::remove_file(to).context(format!("Removing '{}'", to.display()))?;
+    }
+    fs::rename(from, to).context(format!("Moving '{}' to '{}'", from.display(), to.display()))
+}
+
+/// Serializes the module to a temporary file next to `dest` first and moves it into place, so
+/// that `dest` is never left half-written if the process is interrupted.
+pub(crate) fn write_wasm(module: Module, dest: &Path) -> Result<()> {
+    let tmp = dest.with_extension("wasm.tmp");
+    parity_wasm::serialize_to_file(&tmp, module)
+        .context(format!("Writing Wasm file '{}'", tmp.display()))?;
+    replace_file(&tmp, dest)
+}
+
+/// Attempts to perform optional wasm optimization using `wasm-opt`.
+///
+/// The intention is to reduce the size of bloated wasm binaries as a result of missing
+/// optimizations (or bugs?) between Rust and Wasm.
+///
+/// This step depends on the `wasm-opt` tool being installed. If it is not the build will still
+/// succeed, and the user will be encouraged to install it for further optimizations.
+///
+/// Returns sizes of the Wasm bytecode before and after the optimization in bytes, or `None` if
+/// `wasm-opt` is not installed.
+fn optimize_wasm(
+    crate_metadata: &CrateMetadata,
+    build_options: &BuildOptions,
+) -> Result<Option<(u64, u64)>> {
+    // Options on the command line take precedence over `[package.metadata.liquid]`.
+    run_wasm_opt(
+        &crate_metadata.dest_wasm,
+        build_options
+            .optimization_level
+            .or(crate_metadata.optimization_level),
+        build_options
+            .wasm_opt_path
+            .as_deref()
+            .or(crate_metadata.wasm_opt_path.as_deref()),
+        build_options.reserves_stdout(),
+    )
+}
+
+/// Optimizes the Wasm bytecode at `wasm` in place with `wasm-opt`. The optimization level and
+/// the path of `wasm-opt` fall back to the global config if not specified.
+///
+/// Returns sizes of the Wasm bytecode before and after the optimization in bytes, or `None` if
+/// `wasm-opt` is not installed.
+pub(crate) fn run_wasm_opt(
+    wasm: &Path,
+    optimization_level: Option<OptimizationLevel>,
+    wasm_opt_path: Option<&Path>,
+    reserves_stdout: bool,
+) -> Result<Option<(u64, u64)>> {
+    let global_config = GlobalConfig::load()?;
+    let optimization_level = match optimization_level {
+        Some(optimization_level) => optimization_level,
+        None => match &global_config.optimization_level {
+            Some(level) => level.parse().context(format!(
+                "Parsing `optimization-level` in '{}'",
+                GlobalConfig::path().unwrap_or_default().display()
+            ))?,
+            None => Default::default(),
+        },
+    };
+    let wasm_opt_path = wasm_opt_path.or(global_config.wasm_opt_path.as_deref());
+
+    let wasm_opt = match wasm_opt_path {
+        Some(wasm_opt_path) => {
+            let wasm_opt_path = resolve_executable(wasm_opt_path);
+            check_executable(&wasm_opt_path).context("Checking the configured wasm-opt binary")?;
+            wasm_opt_path
+        }
+        // check `wasm-opt` installed
+        None => match which::which("wasm-opt") {
+            Ok(wasm_opt) => wasm_opt,
+            Err(_) => {
+                eprintln!(
+                    "{}",
+                    "wasm-opt is not installed. Install this tool on your system in order to \n\
+                     reduce the size of your Wasm binary. \n\
+                     See https://github.com/WebAssembly/binaryen#tools"
+                        .bright_yellow()
+                );
+                return Ok(None);
+            }
+        },
+    };
+
+    let original_size = fs::metadata(wasm)?.len();
+    let optimized = wasm.with_extension("opt.wasm");
+
+    let output = Command::new(&wasm_opt)
+        .arg(wasm.as_os_str())
+        .arg("--signext-lowering")
+        .arg("-g")
+        .arg(format!("-O{}", optimization_level))
+        .arg("-o")
+        .arg(optimized.as_os_str())
+        .output()
+        .context(format!("Executing '{}'", wasm_opt.display()))?;
+
+    if !output.status.success() {
+        // Dump the output streams produced by wasm-opt into the stdout/stderr.
+        if reserves_stdout {
+            io::stderr().write_all(&output.stdout)?;
+        } else {
+            io::stdout().write_all(&output.stdout)?;
+        }
+        io::stderr().write_all(&output.stderr)?;
+
+        // Without an exit code the process was terminated by a signal, which is usually the
+        // OOM killer when optimizing huge modules.
+        if output.status.code().is_none() {
+            #[cfg(unix)]
+            let signal = {
+                use std::os::unix::process::ExitStatusExt;
+                output.status.signal()
+            };
+            #[cfg(not(unix))]
+            let signal: Option<i32> = None;
+
+            anyhow::bail!(
+                "wasm-opt was killed{}, likely out of memory; try a lower optimization level by \
+                 `--optimization-level`",
+                signal.map_or_else(String::new, |signal| format!(" by signal {}", signal))
+            );
+        }
+        anyhow::bail!("wasm-opt optimization failed");
+    }
+
+    // overwrite existing destination wasm file with the optimized version
+    let optimized_size = fs::metadata(&optimized)?.len();
+    replace_file(&optimized, wasm)?;
+    Ok(Some((original_size, optimized_size)))
+}
+
+/// Formats the type of an ABI parameter as in the signature of a function, e.g.
+/// `(uint256,string)[]` for an array of tuples.
+pub(crate) fn parse_ty(ty_info: &Map<String, Value>) -> Result<String> {
+    const TUPLE_TY: &str = "tuple";
+
+    let ty = match ty_info.get("type").and_then(Value::as_str) {
+        Some(ty) => ty,
+        None => anyhow::bail!("`type` of a parameter is missing or not a string"),
+    };
+    if let Some(dims) = ty.strip_prefix(TUPLE_TY) {
+        let components = match ty_info.get("components").and_then(Value::as_array) {
+            Some(components) => components,
+            None => anyhow::bail!("`components` of a tuple parameter is missing or not an array"),
+        };
+        let component_types = components
+            .iter()
+            .map(|component| match component.as_object() {
+                Some(component) => parse_ty(component),
+                None => anyhow::bail!("a component of a tuple parameter is not an object"),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(format!("({}){}", component_types.join(","), dims))
+    } else {
+        Ok(String::from(ty))
+    }
+}
+
+fn calc_hash(source: &[u8], use_gm: bool) -> [u8; 32] {
+    if !use_gm {
+        let mut hash_result = [0u8; 32];
+        let mut keccak_hasher = tiny_keccak::Keccak::v256();
+        keccak_hasher.update(source);
+        keccak_hasher.finalize(&mut hash_result);
+        hash_result
+    } else {
+        let mut sm3_hash = libsm::sm3::hash::Sm3Hash::new(source);
+        sm3_hash.get_hash()
+    }
+}
+
+fn calc_hash_hex(source: &[u8], use_gm: bool) -> String {
+    calc_hash(source, use_gm)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .join("")
+}
+
+fn calc_selector(source: &[u8], use_gm: bool) -> u32 {
+    let hash_result = calc_hash(source, use_gm);
+
+    u32::from_le_bytes([
+        hash_result[0],
+        hash_result[1],
+        hash_result[2],
+        hash_result[3],
+    ])
+}
+
+/// Runs `abi-gen` of the contract, which writes the ABI of the contract and of all interfaces it
+/// uses to `<name>.abi`.
+fn run_abi_gen(
+    crate_meta: &CrateMetadata,
+    verbosity_behavior: VerbosityBehavior,
+    build_options: &BuildOptions,
+) -> Result<()> {
+    utils::check_channel()?;
+
+    let build = |manifest_path: &ManifestPath| -> Result<()> {
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let mut cmd = Command::new(cargo);
+
+        let work_dir = crate_meta
+            .dest_abi
+            .parent()
+            .expect("the ABI file is a file path so has a parent");
+        cmd.current_dir(work_dir);
+
+        [
+            "run",
+            "--package",
+            "abi-gen",
+            format!(
+                "--manifest-path={}",
+                manifest_path.as_ref().to_string_lossy()
+            )
+            .as_str(),
+            format!("--target-dir={}", crate_meta.target_dir().to_string_lossy()).as_str(),
+            match verbosity_behavior {
+                VerbosityBehavior::Quiet | VerbosityBehavior::Normal => "--quiet",
+                VerbosityBehavior::Verbose => "--verbose",
+            },
+        ]
+        .iter()
+        .for_each(|arg| {
+            cmd.arg(arg);
+        });
+        if build_options.lockfile.is_some() {
+            cmd.arg("--locked");
+        }
+        if build_options.reserves_stdout() {
+            cmd.stdout(Stdio::from(io::stderr()));
+        }
+
+        let status = cmd
+            .status()
+            .context(format!("Error executing `{:?}`", cmd))?;
+        if !status.success() {
+            anyhow::bail!("`{:?}` failed with exit code: {:?}", cmd, status.code());
+        }
+        Ok(())
+    };
+
+    let mut workspace = Workspace::new(&crate_meta.cargo_meta, &crate_meta.root_package.id)?;
+    workspace.with_keep_temp(build_options.keep_temp);
+    if let Some(lockfile) = &build_options.lockfile {
+        workspace.with_lockfile(lockfile)?;
+    }
+    for dependency in &build_options.workspace_dependency_overrides {
+        workspace.with_workspace_dependency_override(&dependency.name, &dependency.version)?;
+    }
+    let liquid_patch = match &build_options.liquid_path {
+        Some(liquid_path) => Some(liquid_patch(crate_meta, liquid_path)?),
+        None => None,
+    };
+    workspace
+        .with_root_package_manifest(|manifest| {
+            manifest
+                .with_added_crate_type("rlib")?
+                .with_profile_release_lto(false)?;
+            if let Some((source, package_name, path)) = &liquid_patch {
+                manifest.with_patch(source, package_name, path)?;
+            }
+            for manifest_override in &build_options.manifest_overrides {
+                manifest.with_override(manifest_override)?;
+            }
+            Ok(())
+        })?
+        .using_temp(build)
+}
+
+/// Generates the ABI file and rewrites selectors in the Wasm bytecode accordingly.
+///
+/// The output of `abi-gen` is cached in `<name>.abi-gen`, it's only run again if any input file
+/// has been modified or any other input has changed since the cached output was generated, which
+/// is recorded in `<name>.abi-stamp`. Selectors are rewritten on every build, since the Wasm
+/// bytecode is always rebuilt.
+fn generate_abi(
+    crate_meta: &CrateMetadata,
+    verbosity_behavior: VerbosityBehavior,
+    use_gm: bool,
+    build_options: &BuildOptions,
+) -> Result<()> {
+    let abi_gen_output = crate_meta.dest_abi.with_extension("abi-gen");
+    let input_files = build_cache::input_files(
+        &crate_meta.cargo_meta,
+        &crate_meta.root_package.id,
+        build_options.liquid_path.as_deref(),
+    )?;
+    let abi_stamp = format!(
+        "{}\n{}\n",
+        build_cache::newest_modified(&input_files)?
+            .and_then(|newest| newest.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |newest| newest.as_nanos()),
+        build_cache::abi_fingerprint(&crate_meta.cargo_meta, build_options, use_gm)
+    );
+    let cached = abi_gen_output.exists()
+        && fs::read_to_string(&crate_meta.abi_stamp).ok().as_deref() == Some(abi_stamp.as_str());
+    if !cached {
+        run_abi_gen(crate_meta, verbosity_behavior, build_options)?;
+        fs::copy(&crate_meta.dest_abi, &abi_gen_output).context(format!(
+            "Caching the output of abi-gen to '{}'",
+            abi_gen_output.display()
+        ))?;
+        fs::write(&crate_meta.abi_stamp, abi_stamp).context(format!(
+            "Writing ABI stamp '{}'",
+            crate_meta.abi_stamp.display()
+        ))?;
+    } else {
+        print_message(
+            build_options,
+            "      inputs unchanged, reusing the cached output of abi-gen",
+        );
+        fs::copy(&abi_gen_output, &crate_meta.dest_abi).context(format!(
+            "Copying the cached output of abi-gen from '{}'",
+            abi_gen_output.display()
+        ))?;
+    }
+
+    if !crate_meta.is_collaboration {
+        let dest_abi = &crate_meta.dest_abi;
+        let abi_content = fs::read_to_string(dest_abi).expect(&format!(
+            "Failed to read the ABI file '{}'",
+            dest_abi.display()
+        ));
+        let abi: Map<String, Value> = serde_json::from_str(&abi_content)?;
+
+        let mut sel_replacements: HashMap<String, HashMap<String, _, _>> = HashMap::new();
+        for (scope, fns) in &abi {
+            let is_iface = scope != LOCAL_SCOPE;
+            let fns = fns.as_array().unwrap();
+            for f in fns {
+                let fn_info = f.as_object().unwrap();
+                let ty = fn_info.get("type").unwrap().as_str().unwrap();
+                if ty == "function" {
+                    let (fn_name, new_sel) = get_name_and_selector(fn_info, use_gm)
+                        .context(format!("Reading ABI file '{}'", dest_abi.display()))?;
+                    let new_sel = format!("i32.const {}", new_sel);
+                    let old_sel = if is_iface {
+                        format!(
+                            "i32.const {}",
+                            calc_selector((scope.to_owned() + &fn_name).as_bytes(), use_gm) as i32
+                        )
+                    } else {
+                        format!(
+                            "i32.const {}",
+                            calc_selector(fn_name.as_bytes(), use_gm) as i32
+                        )
+                    };
+
+                    let entry = sel_replacements
+                        .entry(scope.to_owned())
+                        .or_insert(HashMap::new());
+                    assert!(!entry.contains_key(&fn_name));
+                    entry.insert(fn_name, (old_sel, new_sel, is_iface));
+                }
+            }
+        }
+
+        let dest_wasm = &crate_meta.dest_wasm;
+        let mut wasm_content = wabt::wasm2wat(fs::read(dest_wasm).unwrap()).unwrap();
+
+        for (scope, replacements) in sel_replacements {
+            for (fn_name, (old_sel, new_sel, is_iface)) in replacements {
+                let match_indices = wasm_content.match_indices(&old_sel).collect::<Vec<_>>();
+
+                // It's legal that length of match_indices <= 1. For example, if an interface contains
+                // a method but the method is never used, then this method will be optimized out
+                // in optimization phase, which causes `match_indices` is empty.
+                //
+                // ## Caution
+                // For now, we can't handle the situation that the method is optimized out but in
+                // rest of bytecode there is another occurrence with same sequence of bytes.
+                if match_indices.len() > 1 {
+                    let err_msg = format!(
+                        "method `{}` in {} cannot be invoked correctly, please rename this method",
+                        fn_name,
+                        if scope == LOCAL_SCOPE {
+                            "contract"
+                        } else {
+                            &scope
+                        }
+                    );
+                    anyhow::bail!(err_msg);
+                }
+
+                if match_indices.len() == 1 {
+                    wasm_content = wasm_content.replace(&old_sel, &new_sel);
+
+                    #[cfg(debug_assertions)]
+                    {
+                        eprintln!(
+                            "rewrite selector for {}::{}: {} -> {}",
+                            scope, fn_name, old_sel, new_sel,
+                        );
+                    }
+                } else {
+                    if !is_iface {
+                        let err_msg = format!(
+                            "unable to find selector for method `{}` in contract",
+                            fn_name,
+                        );
+                        anyhow::bail!(err_msg);
+                    }
+                }
+            }
+        }
+
+        let mut wasm_file = fs::File::create(dest_wasm)?;
+        wasm_file.write_all(&wabt::wat2wasm(wasm_content).unwrap())?;
+
+        let local_abi = abi.get("$local").unwrap();
+        let mut abi_file = fs::File::create(dest_abi)?;
+        abi_file.write_all(serde_json::to_string(local_abi)?.as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Returns the name of the function described by an ABI entry and its selector, which is
+/// computed from the signature of the function.
+pub(crate) fn get_name_and_selector(
+    fn_info: &Map<String, Value>,
+    use_gm: bool,
+) -> Result<(String, u32)> {
+    let fn_name = match fn_info.get("name").and_then(Value::as_str) {
+        Some(fn_name) => fn_name.to_string(),
+        None => anyhow::bail!("`name` of a function is missing or not a string"),
+    };
+    let inputs = match fn_info.get("inputs").and_then(Value::as_array) {
+        Some(inputs) => inputs,
+        None => anyhow::bail!("`inputs` of `{}` is missing or not an array", fn_name),
+    };
+    let sig = inputs
+        .iter()
+        .map(|input| match input.as_object() {
+            Some(input) => parse_ty(input),
+            None => anyhow::bail!("an input of `{}` is not an object", fn_name),
+        })
+        .collect::<Result<Vec<_>>>()
+        .context(format!("Parsing inputs of `{}`", fn_name))?
+        .join(",");
+    let sig = format!("{}({})", fn_name, sig);
+    let new_sel = calc_selector(sig.as_bytes(), use_gm);
+    Ok((fn_name, new_sel))
+}
+
+/// Reads the start time of the last successful build from the stamp file, returns `None` if the
+/// stamp is missing or was recorded by a build with a different fingerprint.
+fn read_build_stamp(stamp: &Path, fingerprint: &str) -> Option<SystemTime> {
+    let content = fs::read_to_string(stamp).ok()?;
+    let mut lines = content.lines();
+    let nanos = lines.next()?.parse::<u64>().ok()?;
+    if lines.next()? != fingerprint {
+        return None;
+    }
+    Some(UNIX_EPOCH + Duration::from_nanos(nanos))
+}
+
+fn write_build_stamp(stamp: &Path, started: SystemTime, fingerprint: &str) -> Result<()> {
+    let nanos = started
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_nanos() as u64);
+    fs::write(stamp, format!("{}\n{}\n", nanos, fingerprint))
+        .context(format!("Writing build stamp '{}'", stamp.display()))
+}
+
+/// Converts parameters of an ABI entry to arguments in ink! metadata.
+fn ink_args(params: Option<&Value>) -> Result<Vec<Value>> {
+    params
+        .and_then(Value::as_array)
+        .map(|params| {
+            params
+                .iter()
+                .filter_map(Value::as_object)
+                .map(|param| {
+                    let mut arg = serde_json::json!({
+                        "label": param.get("name").and_then(Value::as_str).unwrap_or(""),
+                        "type": { "displayName": [parse_ty(param)?] },
+                    });
+                    if let Some(indexed) = param.get("indexed") {
+                        arg["indexed"] = indexed.clone();
+                        arg["docs"] = serde_json::json!([]);
+                    }
+                    Ok(arg)
+                })
+                .collect()
+        })
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+/// Places the Wasm bytecode and a `metadata.json` in the layout of `cargo-contract`, i.e.
+/// `target/ink/<package>/`, returns the path of the directory.
+///
+/// Types of liquid are not registered into a type registry as ink! does, they are recorded as
+/// display names only.
+fn write_cargo_contract_layout(
+    crate_metadata: &CrateMetadata,
+    version: &str,
+    use_gm: bool,
+) -> Result<PathBuf> {
+    let output_dir = crate_metadata
+        .target_dir()
+        .join("ink")
+        .join(&crate_metadata.package_name);
+    fs::create_dir_all(&output_dir)
+        .context(format!("Creating directory '{}'", output_dir.display()))?;
+
+    let wasm = fs::read(&crate_metadata.dest_wasm)?;
+    let abi: Vec<Map<String, Value>> =
+        serde_json::from_str(&fs::read_to_string(&crate_metadata.dest_abi)?)?;
+
+    let abi_context = format!("Reading ABI file '{}'", crate_metadata.dest_abi.display());
+    let mut constructors = Vec::new();
+    let mut messages = Vec::new();
+    let mut events = Vec::new();
+    for entry in &abi {
+        let label = entry.get("name").and_then(Value::as_str).unwrap_or("");
+        match entry.get("type").and_then(Value::as_str) {
+            Some("constructor") => constructors.push(serde_json::json!({
+                "label": "new",
+                // Liquid contracts have a single constructor invoked by `deploy`, so it has no
+                // selector in fact.
+                "selector": "0x00000000",
+                "args": ink_args(entry.get("inputs")).context(abi_context.clone())?,
+                "docs": [],
+            })),
+            Some("function") => {
+                let (_, selector) =
+                    get_name_and_selector(entry, use_gm).context(abi_context.clone())?;
+                let outputs = entry
+                    .get("outputs")
+                    .and_then(Value::as_array)
+                    .map(|outputs| {
+                        outputs
+                            .iter()
+                            .filter_map(Value::as_object)
+                            .map(parse_ty)
+                            .collect::<Result<Vec<_>>>()
+                    })
+                    .unwrap_or_else(|| Ok(Vec::new()))
+                    .context(abi_context.clone())?;
+                let return_type = match outputs.len() {
+                    0 => Value::Null,
+                    1 => serde_json::json!({ "displayName": [outputs[0]] }),
+                    _ => serde_json::json!({
+                        "displayName": [format!("({})", outputs.join(","))]
+                    }),
+                };
+                messages.push(serde_json::json!({
+                    "label": label,
+                    "selector": format!(
+                        "0x{}",
+                        selector.to_le_bytes().iter().map(|byte| format!("{:02x}", byte)).join("")
+                    ),
+                    "mutates": !entry.get("constant").and_then(Value::as_bool).unwrap_or(false),
+                    "payable": false,
+                    "args": ink_args(entry.get("inputs")).context(abi_context.clone())?,
+                    "returnType": return_type,
+                    "docs": [],
+                }));
+            }
+            Some("event") => events.push(serde_json::json!({
+                "label": label,
+                "args": ink_args(entry.get("inputs")).context(abi_context.clone())?,
+                "docs": [],
+            })),
+            _ => (),
+        }
+    }
+
+    let lang_version = crate_metadata
+        .root_package
+        .dependencies
+        .iter()
+        .find(|dep| dep.name == "liquid_lang")
+        .map_or_else(String::new, |dep| dep.req.to_string());
+    let metadata = serde_json::json!({
+        "source": {
+            "hash": format!("0x{}", calc_hash_hex(&wasm, use_gm)),
+            "language": format!("liquid {}", lang_version),
+            "compiler": format!("rustc {}", rustc_version::version()?),
+        },
+        "contract": {
+            "name": crate_metadata.root_package.name,
+            "version": version,
+            "authors": crate_metadata.root_package.authors,
+        },
+        "spec": {
+            "constructors": constructors,
+            "messages": messages,
+            "events": events,
+            "docs": [],
+        },
+    });
+
+    fs::write(
+        output_dir.join(format!("{}.wasm", crate_metadata.package_name)),
+        &wasm,
+    )?;
+    fs::write(
+        output_dir.join("metadata.json"),
+        serde_json::to_string_pretty(&metadata)?,
+    )?;
+    Ok(output_dir)
+}
+
+/// Generates the ABI and the client crate of the contract for `--interface-mode`, the Wasm
+/// bytecode is not built.
+fn build_client_crate(
+    crate_metadata: &CrateMetadata,
+    version: &str,
+    use_gm: bool,
+    verbosity_behavior: VerbosityBehavior,
+    build_options: &BuildOptions,
+    progress: &mut Progress,
+) -> Result<String> {
+    if crate_metadata.is_collaboration {
+        anyhow::bail!("`--interface-mode` only supports contracts, not collaborations");
+    }
+    progress.step(build_options, &PAPER, "Generating ABI file");
+    run_abi_gen(crate_metadata, verbosity_behavior, build_options)?;
+    let dest_abi = &crate_metadata.dest_abi;
+    let abi_content =
+        fs::read_to_string(dest_abi).context(format!("Reading '{}'", dest_abi.display()))?;
+    let abi: Map<String, Value> = serde_json::from_str(&abi_content).context(format!(
+        "Parsing the output of abi-gen '{}'",
+        dest_abi.display()
+    ))?;
+    let local_abi = abi
+        .get(LOCAL_SCOPE)
+        .and_then(Value::as_array)
+        .ok_or_else(|| anyhow::anyhow!("abi-gen reported no ABI of the contract"))?;
+    fs::write(dest_abi, serde_json::to_string(local_abi)?)
+        .context(format!("Writing '{}'", dest_abi.display()))?;
+
+    progress.step(build_options, &TRUCK, "Generating client crate");
+    let client_dir = write_client_crate(
+        &crate_metadata.package_name,
+        version,
+        local_abi,
+        use_gm,
+        crate_metadata.target_dir(),
+    )
+    .context(format!(
+        "Generating client crate from '{}'",
+        dest_abi.display()
+    ))?;
+    let message = format!(
+        "\n{}Your client crate (v{}) is ready now:\n{: >6}: {}\n{: >6}: {}",
+        SPARKLE,
+        version,
+        "ABI".green().bold(),
+        dest_abi.display().to_string().bold(),
+        "Client".green().bold(),
+        client_dir.display().to_string().bold(),
+    );
+    if build_options.reserves_stdout() {
+        eprintln!("{}", message.bold());
+        Ok(String::new())
+    } else {
+        Ok(message)
+    }
+}
+
+static LOOKING_GLASS: Emoji<'_, '_> = Emoji("🔍 ", "d(・ω・d)");
+static TRUCK: Emoji<'_, '_> = Emoji("🚚 ", "(∫・ω・)∫");
+static CLIP: Emoji<'_, '_> = Emoji("🔗 ", "∇(・ω・∇)");
+static PAPER: Emoji<'_, '_> = Emoji("📃 ", "∂(・ω・∂)");
+static SPARKLE: Emoji<'_, '_> = Emoji("✨ ", "(˘•ω•˘)ง ");
+
+/// Builds the contract once for every variant in `[package.metadata.liquid.matrix]`, the
+/// artifacts of each variant are named after it.
+fn execute_matrix_build(
+    manifest_path: ManifestPath,
+    use_gm: bool,
+    verbosity_behavior: VerbosityBehavior,
+    analysis_behavior: AnalysisBehavior,
+    cfg_path: &Option<PathBuf>,
+    build_options: BuildOptions,
+) -> Result<String> {
+    let crate_metadata = collect_crate_metadata(&manifest_path, use_gm, None)?;
+    if crate_metadata.matrix.is_empty() {
+        anyhow::bail!(
+            "no variant is found in `[package.metadata.liquid.matrix]`, add variants such as \
+             `full = [\"feature-a\", \"feature-b\"]` to it"
+        );
+    }
+
+    let mut messages = Vec::new();
+    for (name, features) in &crate_metadata.matrix {
+        if !build_options.quiet {
+            eprintln!(
+                "{} {}",
+                "Building variant".bright_green().bold(),
+                format!("`{}` [{}]", name, features.join(", ")).bold()
+            );
+        }
+        let variant_options = BuildOptions {
+            matrix: false,
+            variant: Some(Variant {
+                name: name.clone(),
+                features: features.clone(),
+            }),
+            ..build_options.clone()
+        };
+        let message = execute_build(
+            manifest_path.clone(),
+            use_gm,
+            verbosity_behavior,
+            analysis_behavior,
+            cfg_path,
+            variant_options,
+        )
+        .context(format!("Building variant `{}`", name))?;
+        messages.push(format!("{}: {}", name, message));
+    }
+    Ok(messages.join("\n"))
+}
+
+/// Builds every member of the workspace which depends on `liquid_lang`, in the order of their
+/// names. If `keep_going` is set, remaining members are still built after a failure, and a
+/// summary is reported at the end.
+fn execute_workspace_build(
+    manifest_path: ManifestPath,
+    use_gm: bool,
+    verbosity_behavior: VerbosityBehavior,
+    analysis_behavior: AnalysisBehavior,
+    cfg_path: &Option<PathBuf>,
+    build_options: BuildOptions,
+) -> Result<String> {
+    let metadata = utils::get_workspace_metadata(&manifest_path)?;
+    let mut contracts = metadata
+        .packages
+        .iter()
+        .filter(|package| metadata.workspace_members.contains(&package.id))
+        .filter(|package| {
+            package
+                .dependencies
+                .iter()
+                .any(|dep| dep.name == "liquid_lang")
+        })
+        .collect::<Vec<_>>();
+    contracts.sort_by(|a, b| a.name.cmp(&b.name));
+    if contracts.is_empty() {
+        anyhow::bail!(
+            "no member of the workspace at '{}' depends on `liquid_lang`",
+            manifest_path
+        );
+    }
+
+    let mut messages = Vec::new();
+    let mut failed = Vec::new();
+    for package in &contracts {
+        if !build_options.quiet {
+            eprintln!(
+                "{} {}",
+                "Building contract".bright_green().bold(),
+                format!("`{}`", package.name).bold()
+            );
+        }
+        let member_options = BuildOptions {
+            workspace: false,
+            ..build_options.clone()
+        };
+        let result = ManifestPath::new(&package.manifest_path).and_then(|member_manifest| {
+            execute_build(
+                member_manifest,
+                use_gm,
+                verbosity_behavior,
+                analysis_behavior,
+                cfg_path,
+                member_options,
+            )
+        });
+        match result {
+            Ok(message) => messages.push(format!("{}: {}", package.name, message)),
+            Err(e) if build_options.keep_going => {
+                eprintln!("{} {}: {:?}", "ERROR:".bright_red().bold(), package.name, e);
+                failed.push(package.name.as_str());
+            }
+            Err(e) => return Err(e).context(format!("Building contract `{}`", package.name)),
+        }
+    }
+
+    if failed.is_empty() {
+        return Ok(messages.join("\n"));
+    }
+    eprintln!("{}", "Summary:".bold());
+    for package in &contracts {
+        if failed.contains(&package.name.as_str()) {
+            eprintln!("{: >8} {}", "failed".bright_red().bold(), package.name);
+        } else {
+            eprintln!("{: >8} {}", "built".green().bold(), package.name);
+        }
+    }
+    anyhow::bail!(
+        "{} of {} contracts failed to build: {}",
+        failed.len(),
+        contracts.len(),
+        failed.join(", ")
+    )
+}
+
+pub(crate) fn execute_build(
+    manifest_path: ManifestPath,
+    use_gm: bool,
+    verbosity_behavior: VerbosityBehavior,
+    analysis_behavior: AnalysisBehavior,
+    cfg_path: &Option<PathBuf>,
+    build_options: BuildOptions,
+) -> Result<String> {
+    let build_options = BuildOptions {
+        quiet: build_options.quiet || matches!(verbosity_behavior, VerbosityBehavior::Quiet),
+        ..build_options
+    };
+    if build_options.print_rustflags {
+        let crate_metadata = collect_crate_metadata(&manifest_path, use_gm, None)?;
+        return Ok(effective_rustflags(&crate_metadata, build_options.pedantic));
+    }
+    if build_options.workspace {
+        return execute_workspace_build(
+            manifest_path,
+            use_gm,
+            verbosity_behavior,
+            analysis_behavior,
+            cfg_path,
+            build_options,
+        );
+    }
+    if build_options.matrix {
+        return execute_matrix_build(
+            manifest_path,
+            use_gm,
+            verbosity_behavior,
+            analysis_behavior,
+            cfg_path,
+            build_options,
+        );
+    }
+    let started = Instant::now();
+    let started_at = SystemTime::now();
+
+    if build_options.non_interactive {
+        colored::control::set_override(false);
+        env::set_var("CARGO_TERM_COLOR", "never");
+        env::set_var("CARGO_TERM_PROGRESS_WHEN", "never");
+    }
+    if build_options.stdout && console::user_attended() {
+        anyhow::bail!("refusing to write the Wasm bytecode to a terminal, please redirect stdout");
+    }
+    if build_options.stdout && build_options.output_json {
+        anyhow::bail!("cannot write both the Wasm bytecode and the JSON result to stdout");
+    }
+    let memory_sampler = if build_options.report_memory {
+        let memory_sampler = MemorySampler::start();
+        if memory_sampler.is_none() {
+            eprintln!(
+                "{}",
+                "reporting memory usage is not supported on this platform".bright_yellow()
+            );
+        }
+        memory_sampler
+    } else {
+        None
+    };
+
+    let mut progress = Progress::new(&build_options);
+    progress.step(&build_options, &LOOKING_GLASS, "Collecting crate metadata");
+    explain(
+        &build_options,
+        "Asks cargo about the package to find out its name, version and where the artifacts go.",
+    );
+    let variant_name = build_options
+        .variant
+        .as_ref()
+        .map(|variant| variant.name.as_str());
+    let mut crate_metadata = collect_crate_metadata(&manifest_path, use_gm, variant_name)?;
+    let version = match &build_options.version_override {
+        Some(version) => cargo_metadata::Version::parse(version)
+            .context(format!("`{}` is not a valid semantic version", version))?
+            .to_string(),
+        None => crate_metadata.root_package.version.to_string(),
+    };
+    if let Some(name_template) = &build_options.name_template {
+        let name = name_template.render(&crate_metadata.package_name, &version);
+        crate_metadata.set_wasm_name(&name, variant_name, use_gm);
+    }
+
+    if build_options.interface_mode {
+        return build_client_crate(
+            &crate_metadata,
+            &version,
+            use_gm,
+            verbosity_behavior,
+            &build_options,
+            &mut progress,
+        );
+    }
+
+    // Artifacts written to stdout, the call graph, the ABI check, reports and metrics of the build
+    // and artifacts named after the hash of the Wasm bytecode are not cached, so they are always
+    // rebuilt.
+    let build_stamp = match &build_options.variant {
+        Some(variant) => crate_metadata
+            .target_dir()
+            .join(format!("{}-{}", BUILD_STAMP, variant.name)),
+        None => crate_metadata.target_dir().join(BUILD_STAMP),
+    };
+    let fingerprint = build_cache::build_fingerprint(
+        &crate_metadata.cargo_meta,
+        &build_options,
+        use_gm,
+        analysis_behavior,
+    )?;
+    let mut dest_manifest = build_options
+        .manifest_out
+        .clone()
+        .unwrap_or_else(|| crate_metadata.dest_manifest.clone());
+    if !build_options.reserves_stdout()
+        && !build_options.no_cache
+        && cfg_path.is_none()
+        && build_options.abi_baseline.is_none()
+        && build_options.output_format == OutputFormat::Liquid
+        && !build_options.report_build_inputs
+        && build_options.metrics.is_none()
+        && build_options.report.is_none()
+        && !build_options
+            .name_template
+            .as_ref()
+            .map(NameTemplate::needs_hash)
+            .unwrap_or(false)
+    {
+        if let Some(last_build) = read_build_stamp(&build_stamp, &fingerprint) {
+            let artifacts_exist = [
+                (Artifact::Wasm, &crate_metadata.dest_wasm),
+                (Artifact::Abi, &crate_metadata.dest_abi),
+                (Artifact::Hash, &crate_metadata.dest_hash),
+                (Artifact::Manifest, &dest_manifest),
+            ]
+            .iter()
+            .all(|(artifact, path)| !build_options.emits(*artifact) || path.exists())
+                && (!build_options.split_debug || crate_metadata.dest_debug().exists());
+            let input_files = build_cache::input_files(
+                &crate_metadata.cargo_meta,
+                &crate_metadata.root_package.id,
+                build_options.liquid_path.as_deref(),
+            )?;
+            let changed = matches!(
+                build_cache::newest_modified(&input_files)?,
+                Some(newest) if newest > last_build
+            );
+            if artifacts_exist && !changed {
+                return Ok("Nothing changed, skipping build".to_owned());
+            }
+        }
+    }
+
+    let lib_rs_path = crate_metadata.lib_rs_path();
+    if fs::metadata(&lib_rs_path).is_err() {
+        anyhow::bail!(
+            "the root source file of the contract '{}' is not found, please check whether it \
+             was moved or deleted, or set `[lib] path` in the manifest to where it is",
+            lib_rs_path.display()
+        );
+    }
+    let inputs_path = crate_metadata
+        .target_dir()
+        .join(format!("{}.inputs.json", crate_metadata.package_name));
+    if build_options.report_build_inputs {
+        write_build_inputs(
+            &crate_metadata.cargo_meta,
+            &crate_metadata.root_package.id,
+            &inputs_path,
+        )?;
+    }
+    // Read before the build overwrites it. If the Wasm bytecode is named after its hash, the last
+    // one has a different name and is not found.
+    let last_wasm = if build_options.diff_artifacts {
+        fs::read(&crate_metadata.dest_wasm).ok()
+    } else {
+        None
+    };
+    progress.step(&build_options, &TRUCK, "Building cargo project");
+    explain(
+        &build_options,
+        "Compiles the contract to Wasm, along with a core library built without panic messages.",
+    );
+    let modified_time = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let original_wasm_modified = modified_time(&crate_metadata.original_wasm);
+    let CargoBuildOutput {
+        analysis: build_result,
+        diagnostics,
+    } = build_cargo_project(
+        &crate_metadata,
+        use_gm,
+        verbosity_behavior,
+        analysis_behavior,
+        cfg_path,
+        &build_options,
+    )?;
+    // Cargo leaves the Wasm bytecode untouched if nothing needs to be recompiled.
+    let cached = original_wasm_modified.is_some()
+        && original_wasm_modified == modified_time(&crate_metadata.original_wasm);
+    print_message(
+        &build_options,
+        format_args!(
+            "      {}",
+            if cached {
+                "up to date (cached)"
+            } else {
+                "rebuilt"
+            }
+        ),
+    );
+
+    // Without wasm-opt, post-processing is regarded as a part of building the cargo project.
+    if !build_options.skip_wasm_opt {
+        progress.step(&build_options, &CLIP, "Optimizing Wasm bytecode");
+    }
+    explain(
+        &build_options,
+        "Tree-shaking removes code unreachable from the entry points, and custom sections such as \
+         debug names are stripped, since the chain charges for every byte deployed.",
+    );
+    post_process_wasm(&crate_metadata, verbosity_behavior, &build_options)?;
+    let optimization_sizes = if build_options.skip_wasm_opt {
+        None
+    } else {
+        explain(
+            &build_options,
+            "wasm-opt then inlines, simplifies and shrinks the bytecode further, which saves gas \
+             on both deployment and execution.",
+        );
+        optimize_wasm(&crate_metadata, &build_options)?
+    };
+    if let Some((original_size, optimized_size)) = optimization_sizes {
+        print_message(
+            &build_options,
+            format_args!("      {} bytes -> {} bytes", original_size, optimized_size),
+        );
+    }
+    let dest_debug = if build_options.split_debug {
+        split_debug_info(&crate_metadata)?
+    } else {
+        None
+    };
+    let preserved_sections = if build_options.no_strip_sections {
+        custom_sections(&crate_metadata)
+    } else if build_options.preserve_names {
+        custom_sections(&crate_metadata)
+            .into_iter()
+            .filter(|section| section.name() == "name" || section.name().starts_with(".debug_"))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    progress.step(&build_options, &PAPER, "Generating ABI file");
+    explain(
+        &build_options,
+        "Builds and runs a helper on the host which describes the methods and events of the \
+         contract, so that clients know how to encode calls to it.",
+    );
+    generate_abi(&crate_metadata, verbosity_behavior, use_gm, &build_options)?;
+    embed_build_info(&crate_metadata, &version, preserved_sections)?;
+    if build_options.preserve_names || build_options.no_strip_sections {
+        eprintln!(
+            "{}",
+            "names and debug info are preserved, so the Wasm bytecode is larger than a release \
+             build and should not be deployed to production"
+                .bright_yellow()
+        );
+    }
+
+    if analysis_behavior != AnalysisBehavior::Skip && !crate_metadata.is_collaboration {
+        if let Ok(cfa_result) = serde_json::from_str::<'_, Value>(&build_result) {
+            let cfa_result = cfa_result.as_object().unwrap();
+            let abi_content = fs::read_to_string(&crate_metadata.dest_abi).unwrap();
+            let mut origin_abi: Value = serde_json::from_str(&abi_content).unwrap();
+            let abi_context = format!("Reading ABI file '{}'", crate_metadata.dest_abi.display());
+            for method in origin_abi.as_array_mut().unwrap() {
+                let method = method.as_object_mut().unwrap();
+                if method.contains_key("name")
+                    && method.contains_key("type")
+                    && method["type"] == Value::String("function".into())
+                {
+                    // calculate selector of method
+                    let (method_name, selector) =
+                        get_name_and_selector(method, use_gm).context(abi_context.clone())?;
+                    if cfa_result.contains_key(&method_name) {
+                        method.insert("conflictFields".into(), cfa_result[&method_name].clone());
+                    }
+                    if use_gm {
+                        let (_, normal_selector) =
+                            get_name_and_selector(method, false).context(abi_context.clone())?;
+                        method.insert(
+                            "selector".into(),
+                            serde_json::to_value(vec![normal_selector, selector]).unwrap(),
+                        );
+                    } else {
+                        let (_, gm_selector) =
+                            get_name_and_selector(method, true).context(abi_context.clone())?;
+                        method.insert(
+                            "selector".into(),
+                            serde_json::to_value(vec![selector, gm_selector]).unwrap(),
+                        );
+                    }
+                }
+            }
+            let new_abi = serde_json::to_string(&origin_abi).unwrap();
+            fs::write(&crate_metadata.dest_abi, new_abi).unwrap();
+        } else {
+            eprintln!(
+                "{}\n{}",
+                "unable to parse the result of conflict fields analysis:".yellow(),
+                build_result
+            );
+        }
+    }
+
+    if build_options.deny_floats {
+        let floats = execute_report_floats(&crate_metadata.dest_wasm)?;
+        if let Some(first) = floats.first() {
+            anyhow::bail!(
+                "the Wasm bytecode contains {} floating-point instructions, e.g. `{}` in \
+                 func[{}], whose results may differ across nodes of the chain. Run `cargo liquid \
+                 report-floats {}` to list all of them",
+                floats.len(),
+                first.opcode,
+                first.function,
+                crate_metadata.dest_wasm.display()
+            );
+        }
+    }
+    // `--max-size` overrides `liquid.max-size` of the manifest.
+    let max_size = build_options.max_size.or(crate_metadata.max_size);
+    if max_size.is_some() || build_options.min_size.is_some() {
+        let size = fs::metadata(&crate_metadata.dest_wasm)?.len() as usize;
+        if let Some(max_size) = max_size {
+            if size > max_size {
+                anyhow::bail!(
+                    "the size of the Wasm bytecode is {} bytes, which exceeds the limit of {} \
+                     bytes",
+                    size,
+                    max_size
+                );
+            }
+        }
+        if let Some(min_size) = build_options.min_size {
+            if size < min_size {
+                anyhow::bail!(
+                    "the size of the Wasm bytecode is {} bytes, which is below the minimum of {} \
+                     bytes, the optimization may have stripped almost everything",
+                    size,
+                    min_size
+                );
+            }
+        }
+    }
+    if let Some(abi_baseline) = &build_options.abi_baseline {
+        let issues = execute_semver_check(abi_baseline, &crate_metadata.dest_abi)?;
+        for issue in &issues {
+            print_message(&build_options, issue);
+        }
+        let breaking = issues
+            .iter()
+            .any(|issue| issue.compatibility == Compatibility::BinaryBreaking);
+        if breaking && !build_options.allow_abi_break {
+            anyhow::bail!(
+                "the ABI is incompatible with the baseline '{}'",
+                abi_baseline.display()
+            );
+        }
+    }
+    if let Some(name_template) = build_options
+        .name_template
+        .as_ref()
+        .filter(|name_template| name_template.needs_hash())
+    {
+        // The Wasm bytecode is final at this point, so it's renamed after its own hash.
+        let wasm = fs::read(&crate_metadata.dest_wasm)?;
+        let hash = calc_hash_hex(&wasm, use_gm);
+        let name = name_template
+            .render(&crate_metadata.package_name, &version)
+            .replace("{hash}", &hash[..NAME_HASH_LEN]);
+        let unnamed_wasm = crate_metadata.dest_wasm.clone();
+        crate_metadata.set_wasm_name(&name, variant_name, use_gm);
+        replace_file(&unnamed_wasm, &crate_metadata.dest_wasm)?;
+        if build_options.manifest_out.is_none() {
+            dest_manifest = crate_metadata.dest_manifest.clone();
+        }
+    }
+    let ink_dir = match build_options.output_format {
+        OutputFormat::CargoContract => Some(write_cargo_contract_layout(
+            &crate_metadata,
+            &version,
+            use_gm,
+        )?),
+        OutputFormat::Liquid => None,
+    };
+    if build_options.report_build_inputs {
+        let lock_path = crate_metadata
+            .target_dir()
+            .join(format!("{}.build.lock", crate_metadata.package_name));
+        write_build_lock(&inputs_path, &crate_metadata.dest_wasm, &lock_path)?;
+    }
+    if build_options.emits(Artifact::Hash) {
+        let wasm = fs::read(&crate_metadata.dest_wasm)?;
+        let hash = calc_hash_hex(&wasm, use_gm);
+        fs::write(&crate_metadata.dest_hash, hash)?;
+    }
+    if build_options.emits(Artifact::Manifest) {
+        let wasm = fs::read(&crate_metadata.dest_wasm)?;
+        let manifest = ArtifactManifest {
+            wasm: Some(crate_metadata.dest_wasm.as_path())
+                .filter(|_| build_options.emits(Artifact::Wasm)),
+            sha256: utils::sha256_hex(&wasm),
+            size: wasm.len(),
+            abi: Some(crate_metadata.dest_abi.as_path())
+                .filter(|_| build_options.emits(Artifact::Abi)),
+            version: &version,
+            cargo_liquid_version: env!("CARGO_PKG_VERSION"),
+        };
+        fs::write(&dest_manifest, serde_json::to_string_pretty(&manifest)?)
+            .context(format!("Writing '{}'", dest_manifest.display()))?;
+    }
+    if let Some(last_wasm) = &last_wasm {
+        let wasm = fs::read(&crate_metadata.dest_wasm)?;
+        print_message(&build_options, diff::summarize_changes(last_wasm, &wasm)?);
+    }
+    if let Some(report_path) = &build_options.report {
+        let wasm = fs::read(&crate_metadata.dest_wasm)?;
+        BuildReport {
+            package_name: &crate_metadata.package_name,
+            version: &version,
+            dest_wasm: &crate_metadata.dest_wasm,
+            hash: &calc_hash_hex(&wasm, use_gm),
+            optimization_sizes,
+            package_dir: crate_metadata
+                .root_package
+                .manifest_path
+                .parent()
+                .expect("the manifest path is a file path so has a parent"),
+        }
+        .write(report_path)?;
+    }
+    if let Some(metrics_path) = &build_options.metrics {
+        let metrics = WasmMetrics::new(&fs::read(&crate_metadata.dest_wasm)?)?;
+        fs::write(metrics_path, serde_json::to_string_pretty(&metrics)?)
+            .context(format!("Writing '{}'", metrics_path.display()))?;
+    }
+    if build_options.stdout {
+        // On Windows the standard output handle doesn't translate line endings, so the
+        // bytecode is written as it is.
+        let wasm = fs::read(&crate_metadata.dest_wasm)?;
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        stdout.write_all(&wasm)?;
+        stdout.flush()?;
+    }
+    if !build_options.emits(Artifact::Abi) {
+        fs::remove_file(&crate_metadata.dest_abi)?;
+    }
+    if !build_options.emits(Artifact::Wasm) {
+        fs::remove_file(&crate_metadata.dest_wasm)?;
+    }
+    write_build_stamp(&build_stamp, started_at, &fingerprint)?;
+    if let Some(memory_sampler) = memory_sampler {
+        print_message(
+            &build_options,
+            format_args!(
+                "Peak memory usage: {:.1} MiB",
+                memory_sampler.finish() as f64 / (1024.0 * 1024.0)
+            ),
+        );
+    }
+
+    if build_options.output_json {
+        let emitted = |artifact, path: &PathBuf| {
+            if build_options.emits(artifact) {
+                Some(path.clone())
+            } else {
+                None
+            }
+        };
+        let build_result = BuildResult {
+            version,
+            cached,
+            dest_wasm: emitted(Artifact::Wasm, &crate_metadata.dest_wasm),
+            dest_abi: emitted(Artifact::Abi, &crate_metadata.dest_abi),
+            dest_hash: emitted(Artifact::Hash, &crate_metadata.dest_hash),
+            dest_manifest: emitted(Artifact::Manifest, &dest_manifest),
+            original_size: optimization_sizes.map(|(original_size, _)| original_size),
+            optimized_size: optimization_sizes.map(|(_, optimized_size)| optimized_size),
+            diagnostics: if build_options.json_diagnostics {
+                Some(diagnostics)
+            } else {
+                None
+            },
+        };
+        println!("{}", serde_json::to_string_pretty(&build_result)?);
+        return Ok(String::new());
+    }
+
+    let artifacts = [
+        (Artifact::Wasm, "Binary", &crate_metadata.dest_wasm),
+        (Artifact::Abi, "ABI", &crate_metadata.dest_abi),
+        (Artifact::Hash, "Hash", &crate_metadata.dest_hash),
+        (Artifact::Manifest, "Manifest", &dest_manifest),
+    ]
+    .iter()
+    .filter(|(artifact, ..)| build_options.emits(*artifact))
+    .map(|(_, name, path)| (*name, *path))
+    .chain(dest_debug.iter().map(|path| ("Debug", path)))
+    .chain(ink_dir.iter().map(|dir| ("Ink!", dir)))
+    .map(|(name, path)| {
+        let mut path = path.display().to_string();
+        if cfg!(target_os = "windows") {
+            path = path.replace("\\", "\\\\");
+        }
+        format!("\n{: >6}: {}", name.green().bold(), path.bold())
+    })
+    .join("");
+    let message = format!(
+        "\n{}Done in {}, your project (v{}) is ready now:{}",
+        SPARKLE,
+        HumanDuration(started.elapsed()),
+        version,
+        artifacts,
+    );
+    if build_options.reserves_stdout() {
+        eprintln!("{}", message.bold());
+        Ok(String::new())
+    } else {
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parity_wasm::elements::{
+        CodeSection, DataSection, ExportEntry, ExportSection, Func, FuncBody,
+        FunctionNameSubsection, FunctionSection, FunctionType, GlobalEntry, GlobalSection,
+        GlobalType, Instructions, NameSection, Type, TypeSection, ValueType,
+    };
+
+    fn segment(offset: u32, value: &[u8]) -> DataSegment {
+        let offset = InitExpr::new(vec![Instruction::I32Const(offset as i32), Instruction::End]);
+        DataSegment::new(0, Some(offset), value.to_vec())
+    }
+
+    /// A module whose code reads constants at `addresses`, with `segments` in its data section.
+    fn module(addresses: &[u32], segments: Vec<DataSegment>) -> Module {
+        let mut code = addresses
+            .iter()
+            .flat_map(|address| vec![Instruction::I32Const(*address as i32), Instruction::Drop])
+            .collect::<Vec<_>>();
+        code.push(Instruction::End);
+        Module::new(vec![
+            Section::Code(CodeSection::with_bodies(vec![FuncBody::new(
+                Vec::new(),
+                Instructions::new(code),
+            )])),
+            Section::Data(DataSection::with_entries(segments)),
+        ])
+    }
+
+    /// The initial content of the first 4 KiB of the memory.
+    fn memory(module: &Module) -> Vec<u8> {
+        let segments = module.data_section().unwrap().entries();
+        let offsets = data_segment_offsets(segments).unwrap();
+        let mut memory = vec![0; 4096];
+        for (offset, segment) in offsets.into_iter().zip(segments) {
+            let offset = offset as usize;
+            memory[offset..offset + segment.value().len()].copy_from_slice(segment.value());
+        }
+        memory
+    }
+
+    fn serialized_size(module: &Module) -> usize {
+        module.clone().to_bytes().unwrap().len()
+    }
+
+    /// Creates a contract named `contract` under `dir` depending on a local `liquid_lang`, so
+    /// that `cargo metadata` resolves it without the network, returns the path of its manifest.
+    fn contract(dir: &Path) -> PathBuf {
+        for (package, dependencies) in [
+            ("contract", "liquid_lang = { path = \"../liquid_lang\" }"),
+            ("liquid_lang", ""),
+        ]
+        .iter()
+        {
+            fs::create_dir_all(dir.join(package).join("src")).unwrap();
+            fs::write(dir.join(package).join("src").join("lib.rs"), "").unwrap();
+            fs::write(
+                dir.join(package).join("Cargo.toml"),
+                format!(
+                    "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2018\"\n\n\
+                     [dependencies]\n{}\n",
+                    package, dependencies
+                ),
+            )
+            .unwrap();
+        }
+        dir.join("contract").join("Cargo.toml")
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn collect_crate_metadata_resolves_symlinked_manifest() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = contract(dir.path());
+        let vendored = dir.path().join("vendor").join("contract");
+        fs::create_dir_all(&vendored).unwrap();
+        std::os::unix::fs::symlink(&manifest, vendored.join("Cargo.toml")).unwrap();
+
+        let manifest_path = ManifestPath::new(vendored.join("Cargo.toml")).unwrap();
+        let crate_metadata = collect_crate_metadata(&manifest_path, false, None).unwrap();
+        let target = manifest.canonicalize().unwrap().with_file_name("target");
+        assert_eq!(crate_metadata.cargo_meta.target_directory, target);
+        assert_eq!(crate_metadata.dest_wasm, target.join("contract.wasm"));
+        assert_eq!(
+            crate_metadata.original_wasm,
+            target
+                .join(BUILD_TARGET_ARCH)
+                .join("release")
+                .join("deps")
+                .join("contract.wasm")
+        );
+    }
+
+    #[test]
+    fn collect_crate_metadata_recreates_cleaned_target_directory() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest_path = ManifestPath::new(contract(dir.path())).unwrap();
+        let target = collect_crate_metadata(&manifest_path, false, None)
+            .unwrap()
+            .cargo_meta
+            .target_directory;
+        assert!(target.is_dir());
+
+        let cargo = env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+        let status = Command::new(cargo)
+            .args(["clean", "--manifest-path"])
+            .arg(manifest_path.as_ref())
+            .status()
+            .unwrap();
+        assert!(status.success());
+        assert!(!target.exists());
+
+        // Artifacts are written into the target directory without creating it first.
+        let crate_metadata = collect_crate_metadata(&manifest_path, false, None).unwrap();
+        assert!(target.is_dir());
+        assert_eq!(crate_metadata.cargo_meta.target_directory, target);
+        for path in [
+            &crate_metadata.original_wasm,
+            &crate_metadata.dest_wasm,
+            &crate_metadata.dest_abi,
+        ] {
+            assert!(path.starts_with(&target), "'{}' is outside", path.display());
+        }
+        fs::write(&crate_metadata.dest_wasm, b"\0asm").unwrap();
+        write_build_stamp(&target.join(BUILD_STAMP), SystemTime::now(), "").unwrap();
+        assert!(crate_metadata.dest_wasm.is_file());
+        assert!(target.join(BUILD_STAMP).is_file());
+    }
+
+    /// A module exporting `f`, which adds 16 to an immutable global initialized to 1024 and to an
+    /// exported mutable global initialized to 7.
+    fn globals_module() -> Module {
+        let global = |mutable, value| {
+            GlobalEntry::new(
+                GlobalType::new(ValueType::I32, mutable),
+                InitExpr::new(vec![Instruction::I32Const(value), Instruction::End]),
+            )
+        };
+        let code = vec![
+            Instruction::GetGlobal(0),
+            Instruction::I32Const(16),
+            Instruction::I32Add,
+            Instruction::GetGlobal(1),
+            Instruction::I32Add,
+            Instruction::End,
+        ];
+        Module::new(vec![
+            Section::Type(TypeSection::with_types(vec![Type::Function(
+                FunctionType::new(Vec::new(), vec![ValueType::I32]),
+            )])),
+            Section::Function(FunctionSection::with_entries(vec![Func::new(0)])),
+            Section::Global(GlobalSection::with_entries(vec![
+                global(false, 1024),
+                global(true, 7),
+            ])),
+            Section::Export(ExportSection::with_entries(vec![
+                ExportEntry::new("f".to_owned(), Internal::Function(0)),
+                ExportEntry::new("g".to_owned(), Internal::Global(1)),
+            ])),
+            Section::Code(CodeSection::with_bodies(vec![FuncBody::new(
+                Vec::new(),
+                Instructions::new(code),
+            )])),
+        ])
+    }
+
+    fn invoke_f(module: Module) -> Option<wasmi::RuntimeValue> {
+        let module = wasmi::Module::from_parity_wasm_module(module).unwrap();
+        wasmi::ModuleInstance::new(&module, &wasmi::ImportsBuilder::default())
+            .unwrap()
+            .assert_no_start()
+            .invoke_export("f", &[], &mut wasmi::NopExternals)
+            .unwrap()
+    }
+
+    fn code_size(module: &Module) -> usize {
+        diff::section_sizes(module)
+            .unwrap()
+            .into_iter()
+            .find(|(name, _)| name == "code")
+            .map_or(0, |(_, size)| size)
+    }
+
+    #[test]
+    fn propagate_constants_inlines_immutable_globals() {
+        let mut module = globals_module();
+        let original_size = code_size(&module);
+        let result = invoke_f(module.clone());
+
+        assert_eq!(propagate_constants(&mut module), 1);
+        let code = module.code_section().unwrap().bodies()[0].code().elements();
+        assert_eq!(code[0], Instruction::I32Const(1024));
+        assert_eq!(code[3], Instruction::GetGlobal(1));
+        assert_eq!(invoke_f(module.clone()), result);
+        // `i32.const 1024` takes one more byte than `global.get 0`, which only pays off once
+        // wasm-opt folds the constants.
+        assert_eq!(code_size(&module), original_size + 1);
+    }
+
+    #[test]
+    fn propagate_constants_keeps_written_globals() {
+        let mut module = globals_module();
+        let body = &mut module.code_section_mut().unwrap().bodies_mut()[0];
+        body.code_mut().elements_mut().splice(
+            0..0,
+            vec![Instruction::I32Const(1), Instruction::SetGlobal(0)],
+        );
+        let original = module.clone();
+
+        assert_eq!(propagate_constants(&mut module), 0);
+        assert_eq!(module, original);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn run_wasm_opt_passes_paths_with_spaces_and_quotes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let bin = dir.path().join("binaryen 'v1' \"bin\"");
+        let contract = dir.path().join("my contract $HOME");
+        fs::create_dir_all(&bin).unwrap();
+        fs::create_dir_all(&contract).unwrap();
+        // Copies the input to the output, arguments are only intact if they're not split by
+        // spaces or expanded by a shell on the way.
+        let wasm_opt = bin.join("wasm-opt");
+        fs::write(
+            &wasm_opt,
+            "#!/bin/sh\ninput=\"$1\"\nwhile [ \"$1\" != -o ]; do shift; done\ncp \"$input\" \"$2\"\n",
+        )
+        .unwrap();
+        fs::set_permissions(&wasm_opt, fs::Permissions::from_mode(0o755)).unwrap();
+        let wasm = contract.join("contract.wasm");
+        fs::write(&wasm, b"\0asm").unwrap();
+        // A leftover output of an interrupted run is overwritten.
+        fs::write(wasm.with_extension("opt.wasm"), b"stale").unwrap();
+
+        let sizes = run_wasm_opt(&wasm, Some(OptimizationLevel::Size), Some(&wasm_opt), true);
+        assert_eq!(sizes.unwrap(), Some((4, 4)));
+        assert_eq!(fs::read(&wasm).unwrap(), b"\0asm");
+        assert!(!wasm.with_extension("opt.wasm").exists());
+    }
+
+    #[test]
+    fn replace_file_overwrites_destination() {
+        let dir = tempfile::tempdir().unwrap();
+        let (from, to) = (dir.path().join("new file"), dir.path().join("old file"));
+        fs::write(&from, "new").unwrap();
+        fs::write(&to, "old").unwrap();
+
+        replace_file(&from, &to).unwrap();
+        assert_eq!(fs::read_to_string(&to).unwrap(), "new");
+        assert!(!from.exists());
+    }
+
+    #[test]
+    fn resolve_executable_appends_extension_of_executables() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("wasm-opt");
+        let expected = if cfg!(windows) {
+            dir.path().join("wasm-opt.exe")
+        } else {
+            missing.clone()
+        };
+        assert_eq!(resolve_executable(&missing), expected);
+
+        let existing = dir.path().join("wasm opt");
+        fs::write(&existing, "").unwrap();
+        assert_eq!(resolve_executable(&existing), existing);
+    }
+
+    const PATH: &[u8] = b"/home/user/contract/src/lib.rs";
+
+    /// A segment at 1024 with `hello` right before `PATH`, a panic location of `PATH` at line 10
+    /// and column 5 at 1060, and `world` right after the location at 1076.
+    fn panic_location_segment() -> DataSegment {
+        let mut value = b"hello".to_vec();
+        value.extend(PATH);
+        value.resize(36, 0);
+        for word in [1029, PATH.len() as u32, 10, 5].iter() {
+            value.extend(&u32::to_le_bytes(*word));
+        }
+        value.extend(b"world");
+        segment(1024, &value)
+    }
+
+    #[test]
+    fn strip_panic_messages_removes_exact_paths() {
+        let mut module = module(&[1024, 1060, 1076], vec![panic_location_segment()]);
+        let original_memory = memory(&module);
+        let original_size = serialized_size(&module);
+
+        let stripped = strip_panic_messages(&mut module);
+        assert_eq!(stripped.locations, 1);
+        assert_eq!(stripped.zeroed, PATH.len());
+
+        let memory = memory(&module);
+        assert_eq!(&memory[1024..1029], b"hello");
+        assert_eq!(&memory[1076..1081], b"world");
+        assert!(memory[1029..1060].iter().all(|byte| *byte == 0));
+        assert_eq!(read_u32(&memory, 1060), 1029);
+        assert_eq!(read_u32(&memory, 1064), 0);
+        assert_eq!(&memory[1068..1076], &original_memory[1068..1076]);
+
+        let segments = module.data_section().unwrap().entries();
+        assert_eq!(segments.len(), 2);
+        assert!(serialized_size(&module) < original_size - PATH.len() + DATA_SEGMENT_HEADER_LIMIT);
+        assert_eq!(stripped.removed, original_size - serialized_size(&module));
+    }
+
+    #[test]
+    fn strip_panic_messages_keeps_referenced_paths() {
+        let mut module = module(&[1029, 1060], vec![panic_location_segment()]);
+        let original_memory = memory(&module);
+
+        let stripped = strip_panic_messages(&mut module);
+        assert_eq!(stripped.locations, 0);
+        assert_eq!(stripped.zeroed, 0);
+        assert_eq!(memory(&module), original_memory);
+    }
+
+    #[test]
+    fn strip_panic_messages_reindexes_data_names() {
+        let segments = vec![panic_location_segment(), segment(2048, b"data")];
+        let mut module = module(&[1024, 1060, 2048], segments);
+        let names = |entries: &[(u32, &str)]| {
+            let mut content = Vec::new();
+            VarUint32::from(entries.len())
+                .serialize(&mut content)
+                .unwrap();
+            for (idx, name) in entries {
+                VarUint32::from(*idx).serialize(&mut content).unwrap();
+                name.to_string().serialize(&mut content).unwrap();
+            }
+            let mut payload = vec![DATA_NAMES_SUBSECTION];
+            VarUint32::from(content.len())
+                .serialize(&mut payload)
+                .unwrap();
+            payload.extend(content);
+            payload
+        };
+        module
+            .sections_mut()
+            .push(Section::Custom(CustomSection::new(
+                "name".to_owned(),
+                names(&[(0, ".rodata"), (1, ".data")]),
+            )));
+
+        strip_panic_messages(&mut module);
+        let payload = module
+            .custom_sections()
+            .find(|section| section.name() == "name")
+            .unwrap()
+            .payload();
+        assert_eq!(payload, &names(&[(0, ".rodata"), (2, ".data")])[..]);
+    }
+
+    #[test]
+    fn trim_data_segments_removes_rewritten_segments_and_zeros() {
+        let segments = vec![
+            segment(1024, b"hello\0\0\0"),
+            segment(2048, b"\0\0hello"),
+            segment(1024, b"hello\0\0\0"),
+        ];
+        let mut module = module(&[1024, 2050], segments);
+        module.sections_mut().insert(1, Section::DataCount(3));
+        let original_memory = memory(&module);
+        let original_size = serialized_size(&module);
+
+        assert_eq!(trim_data_segments(&mut module), 13);
+        assert_eq!(memory(&module), original_memory);
+        assert!(serialized_size(&module) < original_size);
+        // Identical strings at different addresses stay where the code expects them.
+        let segments = module.data_section().unwrap().entries();
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].value(), b"hello");
+        assert_eq!(segments[1].value(), b"hello");
+        assert!(module
+            .sections()
+            .iter()
+            .any(|section| matches!(section, Section::DataCount(2))));
+    }
+
+    #[test]
+    fn trim_data_segments_keeps_overwritten_bytes() {
+        let segments = vec![
+            segment(1024, b"abc"),
+            segment(1025, b"x"),
+            segment(1024, b"abc"),
+            segment(1024, b"\0\0\0"),
+        ];
+        let mut module = module(&[1024], segments);
+        let original_memory = memory(&module);
+
+        assert_eq!(trim_data_segments(&mut module), 0);
+        assert_eq!(memory(&module), original_memory);
+        assert_eq!(module.data_section().unwrap().entries().len(), 4);
+    }
+
+    /// A module in which the exported `main` calls `helper`, while the exported `unused` is not
+    /// called, with a serialized name section and a `producers` custom section.
+    fn exports_module() -> Module {
+        let mut names = FunctionNameSubsection::default();
+        for (idx, name) in ["main", "helper", "unused"].iter().enumerate() {
+            names.names_mut().insert(idx as u32, (*name).to_owned());
+        }
+        let body = |code| FuncBody::new(Vec::new(), Instructions::new(code));
+        let module = Module::new(vec![
+            Section::Type(TypeSection::with_types(vec![Type::Function(
+                FunctionType::new(Vec::new(), Vec::new()),
+            )])),
+            Section::Function(FunctionSection::with_entries(vec![Func::new(0); 3])),
+            Section::Export(ExportSection::with_entries(vec![
+                ExportEntry::new("main".to_owned(), Internal::Function(0)),
+                ExportEntry::new("unused".to_owned(), Internal::Function(2)),
+            ])),
+            Section::Code(CodeSection::with_bodies(vec![
+                body(vec![Instruction::Call(1), Instruction::End]),
+                body(vec![Instruction::End]),
+                body(vec![Instruction::End]),
+            ])),
+            Section::Name(NameSection::new(None, Some(names), None)),
+            Section::Custom(CustomSection::new(
+                "producers".to_owned(),
+                b"rustc".to_vec(),
+            )),
+        ]);
+        parity_wasm::deserialize_buffer(&parity_wasm::serialize(module).unwrap()).unwrap()
+    }
+
+    fn export_names(module: &Module) -> Vec<&str> {
+        module
+            .export_section()
+            .unwrap()
+            .entries()
+            .iter()
+            .map(ExportEntry::field)
+            .collect()
+    }
+
+    fn has_custom_sections(module: &Module) -> bool {
+        module
+            .sections()
+            .iter()
+            .any(|section| matches!(section, Section::Custom(_) | Section::Name(_)))
+    }
+
+    #[test]
+    fn process_module_strips_unused_exports_and_custom_sections() {
+        let opts = ProcessOptions {
+            used_exports: vec!["main".to_owned()],
+            tree_shaking: true,
+            keep_custom_sections: false,
+            strip_data_count: false,
+        };
+        let module = process_module(exports_module(), opts).unwrap();
+
+        assert_eq!(export_names(&module), ["main"]);
+        assert_eq!(module.functions_space(), 2);
+        assert!(!has_custom_sections(&module));
+    }
+
+    #[test]
+    fn process_module_keeps_custom_sections_if_requested() {
+        let opts = ProcessOptions {
+            used_exports: vec!["main".to_owned()],
+            tree_shaking: true,
+            keep_custom_sections: true,
+            strip_data_count: false,
+        };
+        let module = process_module(exports_module(), opts).unwrap();
+
+        assert_eq!(export_names(&module), ["main"]);
+        assert_eq!(module.functions_space(), 2);
+        assert!(module
+            .custom_sections()
+            .any(|section| section.name() == "producers"));
+        let names = module.names_section().unwrap().functions().unwrap().names();
+        assert_eq!(names.get(0).unwrap(), "main");
+        assert_eq!(names.get(1).unwrap(), "helper");
+        assert_eq!(names.get(2), None);
+    }
+
+    #[test]
+    fn process_module_strips_custom_sections_without_tree_shaking() {
+        let mut module = exports_module();
+        module.sections_mut().push(Section::DataCount(0));
+        let opts = ProcessOptions {
+            used_exports: Vec::new(),
+            tree_shaking: false,
+            keep_custom_sections: false,
+            strip_data_count: true,
+        };
+        let module = process_module(module, opts).unwrap();
+
+        assert_eq!(export_names(&module), ["main", "unused"]);
+        assert_eq!(module.functions_space(), 3);
+        assert!(!has_custom_sections(&module));
+        assert!(!module
+            .sections()
+            .iter()
+            .any(|section| matches!(section, Section::DataCount(_))));
+    }
+
+    #[test]
+    fn is_sysroot_failure_matches_xargo_errors() {
+        let build = r#"`CARGO_TARGET_DIR="/tmp/cargo-xbuildK3f/target" "cargo" "rustc" "-p" "alloc" "--release" "--manifest-path" "/tmp/cargo-xbuildK3f/Cargo.toml" "--target" "wasm32-unknown-unknown" "--" "-Z" "force-unstable-if-unmarked"` failed with exit code: Some(101)"#;
+        assert!(is_sysroot_failure(build));
+        let copy = "copying files from `/tmp/cargo-xbuildK3f/target/wasm32-unknown-unknown/release/deps/liballoc.rlib` to `/c/target/sysroot/lib/rustlib/wasm32-unknown-unknown/lib/liballoc.rlib` failed: No such file or directory (os error 2)";
+        assert!(is_sysroot_failure(copy));
+
+        // Failures unrelated to the sysroot, even if the sysroot is mentioned.
+        let metadata = "cargo metadata invocation failed: `cargo metadata` exited with an error: error: failed to parse manifest at `/sysroot/c/Cargo.toml`";
+        assert!(!is_sysroot_failure(metadata));
+        let rustc = r#"`"rustc" "-vV"` failed with exit code: Some(1)"#;
+        assert!(!is_sysroot_failure(rustc));
     }
-    Ok(format!(
-        "\n{}Done in {}, your project is ready now:\n{: >6}: {}\n{: >6}: {}",
-        SPARKLE,
-        HumanDuration(started.elapsed()),
-        "Binary".green().bold(),
-        dest_wasm.bold(),
-        "ABI".green().bold(),
-        dest_abi.bold(),
-    ))
 }
@@ -0,0 +1,246 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{build::BuildOptions, build_inputs::input_packages};
+use crate::{config::GlobalConfig, utils, AnalysisBehavior};
+use anyhow::{Context, Result};
+use cargo_metadata::{Metadata, PackageId};
+use std::{
+    env,
+    fmt::{Debug, Write},
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Configuration files of cargo, relative to the directories cargo looks them up in.
+const CARGO_CONFIG_FILES: [&str; 2] = [".cargo/config", ".cargo/config.toml"];
+
+/// Environment variables read by cargo or rustc which change the Wasm bytecode.
+const ENV_VARS: [&str; 6] = [
+    "RUSTFLAGS",
+    "CARGO_ENCODED_RUSTFLAGS",
+    "RUSTC",
+    "RUSTC_WRAPPER",
+    "RUSTUP_TOOLCHAIN",
+    "SOURCE_DATE_EPOCH",
+];
+
+/// Prefixes of environment variables of cargo which change the Wasm bytecode, e.g.
+/// `CARGO_PROFILE_RELEASE_LTO`.
+const ENV_VAR_PREFIXES: [&str; 3] = ["CARGO_PROFILE_", "CARGO_BUILD_", "CARGO_TARGET_"];
+
+/// Lists files the contract is built from which may be edited locally: the manifests, Rust
+/// source files and toolchain files of all path packages it depends on, including itself and
+/// other members of the workspace, the manifest and cargo configuration of the workspace, and
+/// files of the local `liquid-lang` package patched in by `--liquid-path`.
+///
+/// Packages from registries and git repositories are immutable, their versions are pinned by
+/// `Cargo.lock`, which is covered by the fingerprints instead.
+pub(crate) fn input_files(
+    metadata: &Metadata,
+    root_package_id: &PackageId,
+    liquid_path: Option<&Path>,
+) -> Result<Vec<PathBuf>> {
+    let mut files = vec![metadata.workspace_root.join("Cargo.toml")];
+    let mut config_dirs = vec![metadata.workspace_root.clone()];
+    for package in input_packages(metadata, root_package_id) {
+        if package.source.is_some() {
+            continue;
+        }
+        let package_dir = package
+            .manifest_path
+            .parent()
+            .expect("the manifest path is a file path so has a parent");
+        files.extend(utils::package_files(package_dir)?);
+        config_dirs.push(package_dir.to_path_buf());
+    }
+    if let Some(liquid_path) = liquid_path {
+        files.extend(utils::package_files(liquid_path)?);
+    }
+    for dir in config_dirs {
+        files.extend(CARGO_CONFIG_FILES.iter().map(|file| dir.join(file)));
+    }
+    Ok(files)
+}
+
+/// Returns the latest mtime of `files`, missing files are skipped.
+pub(crate) fn newest_modified(files: &[PathBuf]) -> Result<Option<SystemTime>> {
+    let mut newest = None;
+    for file in files {
+        let modified = match fs::metadata(file) {
+            Ok(metadata) => metadata
+                .modified()
+                .context(format!("Reading mtime of '{}'", file.display()))?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).context(format!("Reading metadata of '{}'", file.display()))
+            }
+        };
+        newest = newest.max(Some(modified));
+    }
+    Ok(newest)
+}
+
+/// The SHA-256 digest of the content of the file, or `missing` if it can't be read.
+fn file_digest(path: &Path) -> String {
+    fs::read(path).map_or_else(
+        |_| "missing".to_owned(),
+        |content| utils::sha256_hex(&content),
+    )
+}
+
+/// Lockfiles which pin versions of dependencies of the build, i.e. `Cargo.lock` of the workspace
+/// and the one passed by `--lockfile`.
+fn lockfiles(metadata: &Metadata, build_options: &BuildOptions) -> Vec<PathBuf> {
+    let mut lockfiles = vec![metadata.workspace_root.join("Cargo.lock")];
+    lockfiles.extend(build_options.lockfile.clone());
+    lockfiles
+}
+
+/// Appends `name=value` lines describing `fields` to `description`.
+fn describe(description: &mut String, fields: &[(&str, &dyn Debug)]) {
+    for (name, value) in fields {
+        let _ = writeln!(description, "{}={:?}", name, value);
+    }
+}
+
+/// Describes the inputs of `abi-gen` besides the files listed by `input_files`, its output is
+/// cached as long as the SHA-256 digest of the description stays the same.
+pub(crate) fn abi_fingerprint(
+    metadata: &Metadata,
+    build_options: &BuildOptions,
+    use_gm: bool,
+) -> String {
+    let mut description = String::new();
+    describe(
+        &mut description,
+        &[
+            ("gm", &use_gm),
+            ("liquid_path", &build_options.liquid_path),
+            ("manifest_overrides", &build_options.manifest_overrides),
+            (
+                "workspace_dependency_overrides",
+                &build_options.workspace_dependency_overrides,
+            ),
+            ("variant", &build_options.variant),
+        ],
+    );
+    for lockfile in lockfiles(metadata, build_options) {
+        let _ = writeln!(
+            description,
+            "{}={}",
+            lockfile.display(),
+            file_digest(&lockfile)
+        );
+    }
+    utils::sha256_hex(description.as_bytes())
+}
+
+/// Describes everything the build depends on besides the files listed by `input_files`: all
+/// options which affect the artifacts or the checks of the build, whether conflict fields are
+/// analyzed, environment variables of cargo, the toolchain, the global config, wasm-opt and the
+/// lockfiles. The build is skipped only as long as the SHA-256 digest of the description stays
+/// the same.
+pub(crate) fn build_fingerprint(
+    metadata: &Metadata,
+    build_options: &BuildOptions,
+    use_gm: bool,
+    analysis_behavior: AnalysisBehavior,
+) -> Result<String> {
+    let o = build_options;
+    let mut description = abi_fingerprint(metadata, build_options, use_gm);
+    description.push('\n');
+    describe(
+        &mut description,
+        &[
+            ("analysis_behavior", &analysis_behavior),
+            ("lockfile", &o.lockfile),
+            ("cargo_configs", &o.cargo_configs),
+            ("xargo_args", &o.xargo_args),
+            ("pedantic", &o.pedantic),
+            ("emit", &o.emit),
+            ("trim_data", &o.trim_data),
+            ("strip_unreachable", &o.strip_unreachable),
+            ("diff_artifacts", &o.diff_artifacts),
+            ("report", &o.report),
+            ("metrics", &o.metrics),
+            ("report_build_inputs", &o.report_build_inputs),
+            ("version_override", &o.version_override),
+            ("json_diagnostics", &o.json_diagnostics),
+            ("abi_baseline", &o.abi_baseline),
+            ("allow_abi_break", &o.allow_abi_break),
+            ("output_format", &o.output_format),
+            ("optimization_level", &o.optimization_level),
+            ("skip_wasm_opt", &o.skip_wasm_opt),
+            ("max_size", &o.max_size),
+            ("min_size", &o.min_size),
+            ("report_data_segments", &o.report_data_segments),
+            ("deny_floats", &o.deny_floats),
+            ("allow_start_function", &o.allow_start_function),
+            ("split_debug", &o.split_debug),
+            ("panic", &o.panic),
+            ("keep_exports", &o.keep_exports),
+            ("legacy_wasm", &o.legacy_wasm),
+            ("const_propagation", &o.const_propagation),
+            ("strip_panic_messages", &o.strip_panic_messages),
+            ("wasm_opt_path", &o.wasm_opt_path),
+            ("emit_stack_usage", &o.emit_stack_usage),
+            ("manifest_out", &o.manifest_out),
+            ("report_unused_exports", &o.report_unused_exports),
+            ("no_optimize_internal", &o.no_optimize_internal),
+            ("skip_post_process", &o.skip_post_process),
+            ("preserve_names", &o.preserve_names),
+            ("no_strip_sections", &o.no_strip_sections),
+            ("inject_gas", &o.inject_gas),
+            ("stack_limit", &o.stack_limit),
+            ("name_template", &o.name_template),
+        ],
+    );
+
+    for var in ENV_VARS.iter() {
+        let _ = writeln!(description, "{}={:?}", var, env::var_os(var));
+    }
+    let mut prefixed_vars = env::vars_os()
+        .filter(|(var, _)| {
+            let var = var.to_string_lossy();
+            ENV_VAR_PREFIXES
+                .iter()
+                .any(|prefix| var.starts_with(prefix))
+        })
+        .collect::<Vec<_>>();
+    prefixed_vars.sort();
+    for (var, value) in prefixed_vars {
+        let _ = writeln!(description, "{:?}={:?}", var, value);
+    }
+
+    let rustc = rustc_version::version_meta().context("Querying the version of rustc")?;
+    let _ = writeln!(description, "rustc={}", rustc.short_version_string);
+    if let Some(config) = GlobalConfig::path() {
+        let _ = writeln!(description, "config={}", file_digest(&config));
+    }
+    // wasm-opt found in `PATH` may be upgraded in place, a configured one is covered by the
+    // options and the global config.
+    if let Ok(wasm_opt) = which::which("wasm-opt") {
+        let modified = fs::metadata(&wasm_opt)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok());
+        let _ = writeln!(
+            description,
+            "wasm-opt={} {:?}",
+            wasm_opt.display(),
+            modified
+        );
+    }
+    Ok(utils::sha256_hex(description.as_bytes()))
+}
@@ -0,0 +1,128 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::utils;
+use anyhow::{Context, Result};
+use cargo_metadata::{DependencyKind, Metadata, Package, PackageId};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, VecDeque},
+    fs,
+    path::Path,
+};
+
+/// A source file which the build depends on.
+#[derive(Serialize)]
+struct BuildInput {
+    path: String,
+    sha256: String,
+}
+
+/// Packages the root package depends on transitively, including itself. Dev dependencies are
+/// skipped since they're not built, build dependencies are kept since they run during the build.
+pub(crate) fn input_packages<'a>(
+    metadata: &'a Metadata,
+    root_package_id: &'a PackageId,
+) -> Vec<&'a Package> {
+    let nodes = metadata
+        .resolve
+        .as_ref()
+        .map_or(&[][..], |resolve| resolve.nodes.as_slice());
+    let mut visited = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(root_package_id);
+    visited.insert(root_package_id);
+    while let Some(id) = queue.pop_front() {
+        let deps = nodes
+            .iter()
+            .find(|node| &node.id == id)
+            .map_or(&[][..], |node| node.deps.as_slice());
+        for dep in deps {
+            let built = dep.dep_kinds.is_empty()
+                || dep
+                    .dep_kinds
+                    .iter()
+                    .any(|info| info.kind != DependencyKind::Development);
+            if built && visited.insert(&dep.pkg) {
+                queue.push_back(&dep.pkg);
+            }
+        }
+    }
+    visited.into_iter().map(|id| &metadata[id]).collect()
+}
+
+/// Hashes every `*.rs` file of the packages the contract is built from with SHA-256, and writes
+/// them to `inputs_path`.
+///
+/// Paths of files in the workspace are relative to the workspace root, files of other packages,
+/// e.g. in the registry or the vendor directory, are prefixed by `<name>-<version>` instead, so
+/// that the record doesn't depend on where the build happens.
+pub(crate) fn write_build_inputs(
+    metadata: &Metadata,
+    root_package_id: &PackageId,
+    inputs_path: &Path,
+) -> Result<()> {
+    let mut inputs = Vec::new();
+    for package in input_packages(metadata, root_package_id) {
+        let package_dir = package
+            .manifest_path
+            .parent()
+            .expect("the manifest path is a file path so has a parent");
+        let in_workspace = package_dir.starts_with(&metadata.workspace_root);
+        for file in utils::rust_source_files(package_dir)? {
+            let content = fs::read(&file).context(format!("Reading '{}'", file.display()))?;
+            let path = if in_workspace {
+                file.strip_prefix(&metadata.workspace_root)
+                    .expect("the file is in the workspace")
+                    .display()
+                    .to_string()
+            } else {
+                Path::new(&format!("{}-{}", package.name, package.version))
+                    .join(
+                        file.strip_prefix(package_dir)
+                            .expect("the file is in the package"),
+                    )
+                    .display()
+                    .to_string()
+            };
+            inputs.push(BuildInput {
+                path: path.replace('\\', "/"),
+                sha256: utils::sha256_hex(&content),
+            });
+        }
+    }
+    fs::write(inputs_path, serde_json::to_string_pretty(&inputs)?)
+        .context(format!("Writing '{}'", inputs_path.display()))
+}
+
+/// Records the hashes of the build inputs and of the Wasm bytecode built from them in
+/// `lock_path`, which ties the artifact to the exact sources.
+pub(crate) fn write_build_lock(
+    inputs_path: &Path,
+    dest_wasm: &Path,
+    lock_path: &Path,
+) -> Result<()> {
+    let inputs = fs::read(inputs_path).context(format!("Reading '{}'", inputs_path.display()))?;
+    let wasm = fs::read(dest_wasm).context(format!("Reading '{}'", dest_wasm.display()))?;
+    let lock = serde_json::json!({
+        "inputs": {
+            "path": inputs_path.file_name().map(|name| name.to_string_lossy()),
+            "sha256": utils::sha256_hex(&inputs),
+        },
+        "wasm": {
+            "path": dest_wasm.file_name().map(|name| name.to_string_lossy()),
+            "sha256": utils::sha256_hex(&wasm),
+        },
+    });
+    fs::write(lock_path, serde_json::to_string_pretty(&lock)?)
+        .context(format!("Writing '{}'", lock_path.display()))
+}
@@ -0,0 +1,117 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::diff::{exports, imports, section_sizes};
+use anyhow::{Context, Result};
+use std::{fmt::Write as _, fs, path::Path, process::Command};
+
+/// What the build pipeline found out about the contract, which the report is assembled from.
+pub(crate) struct BuildReport<'a> {
+    pub package_name: &'a str,
+    pub version: &'a str,
+    pub dest_wasm: &'a Path,
+    /// The hash of the Wasm bytecode in hex, as written to the hash artifact.
+    pub hash: &'a str,
+    /// Sizes of the Wasm bytecode in bytes before and after being optimized by wasm-opt.
+    pub optimization_sizes: Option<(u64, u64)>,
+    /// The directory of the package, where git is asked about the commit the build is from.
+    pub package_dir: &'a Path,
+}
+
+/// Runs git in `dir`, returns the trimmed output or `None` if git failed, e.g. it's not a repository.
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())?;
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_owned())
+}
+
+impl BuildReport<'_> {
+    fn to_markdown(&self) -> Result<String> {
+        let wasm = fs::read(self.dest_wasm)
+            .context(format!("Reading Wasm file '{}'", self.dest_wasm.display()))?;
+        let module = parity_wasm::deserialize_buffer(&wasm)
+            .context(format!("Loading Wasm file '{}'", self.dest_wasm.display()))?;
+
+        let mut report = String::new();
+        writeln!(
+            report,
+            "# Build report of `{}` {}",
+            self.package_name, self.version
+        )?;
+        writeln!(report)?;
+        writeln!(report, "| | |")?;
+        writeln!(report, "|---|---|")?;
+        writeln!(report, "| Wasm | `{}` |", self.dest_wasm.display())?;
+        writeln!(report, "| Size | {} bytes |", wasm.len())?;
+        if let Some((original_size, optimized_size)) = self.optimization_sizes {
+            writeln!(
+                report,
+                "| wasm-opt | {} bytes -> {} bytes |",
+                original_size, optimized_size
+            )?;
+        }
+        writeln!(report, "| Hash | `{}` |", self.hash)?;
+        match git(self.package_dir, &["rev-parse", "HEAD"]) {
+            Some(commit) => {
+                let dirty = git(self.package_dir, &["status", "--porcelain"])
+                    .map(|status| !status.is_empty())
+                    .unwrap_or(false);
+                writeln!(
+                    report,
+                    "| Commit | `{}`{} |",
+                    commit,
+                    if dirty {
+                        " (with uncommitted changes)"
+                    } else {
+                        ""
+                    }
+                )?;
+            }
+            None => writeln!(report, "| Commit | not a git repository |")?,
+        }
+        writeln!(report, "| cargo-liquid | {} |", env!("CARGO_PKG_VERSION"))?;
+
+        writeln!(report)?;
+        writeln!(report, "## Sections")?;
+        writeln!(report)?;
+        writeln!(report, "| Section | Size (bytes) |")?;
+        writeln!(report, "|---|---:|")?;
+        for (name, size) in section_sizes(&module)? {
+            writeln!(report, "| {} | {} |", name, size)?;
+        }
+
+        for (title, entries) in
+            [("Exports", exports(&module)), ("Imports", imports(&module))].iter()
+        {
+            writeln!(report)?;
+            writeln!(report, "## {}", title)?;
+            writeln!(report)?;
+            if entries.is_empty() {
+                writeln!(report, "None")?;
+            }
+            for entry in entries {
+                writeln!(report, "- `{}`", entry)?;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Writes the report in Markdown to `path`.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_markdown()?)
+            .context(format!("Writing build report '{}'", path.display()))
+    }
+}
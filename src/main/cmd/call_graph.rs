@@ -0,0 +1,646 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::prof::function_names;
+use parity_wasm::elements::{
+    GlobalType, ImportCountType, IndexMap, Instruction, Internal, Module, Section, Type, ValueType,
+};
+use std::{cmp::Reverse, collections::BTreeSet, mem};
+
+/// How many of the deepest call chains are reported.
+const REPORTED_CHAINS: usize = 10;
+
+/// A chain of calls starting from an entry point of the module, i.e. an exported function or a
+/// function which can be called indirectly.
+pub(crate) struct CallChain {
+    pub functions: Vec<String>,
+    /// Estimated stack usage in bytes of all functions of the chain.
+    pub stack_usage: u64,
+}
+
+pub(crate) struct StackUsage {
+    /// The deepest call chains, sorted by depth in descending order.
+    pub deepest_chains: Vec<CallChain>,
+    /// Estimated peak stack usage in bytes among all call chains.
+    pub peak: u64,
+    /// Functions which are part of a recursion, whose stack usage can't be bounded statically.
+    pub recursive: Vec<String>,
+}
+
+fn value_size(ty: ValueType) -> u64 {
+    match ty {
+        ValueType::I32 | ValueType::F32 => 4,
+        _ => 8,
+    }
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Unvisited,
+    Visiting,
+    Visited,
+}
+
+/// The call graph of the module, in which imported functions have no callees and no frames.
+struct CallGraph {
+    callees: Vec<BTreeSet<u32>>,
+    frames: Vec<u64>,
+    /// Whether the function contains `call_indirect`.
+    calls_indirect: Vec<bool>,
+}
+
+impl CallGraph {
+    fn new(module: &Module) -> Self {
+        let imported = module.import_count(ImportCountType::Function);
+        let types = module
+            .type_section()
+            .map_or(&[][..], |section| section.types());
+        let functions = module
+            .function_section()
+            .map_or(&[][..], |section| section.entries());
+        let bodies = module
+            .code_section()
+            .map_or(&[][..], |section| section.bodies());
+        let stack_pointer = stack_pointer(module);
+
+        let mut callees = vec![BTreeSet::new(); imported];
+        let mut frames = vec![0; imported];
+        let mut calls_indirect = vec![false; imported];
+        for (func, body) in functions.iter().zip(bodies) {
+            let params = match types.get(func.type_ref() as usize) {
+                Some(Type::Function(ty)) => ty.params().iter().copied().map(value_size).sum(),
+                None => 0,
+            };
+            let locals: u64 = body
+                .locals()
+                .iter()
+                .map(|local| local.count() as u64 * value_size(local.value_type()))
+                .sum();
+            let instructions = body.code().elements();
+            // Rust allocates the frame on the shadow stack by `global.get $sp; i32.const N;
+            // i32.sub` in the prologue of the function.
+            let shadow_frame = match (stack_pointer, instructions) {
+                (
+                    Some(stack_pointer),
+                    [Instruction::GetGlobal(global), Instruction::I32Const(size), Instruction::I32Sub, ..],
+                ) if *global == stack_pointer && *size > 0 => *size as u64,
+                _ => 0,
+            };
+            frames.push(params + locals + shadow_frame);
+            calls_indirect.push(
+                instructions
+                    .iter()
+                    .any(|instruction| matches!(instruction, Instruction::CallIndirect(..))),
+            );
+            callees.push(
+                instructions
+                    .iter()
+                    .filter_map(|instruction| match instruction {
+                        Instruction::Call(callee) => Some(*callee),
+                        _ => None,
+                    })
+                    .collect(),
+            );
+        }
+
+        CallGraph {
+            callees,
+            frames,
+            calls_indirect,
+        }
+    }
+
+    /// Collects all functions reachable from `roots`. Once a function calling indirectly is
+    /// reached, all functions in tables are regarded as reachable.
+    fn reachable(
+        &self,
+        roots: impl IntoIterator<Item = u32>,
+        tables: &BTreeSet<u32>,
+    ) -> BTreeSet<u32> {
+        let mut reachable = BTreeSet::new();
+        let mut pending = roots.into_iter().collect::<Vec<_>>();
+        let mut tables_reached = false;
+        while let Some(func) = pending.pop() {
+            if !reachable.insert(func) {
+                continue;
+            }
+            let idx = func as usize;
+            if let Some(callees) = self.callees.get(idx) {
+                pending.extend(callees.iter().copied());
+            }
+            if !tables_reached && self.calls_indirect.get(idx).copied().unwrap_or_default() {
+                tables_reached = true;
+                pending.extend(tables.iter().copied());
+            }
+        }
+        reachable
+    }
+}
+
+/// Guesses the global used as the shadow stack pointer, which is the first mutable `i32` global
+/// defined by the module.
+fn stack_pointer(module: &Module) -> Option<u32> {
+    let imported = module.import_count(ImportCountType::Global) as u32;
+    module.global_section().and_then(|section| {
+        section
+            .entries()
+            .iter()
+            .position(|global| {
+                let ty: &GlobalType = global.global_type();
+                ty.is_mutable() && ty.content_type() == ValueType::I32
+            })
+            .map(|idx| imported + idx as u32)
+    })
+}
+
+/// Computes the longest path starting from every function, weighted by `weight`. Edges closing a
+/// cycle are ignored and the functions involved are collected in `recursive`.
+struct LongestPath<'a> {
+    graph: &'a CallGraph,
+    weight: &'a dyn Fn(u32) -> u64,
+    states: Vec<State>,
+    /// The total weight of the longest path and the next function of it.
+    paths: Vec<(u64, Option<u32>)>,
+    recursive: BTreeSet<u32>,
+}
+
+impl<'a> LongestPath<'a> {
+    fn new(graph: &'a CallGraph, weight: &'a dyn Fn(u32) -> u64) -> Self {
+        let len = graph.frames.len();
+        LongestPath {
+            graph,
+            weight,
+            states: vec![State::Unvisited; len],
+            paths: vec![(0, None); len],
+            recursive: BTreeSet::new(),
+        }
+    }
+
+    fn visit(&mut self, func: u32) -> u64 {
+        let idx = func as usize;
+        match self.states.get(idx) {
+            Some(State::Visited) => return self.paths[idx].0,
+            Some(State::Visiting) => {
+                self.recursive.insert(func);
+                return 0;
+            }
+            Some(State::Unvisited) => (),
+            None => return 0,
+        }
+
+        self.states[idx] = State::Visiting;
+        let mut longest = (0, None);
+        for callee in self.graph.callees[idx].clone() {
+            let total = self.visit(callee);
+            if longest.1.is_none() || total > longest.0 {
+                longest = (total, Some(callee));
+            }
+        }
+        self.states[idx] = State::Visited;
+        self.paths[idx] = (longest.0 + (self.weight)(func), longest.1);
+        self.paths[idx].0
+    }
+
+    fn chain(&self, func: u32) -> Vec<u32> {
+        let mut chain = vec![func];
+        let mut visited = BTreeSet::new();
+        while let Some(next) = self.paths[*chain.last().unwrap() as usize].1 {
+            if !visited.insert(next) {
+                break;
+            }
+            chain.push(next);
+        }
+        chain
+    }
+}
+
+/// Functions which can be called indirectly, i.e. the functions in tables.
+fn table_functions(module: &Module) -> BTreeSet<u32> {
+    module
+        .elements_section()
+        .into_iter()
+        .flat_map(|section| section.entries())
+        .flat_map(|segment| segment.members().iter().copied())
+        .collect()
+}
+
+/// Entry points of the module, i.e. the exported functions and the functions in tables.
+fn entry_points(module: &Module) -> BTreeSet<u32> {
+    module
+        .export_section()
+        .into_iter()
+        .flat_map(|section| section.entries())
+        .filter_map(|entry| match entry.internal() {
+            Internal::Function(idx) => Some(*idx),
+            _ => None,
+        })
+        .chain(table_functions(module))
+        .collect()
+}
+
+/// An export which is neither an entry point of the contract nor required by the chain.
+pub(crate) struct UnusedExport {
+    pub name: String,
+    /// Whether the exported function is also reachable from the entry points, in which case
+    /// removing the export doesn't remove the function itself.
+    pub reachable: bool,
+}
+
+/// Finds exports which are neither in `entry_points`, in `required`, nor memories or tables, which
+/// are used to initialize the instance.
+pub(crate) fn unused_exports(
+    module: &Module,
+    entry_points: &[&str],
+    required: &[&str],
+) -> Vec<UnusedExport> {
+    let exports = module
+        .export_section()
+        .map_or(&[][..], |section| section.entries());
+    let roots = exports
+        .iter()
+        .filter(|entry| entry_points.contains(&entry.field()))
+        .filter_map(|entry| match entry.internal() {
+            Internal::Function(idx) => Some(*idx),
+            _ => None,
+        })
+        .chain(module.start_section());
+    let reachable = CallGraph::new(module).reachable(roots, &table_functions(module));
+
+    exports
+        .iter()
+        .filter(|entry| {
+            !entry_points.contains(&entry.field()) && !required.contains(&entry.field())
+        })
+        .filter_map(|entry| match entry.internal() {
+            Internal::Function(idx) => Some(UnusedExport {
+                name: entry.field().to_owned(),
+                reachable: reachable.contains(idx),
+            }),
+            Internal::Global(_) => Some(UnusedExport {
+                name: entry.field().to_owned(),
+                reachable: false,
+            }),
+            Internal::Memory(_) | Internal::Table(_) => None,
+        })
+        .collect()
+}
+
+/// Exports and functions removed by [`strip_unreachable_exports`].
+pub(crate) struct StrippedItems {
+    pub exports: Vec<String>,
+    pub functions: usize,
+}
+
+/// Moves entries of the map to their new indices, entries whose index is mapped to `None` are
+/// dropped.
+fn remap_indices<T>(map: &mut IndexMap<T>, new_index: impl Fn(u32) -> Option<u32>) {
+    let old = mem::replace(map, IndexMap::with_capacity(0));
+    for (idx, value) in old {
+        if let Some(idx) = new_index(idx) {
+            map.insert(idx, value);
+        }
+    }
+}
+
+/// Computes the functions reachable from `entry_points` and removes exports of functions which
+/// are not, along with exports of globals which are not in `entry_points`, then removes the
+/// definitions of the unreachable functions. Memories and tables are always kept, since they're
+/// used to initialize the instance, and so are functions in tables.
+///
+/// Function indices are updated everywhere, including the name section, which is parsed
+/// beforehand. An unparsable name section is removed as it would refer to wrong functions.
+pub(crate) fn strip_unreachable_exports(
+    module: &mut Module,
+    entry_points: &[&str],
+) -> StrippedItems {
+    let exports = module
+        .export_section()
+        .map_or(&[][..], |section| section.entries());
+    let tables = table_functions(module);
+    let roots = exports
+        .iter()
+        .filter(|entry| entry_points.contains(&entry.field()))
+        .filter_map(|entry| match entry.internal() {
+            Internal::Function(idx) => Some(*idx),
+            _ => None,
+        })
+        .chain(module.start_section());
+    let mut live = CallGraph::new(module).reachable(roots, &tables);
+    live.extend(&tables);
+
+    let mut stripped = StrippedItems {
+        exports: Vec::new(),
+        functions: 0,
+    };
+    if let Some(section) = module.export_section_mut() {
+        section.entries_mut().retain(|entry| {
+            let keep = match entry.internal() {
+                Internal::Function(idx) => live.contains(idx),
+                Internal::Global(_) => entry_points.contains(&entry.field()),
+                Internal::Memory(_) | Internal::Table(_) => true,
+            };
+            if !keep {
+                stripped.exports.push(entry.field().to_owned());
+            }
+            keep
+        });
+    }
+
+    // Imported functions are never removed, so indices of defined functions start after them.
+    let imported = module.import_count(ImportCountType::Function) as u32;
+    let defined = module
+        .function_section()
+        .map_or(0, |section| section.entries().len()) as u32;
+    let mut new_indices = (0..imported).map(Some).collect::<Vec<_>>();
+    let mut next = imported;
+    for idx in imported..imported + defined {
+        if live.contains(&idx) {
+            new_indices.push(Some(next));
+            next += 1;
+        } else {
+            new_indices.push(None);
+            stripped.functions += 1;
+        }
+    }
+    if stripped.functions == 0 {
+        return stripped;
+    }
+    let new_index = |idx: u32| new_indices.get(idx as usize).copied().flatten();
+    let is_live = |idx: u32| new_index(idx + imported).is_some();
+
+    *module = mem::take(module)
+        .parse_names()
+        .unwrap_or_else(|(_, module)| module);
+    for section in module.sections_mut() {
+        match section {
+            Section::Function(section) => {
+                let mut idx = 0;
+                section.entries_mut().retain(|_| {
+                    idx += 1;
+                    is_live(idx - 1)
+                });
+            }
+            Section::Code(section) => {
+                let mut idx = 0;
+                section.bodies_mut().retain(|_| {
+                    idx += 1;
+                    is_live(idx - 1)
+                });
+                for body in section.bodies_mut() {
+                    for instruction in body.code_mut().elements_mut() {
+                        if let Instruction::Call(callee) = instruction {
+                            // Callees of live functions are live as well.
+                            *callee =
+                                new_index(*callee).expect("callees of live functions are live");
+                        }
+                    }
+                }
+            }
+            Section::Export(section) => {
+                for entry in section.entries_mut() {
+                    if let Internal::Function(idx) = entry.internal_mut() {
+                        *idx = new_index(*idx).expect("unreachable exports are removed");
+                    }
+                }
+            }
+            Section::Element(section) => {
+                for segment in section.entries_mut() {
+                    for member in segment.members_mut() {
+                        *member = new_index(*member).expect("functions in tables are kept");
+                    }
+                }
+            }
+            Section::Start(start) => {
+                *start = new_index(*start).expect("the start function is a root");
+            }
+            Section::Name(names) => {
+                if let Some(functions) = names.functions_mut() {
+                    remap_indices(functions.names_mut(), new_index);
+                }
+                if let Some(locals) = names.locals_mut() {
+                    remap_indices(locals.local_names_mut(), new_index);
+                }
+            }
+            _ => (),
+        }
+    }
+    module
+        .sections_mut()
+        .retain(|section| !matches!(section, Section::Custom(section) if section.name() == "name"));
+    stripped
+}
+
+/// Estimates the stack usage of the module by traversing its call graph from the entry points.
+///
+/// The frame of a function is estimated by the sizes of its parameters and local variables, along
+/// with the space it allocates on the shadow stack. Indirect calls are not followed.
+pub(crate) fn analyze_stack_usage(module: &Module) -> StackUsage {
+    let names = function_names(module);
+    let name = |idx: u32| {
+        names
+            .get(&idx)
+            .cloned()
+            .unwrap_or_else(|| format!("func[{}]", idx))
+    };
+    let graph = CallGraph::new(module);
+    let entry_points = entry_points(module);
+
+    let depth = |_: u32| 1;
+    let mut deepest = LongestPath::new(&graph, &depth);
+    let frame = |func: u32| graph.frames.get(func as usize).copied().unwrap_or_default();
+    let mut heaviest = LongestPath::new(&graph, &frame);
+    let mut peak = 0;
+    for func in &entry_points {
+        deepest.visit(*func);
+        peak = peak.max(heaviest.visit(*func));
+    }
+
+    let mut chains = entry_points
+        .iter()
+        .map(|func| deepest.chain(*func))
+        .collect::<Vec<_>>();
+    chains.sort_by_key(|chain| Reverse(chain.len()));
+    let deepest_chains = chains
+        .into_iter()
+        .take(REPORTED_CHAINS)
+        .map(|chain| CallChain {
+            stack_usage: chain.iter().map(|func| frame(*func)).sum(),
+            functions: chain.into_iter().map(name).collect(),
+        })
+        .collect();
+
+    let recursive = deepest.recursive.iter().copied().map(name).collect();
+    StackUsage {
+        deepest_chains,
+        peak,
+        recursive,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::{MockEnvironment, CALL_ENTRY_POINT};
+    use parity_wasm::elements::{
+        CodeSection, ElementSection, ElementSegment, ExportEntry, ExportSection, External, Func,
+        FuncBody, FunctionNameSubsection, FunctionSection, FunctionType, ImportEntry,
+        ImportSection, InitExpr, Instructions, Local, LocalNameSubsection, MemorySection,
+        MemoryType, NameSection, TableSection, TableType, TypeSection,
+    };
+
+    /// Names of the functions in [`module`], indexed by their original indices.
+    const NAMES: [&str; 8] = [
+        "host", "main", "dead", "helper", "in_table", "start", "init", "orphan",
+    ];
+
+    fn body(code: Vec<Instruction>) -> FuncBody {
+        FuncBody::new(Vec::new(), Instructions::new(code))
+    }
+
+    /// A contract importing `env.host` as function 0, in which `main` calls `helper` calling the
+    /// import, `in_table` is only referenced by the table, `start` is the start function calling
+    /// `init`, while `dead` is exported but not an entry point and `orphan` is never referenced.
+    /// All functions are named in a serialized name section.
+    fn module() -> Module {
+        let mut function_names = FunctionNameSubsection::default();
+        for (idx, name) in NAMES.iter().enumerate() {
+            function_names
+                .names_mut()
+                .insert(idx as u32, (*name).to_owned());
+        }
+        let mut local_names = LocalNameSubsection::default();
+        let mut helper_locals = IndexMap::default();
+        helper_locals.insert(0, "tmp".to_owned());
+        local_names.local_names_mut().insert(3, helper_locals);
+
+        let module = Module::new(vec![
+            Section::Type(TypeSection::with_types(vec![Type::Function(
+                FunctionType::new(Vec::new(), Vec::new()),
+            )])),
+            Section::Import(ImportSection::with_entries(vec![ImportEntry::new(
+                "env".to_owned(),
+                "host".to_owned(),
+                External::Function(0),
+            )])),
+            Section::Function(FunctionSection::with_entries(vec![Func::new(0); 7])),
+            Section::Table(TableSection::with_entries(vec![TableType::new(1, None)])),
+            Section::Memory(MemorySection::with_entries(vec![MemoryType::new(1, None)])),
+            Section::Export(ExportSection::with_entries(vec![
+                ExportEntry::new(CALL_ENTRY_POINT.to_owned(), Internal::Function(1)),
+                ExportEntry::new("dead".to_owned(), Internal::Function(2)),
+                ExportEntry::new("memory".to_owned(), Internal::Memory(0)),
+            ])),
+            Section::Start(5),
+            Section::Element(ElementSection::with_entries(vec![ElementSegment::new(
+                0,
+                Some(InitExpr::new(vec![
+                    Instruction::I32Const(0),
+                    Instruction::End,
+                ])),
+                vec![4],
+            )])),
+            Section::Code(CodeSection::with_bodies(vec![
+                body(vec![Instruction::Call(3), Instruction::End]),
+                body(vec![Instruction::Call(0), Instruction::End]),
+                FuncBody::new(
+                    vec![Local::new(1, ValueType::I32)],
+                    Instructions::new(vec![Instruction::Call(0), Instruction::End]),
+                ),
+                body(vec![Instruction::End]),
+                body(vec![Instruction::Call(6), Instruction::End]),
+                body(vec![Instruction::End]),
+                body(vec![Instruction::Call(2), Instruction::End]),
+            ])),
+            Section::Name(NameSection::new(
+                None,
+                Some(function_names),
+                Some(local_names),
+            )),
+        ]);
+        // Round trip the module so the name section is a custom section, like in built contracts.
+        parity_wasm::deserialize_buffer(&parity_wasm::serialize(module).unwrap()).unwrap()
+    }
+
+    fn calls(module: &Module, func: u32) -> Vec<u32> {
+        let imported = module.import_count(ImportCountType::Function) as u32;
+        module.code_section().unwrap().bodies()[(func - imported) as usize]
+            .code()
+            .elements()
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Call(callee) => Some(*callee),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn strip_unreachable_exports_keeps_imports_tables_and_start_function() {
+        let mut module = module();
+        let stripped = strip_unreachable_exports(&mut module, &[CALL_ENTRY_POINT]);
+
+        assert_eq!(stripped.exports, ["dead"]);
+        assert_eq!(stripped.functions, 2);
+        assert_eq!(module.import_count(ImportCountType::Function), 1);
+        assert_eq!(module.function_section().unwrap().entries().len(), 5);
+        assert_eq!(module.code_section().unwrap().bodies().len(), 5);
+
+        let exports = module.export_section().unwrap().entries();
+        assert_eq!(exports.len(), 2);
+        assert_eq!(exports[0].internal(), &Internal::Function(1));
+        assert_eq!(exports[1].internal(), &Internal::Memory(0));
+        assert_eq!(calls(&module, 1), [2]);
+        assert_eq!(calls(&module, 2), [0]);
+        assert_eq!(
+            module.elements_section().unwrap().entries()[0].members(),
+            [3]
+        );
+        assert_eq!(module.start_section(), Some(4));
+        assert_eq!(calls(&module, 4), [5]);
+    }
+
+    #[test]
+    fn strip_unreachable_exports_remaps_the_name_section() {
+        let mut module = module();
+        strip_unreachable_exports(&mut module, &[CALL_ENTRY_POINT]);
+
+        let names = module.names_section().expect("the name section is kept");
+        let functions = names.functions().unwrap().names();
+        let remaining = (0..6)
+            .map(|idx| functions.get(idx).map(String::as_str))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            remaining,
+            ["host", "main", "helper", "in_table", "start", "init"]
+                .iter()
+                .copied()
+                .map(Some)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(functions.iter().count(), 6);
+        let locals = names.locals().unwrap().local_names();
+        assert_eq!(locals.iter().count(), 1);
+        assert_eq!(locals.get(2).unwrap().get(0).unwrap(), "tmp");
+    }
+
+    #[test]
+    fn stripped_module_is_instantiable() {
+        let mut module = module();
+        strip_unreachable_exports(&mut module, &[CALL_ENTRY_POINT]);
+        let module: Module =
+            parity_wasm::deserialize_buffer(&parity_wasm::serialize(module).unwrap()).unwrap();
+
+        let mut env = MockEnvironment::new(Vec::new());
+        let instance = env.instantiate(module).unwrap();
+        env.invoke(&instance, CALL_ENTRY_POINT).unwrap();
+    }
+}
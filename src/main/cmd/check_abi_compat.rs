@@ -0,0 +1,482 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::build::get_name_and_selector;
+use crate::utils;
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{convert::TryInto, error, fmt, fs, path::Path};
+
+/// The length of an address in bytes.
+const ADDRESS_LENGTH: usize = 20;
+
+/// A function call which the call data was decoded as.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct AbiMatch {
+    pub function_name: String,
+    /// Decoded arguments in the order of the ABI.
+    pub decoded_args: Vec<DecodedArg>,
+}
+
+/// An argument of the call. Integers wider than 64 bits are strings, bytes are hex strings.
+#[derive(Serialize)]
+pub(crate) struct DecodedArg {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub ty: String,
+    pub value: Value,
+}
+
+/// Why the call data doesn't encode a valid call of a function in the ABI.
+#[derive(Debug)]
+pub(crate) enum AbiDecodeError {
+    /// The call data is shorter than a selector.
+    MissingSelector,
+    /// No function in the ABI has the selector.
+    UnknownSelector(u32),
+    /// The call data ends before the argument is decoded completely.
+    UnexpectedEnd { arg: String },
+    /// The argument has an invalid value of its type, e.g. a `bool` which is neither 0 nor 1.
+    InvalidValue { arg: String, reason: String },
+    /// The type of the argument is not supported by Liquid.
+    UnsupportedType { arg: String, ty: String },
+    /// Bytes are left after all arguments are decoded.
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for AbiDecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AbiDecodeError::MissingSelector => {
+                write!(f, "the call data is shorter than a 4-byte selector")
+            }
+            AbiDecodeError::UnknownSelector(selector) => write!(
+                f,
+                "no function in the ABI has the selector 0x{}",
+                utils::encode_hex(&selector.to_le_bytes())
+            ),
+            AbiDecodeError::UnexpectedEnd { arg } => {
+                write!(f, "the call data ends before argument `{}` is decoded", arg)
+            }
+            AbiDecodeError::InvalidValue { arg, reason } => {
+                write!(f, "argument `{}` is invalid: {}", arg, reason)
+            }
+            AbiDecodeError::UnsupportedType { arg, ty } => {
+                write!(f, "argument `{}` has unsupported type `{}`", arg, ty)
+            }
+            AbiDecodeError::TrailingBytes(len) => {
+                write!(f, "{} bytes are left after all arguments are decoded", len)
+            }
+        }
+    }
+}
+
+impl error::Error for AbiDecodeError {}
+
+/// Decodes arguments in the SCALE encoding, which contracts written in Liquid use for call data.
+struct Decoder<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, len: usize, arg: &str) -> Result<&'a [u8], AbiDecodeError> {
+        if self.input.len() < len {
+            return Err(AbiDecodeError::UnexpectedEnd {
+                arg: arg.to_owned(),
+            });
+        }
+        let (taken, rest) = self.input.split_at(len);
+        self.input = rest;
+        Ok(taken)
+    }
+
+    /// Decodes an integer in the compact encoding.
+    fn compact_integer(&mut self, arg: &str) -> Result<u64, AbiDecodeError> {
+        let first = self.take(1, arg)?[0];
+        Ok(match first & 0b11 {
+            0b00 => (first >> 2) as u64,
+            0b01 => (u16::from_le_bytes([first, self.take(1, arg)?[0]]) >> 2) as u64,
+            0b10 => {
+                let mut bytes = [first, 0, 0, 0];
+                bytes[1..].copy_from_slice(self.take(3, arg)?);
+                (u32::from_le_bytes(bytes) >> 2) as u64
+            }
+            _ => {
+                let len = (first >> 2) as usize + 4;
+                let bytes = self.take(len, arg)?;
+                if len > 8 {
+                    return Err(AbiDecodeError::InvalidValue {
+                        arg: arg.to_owned(),
+                        reason: "the length is too large".to_owned(),
+                    });
+                }
+                let mut buf = [0u8; 8];
+                buf[..len].copy_from_slice(bytes);
+                u64::from_le_bytes(buf)
+            }
+        })
+    }
+
+    /// Decodes a length prefix in the compact encoding.
+    fn compact(&mut self, arg: &str) -> Result<usize, AbiDecodeError> {
+        let len = self.compact_integer(arg)?;
+        // Every element takes at least one byte, so a longer length can't be valid.
+        if len > self.input.len() as u64 {
+            return Err(AbiDecodeError::UnexpectedEnd {
+                arg: arg.to_owned(),
+            });
+        }
+        Ok(len as usize)
+    }
+
+    fn integer(&mut self, bits: usize, signed: bool, arg: &str) -> Result<Value, AbiDecodeError> {
+        let bytes = self.take(bits / 8, arg)?;
+        if bits <= 128 {
+            let negative = signed && bytes[bytes.len() - 1] & 0x80 != 0;
+            let mut buf = [if negative { 0xff } else { 0 }; 16];
+            buf[..bytes.len()].copy_from_slice(bytes);
+            return Ok(match (signed, bits <= 64) {
+                (false, true) => Value::from(u128::from_le_bytes(buf) as u64),
+                (true, true) => Value::from(i128::from_le_bytes(buf) as i64),
+                (false, false) => Value::from(u128::from_le_bytes(buf).to_string()),
+                (true, false) => Value::from(i128::from_le_bytes(buf).to_string()),
+            });
+        }
+        // Wider integers are shown as they are, in big-endian hex.
+        let mut be = bytes.to_vec();
+        be.reverse();
+        Ok(Value::from(format!("0x{}", utils::encode_hex(&be))))
+    }
+
+    fn decode(
+        &mut self,
+        ty: &str,
+        components: &[Value],
+        arg: &str,
+    ) -> Result<Value, AbiDecodeError> {
+        let unsupported = || AbiDecodeError::UnsupportedType {
+            arg: arg.to_owned(),
+            ty: ty.to_owned(),
+        };
+        // Arrays are decoded element by element, the last suffix is the outermost dimension.
+        if let Some(start) = ty.strip_suffix(']').and_then(|ty| ty.rfind('[')) {
+            let elem_ty = &ty[..start];
+            let len = match &ty[start + 1..ty.len() - 1] {
+                "" => self.compact(arg)?,
+                len => len.parse::<usize>().map_err(|_| unsupported())?,
+            };
+            return (0..len)
+                .map(|idx| self.decode(elem_ty, components, &format!("{}[{}]", arg, idx)))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array);
+        }
+        match ty {
+            "bool" => match self.take(1, arg)?[0] {
+                0 => Ok(Value::Bool(false)),
+                1 => Ok(Value::Bool(true)),
+                byte => Err(AbiDecodeError::InvalidValue {
+                    arg: arg.to_owned(),
+                    reason: format!("`{}` is not a valid bool", byte),
+                }),
+            },
+            "string" => {
+                let len = self.compact(arg)?;
+                let bytes = self.take(len, arg)?;
+                String::from_utf8(bytes.to_vec())
+                    .map(Value::String)
+                    .map_err(|_| AbiDecodeError::InvalidValue {
+                        arg: arg.to_owned(),
+                        reason: "the string is not UTF-8".to_owned(),
+                    })
+            }
+            "bytes" => {
+                let len = self.compact(arg)?;
+                Ok(Value::from(format!(
+                    "0x{}",
+                    utils::encode_hex(self.take(len, arg)?)
+                )))
+            }
+            "address" => Ok(Value::from(format!(
+                "0x{}",
+                utils::encode_hex(self.take(ADDRESS_LENGTH, arg)?)
+            ))),
+            "tuple" => components
+                .iter()
+                .enumerate()
+                .map(|(idx, component)| {
+                    let component = component.as_object().ok_or_else(unsupported)?;
+                    let component_ty = component
+                        .get("type")
+                        .and_then(Value::as_str)
+                        .ok_or_else(unsupported)?;
+                    let nested = component
+                        .get("components")
+                        .and_then(Value::as_array)
+                        .map(Vec::as_slice)
+                        .unwrap_or_default();
+                    self.decode(component_ty, nested, &format!("{}.{}", arg, idx))
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+            _ => {
+                if let Some(len) = ty.strip_prefix("bytes") {
+                    let len = len.parse::<usize>().map_err(|_| unsupported())?;
+                    if len == 0 || len > 32 {
+                        return Err(unsupported());
+                    }
+                    return Ok(Value::from(format!(
+                        "0x{}",
+                        utils::encode_hex(self.take(len, arg)?)
+                    )));
+                }
+                let (signed, bits) = match ty.strip_prefix("uint") {
+                    Some(bits) => (false, bits),
+                    None => (true, ty.strip_prefix("int").ok_or_else(unsupported)?),
+                };
+                let bits = bits.parse::<usize>().map_err(|_| unsupported())?;
+                if ![8, 16, 32, 64, 128, 256].contains(&bits) {
+                    return Err(unsupported());
+                }
+                self.integer(bits, signed, arg)
+            }
+        }
+    }
+}
+
+/// Looks up the function called by the call data in the ABI by the selector in the first 4
+/// bytes, and decodes the remaining bytes as arguments of the function.
+///
+/// Selectors are calculated with SM3 if `use_gm` is set and with Keccak-256 otherwise. Decode
+/// errors are [`AbiDecodeError`]s.
+pub(crate) fn execute_check_abi_compat(
+    abi_path: &Path,
+    call_data: &[u8],
+    use_gm: bool,
+) -> Result<AbiMatch> {
+    let content = fs::read_to_string(abi_path)
+        .context(format!("Reading ABI file '{}'", abi_path.display()))?;
+    let entries: Vec<Map<String, Value>> = serde_json::from_str(&content)
+        .context(format!("Parsing ABI file '{}'", abi_path.display()))?;
+
+    if call_data.len() < 4 {
+        return Err(AbiDecodeError::MissingSelector.into());
+    }
+    let (selector, args) = call_data.split_at(4);
+    let selector = u32::from_le_bytes(selector.try_into().expect("the selector has 4 bytes"));
+    let functions = entries
+        .iter()
+        .filter(|entry| entry.get("type").and_then(Value::as_str) == Some("function"))
+        .map(|entry| Ok((get_name_and_selector(entry, use_gm)?, entry)))
+        .collect::<Result<Vec<_>>>()
+        .context(format!("Reading ABI file '{}'", abi_path.display()))?;
+    let (function_name, entry) = functions
+        .into_iter()
+        .find(|((_, function_selector), _)| *function_selector == selector)
+        .map(|((name, _), entry)| (name, entry))
+        .ok_or(AbiDecodeError::UnknownSelector(selector))?;
+
+    let mut decoder = Decoder { input: args };
+    let mut decoded_args = Vec::new();
+    let inputs = entry["inputs"]
+        .as_array()
+        .expect("inputs are checked above");
+    for (idx, input) in inputs.iter().enumerate() {
+        let name = input
+            .get("name")
+            .and_then(Value::as_str)
+            .filter(|name| !name.is_empty())
+            .map_or_else(|| format!("arg{}", idx), str::to_owned);
+        let ty = input
+            .get("type")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        let components = input
+            .get("components")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        let value = decoder
+            .decode(ty, components, &name)
+            .context(format!("Decoding arguments of `{}`", function_name))?;
+        decoded_args.push(DecodedArg {
+            name,
+            ty: ty.to_owned(),
+            value,
+        });
+    }
+    if !decoder.input.is_empty() {
+        return Err(AbiDecodeError::TrailingBytes(decoder.input.len()))
+            .context(format!("Decoding arguments of `{}`", function_name));
+    }
+    Ok(AbiMatch {
+        function_name,
+        decoded_args,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Encodes `value` in the compact encoding, independently of the decoder.
+    fn encode_compact(value: u64) -> Vec<u8> {
+        match value {
+            0..=0x3f => vec![(value as u8) << 2],
+            0x40..=0x3fff => ((value as u16) << 2 | 0b01).to_le_bytes().to_vec(),
+            0x4000..=0x3fff_ffff => ((value as u32) << 2 | 0b10).to_le_bytes().to_vec(),
+            _ => {
+                let bytes = value.to_le_bytes();
+                let len = 8 - value.leading_zeros() as usize / 8;
+                let mut encoded = vec![((len - 4) as u8) << 2 | 0b11];
+                encoded.extend_from_slice(&bytes[..len]);
+                encoded
+            }
+        }
+    }
+
+    fn decode(ty: &str, components: &[Value], input: &[u8]) -> Result<Value, AbiDecodeError> {
+        let mut decoder = Decoder { input };
+        let value = decoder.decode(ty, components, "arg")?;
+        assert!(decoder.input.is_empty(), "`{}` left trailing bytes", ty);
+        Ok(value)
+    }
+
+    #[test]
+    fn compact_integers_round_trip_across_mode_boundaries() {
+        let cases: &[(u64, &[u8])] = &[
+            (0, &[0x00]),
+            (63, &[0xfc]),
+            (64, &[0x01, 0x01]),
+            (16383, &[0xfd, 0xff]),
+            (16384, &[0x02, 0x00, 0x01, 0x00]),
+            ((1 << 30) - 1, &[0xfe, 0xff, 0xff, 0xff]),
+            (1 << 30, &[0x03, 0x00, 0x00, 0x00, 0x40]),
+            (
+                u64::MAX,
+                &[0x13, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            ),
+        ];
+        for (value, encoded) in cases {
+            assert_eq!(encode_compact(*value), *encoded, "encoding {}", value);
+            let mut decoder = Decoder { input: encoded };
+            assert_eq!(decoder.compact_integer("arg").unwrap(), *value);
+            assert!(decoder.input.is_empty());
+        }
+    }
+
+    #[test]
+    fn compact_length_is_bounded_by_remaining_input() {
+        let mut input = encode_compact(64);
+        input.extend_from_slice(&[0u8; 64]);
+        assert_eq!(Decoder { input: &input }.compact("arg").unwrap(), 64);
+        assert!(matches!(
+            Decoder {
+                input: &input[..64]
+            }
+            .compact("arg"),
+            Err(AbiDecodeError::UnexpectedEnd { .. })
+        ));
+    }
+
+    #[test]
+    fn fixed_integers_round_trip() {
+        assert_eq!(decode("uint8", &[], &[0xff]).unwrap(), json!(255));
+        assert_eq!(decode("int8", &[], &[0xff]).unwrap(), json!(-1));
+        assert_eq!(
+            decode("uint32", &[], &0xdead_beefu32.to_le_bytes()).unwrap(),
+            json!(0xdead_beefu32)
+        );
+        assert_eq!(
+            decode("int64", &[], &i64::MIN.to_le_bytes()).unwrap(),
+            json!(i64::MIN)
+        );
+        assert_eq!(
+            decode("uint128", &[], &u128::MAX.to_le_bytes()).unwrap(),
+            json!(u128::MAX.to_string())
+        );
+        assert_eq!(
+            decode("int128", &[], &(-2i128).to_le_bytes()).unwrap(),
+            json!("-2")
+        );
+        let mut uint256 = [0u8; 32];
+        uint256[0] = 0x01;
+        uint256[31] = 0x80;
+        assert_eq!(
+            decode("uint256", &[], &uint256).unwrap(),
+            json!(format!("0x80{}01", "00".repeat(30)))
+        );
+    }
+
+    #[test]
+    fn arrays_round_trip() {
+        let fixed = [1u16.to_le_bytes(), 2u16.to_le_bytes()].concat();
+        assert_eq!(decode("uint16[2]", &[], &fixed).unwrap(), json!([1, 2]));
+
+        let mut dynamic = encode_compact(3);
+        dynamic.extend_from_slice(&[1, 0, 1]);
+        assert_eq!(
+            decode("bool[]", &[], &dynamic).unwrap(),
+            json!([true, false, true])
+        );
+
+        let mut nested = encode_compact(2);
+        nested.extend_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(
+            decode("uint8[2][]", &[], &nested).unwrap(),
+            json!([[1, 2], [3, 4]])
+        );
+    }
+
+    #[test]
+    fn tuples_round_trip() {
+        let components = [
+            json!({ "name": "flag", "type": "bool" }),
+            json!({
+                "name": "inner",
+                "type": "tuple",
+                "components": [{ "name": "text", "type": "string" }],
+            }),
+        ];
+        let mut input = vec![1];
+        input.extend(encode_compact(2));
+        input.extend_from_slice(b"hi");
+        assert_eq!(
+            decode("tuple", &components, &input).unwrap(),
+            json!([true, ["hi"]])
+        );
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        let truncated: &[(&str, &[u8])] = &[
+            ("uint32", &[0x01, 0x02, 0x03]),
+            ("address", &[0u8; ADDRESS_LENGTH - 1]),
+            ("string", &[0x14, b'h', b'i']),
+            ("uint8[3]", &[1, 2]),
+            ("bytes", &[0x01]),
+            ("bytes", &[0x03, 0x00, 0x00, 0x00]),
+        ];
+        for (ty, input) in truncated {
+            assert!(
+                matches!(
+                    Decoder { input }.decode(ty, &[], "arg"),
+                    Err(AbiDecodeError::UnexpectedEnd { .. })
+                ),
+                "truncated `{}` was decoded",
+                ty
+            );
+        }
+    }
+}
@@ -0,0 +1,356 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::build::get_name_and_selector;
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::{
+    collections::BTreeSet,
+    fmt::Write,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// The SCALE codec used by the generated crate, the same one as contracts created by
+/// `cargo liquid new`.
+const SCALE_DEPENDENCY: &str = "scale = { package = \"parity-scale-codec\", version = \"1.3.1\", \
+                                default-features = false, features = [\"full\"] }";
+
+/// Keywords of Rust 2018, including reserved ones, which must be raw identifiers in the generated
+/// code.
+const KEYWORDS: &[&str] = &[
+    "abstract", "as", "async", "await", "become", "box", "break", "const", "continue", "do", "dyn",
+    "else", "enum", "extern", "false", "final", "fn", "for", "if", "impl", "in", "let", "loop",
+    "macro", "match", "mod", "move", "mut", "override", "priv", "pub", "ref", "return", "static",
+    "struct", "trait", "true", "try", "type", "typeof", "unsafe", "unsized", "use", "virtual",
+    "where", "while", "yield",
+];
+
+/// Keywords which can't be raw identifiers.
+const PATH_KEYWORDS: &[&str] = &["crate", "self", "Self", "super"];
+
+/// Turns a name in the ABI into a Rust identifier, keywords are escaped as raw identifiers.
+fn ident(name: &str) -> Result<String> {
+    if PATH_KEYWORDS.contains(&name) {
+        anyhow::bail!(
+            "`{}` can't be used as an identifier in the client crate",
+            name
+        );
+    }
+    if KEYWORDS.contains(&name) {
+        return Ok(format!("r#{}", name));
+    }
+    Ok(name.to_owned())
+}
+
+/// Maps an ABI type to the Rust type encoded the same way by SCALE. Integers wider than 128 bits
+/// have no primitive type, so they are little-endian byte arrays.
+fn rust_type(ty: &str, components: &[Value]) -> Result<String> {
+    if let Some(start) = ty.strip_suffix(']').and_then(|ty| ty.rfind('[')) {
+        let elem_ty = rust_type(&ty[..start], components)?;
+        return Ok(match &ty[start + 1..ty.len() - 1] {
+            "" => format!("Vec<{}>", elem_ty),
+            len => format!("[{}; {}]", elem_ty, len),
+        });
+    }
+    let rust_ty = match ty {
+        "bool" => "bool".to_owned(),
+        "string" => "String".to_owned(),
+        "bytes" => "Vec<u8>".to_owned(),
+        "address" => "[u8; 20]".to_owned(),
+        "tuple" => {
+            let types = components
+                .iter()
+                .map(|component| {
+                    let component_ty = component
+                        .get("type")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default();
+                    rust_type(component_ty, nested_components(component))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            rust_tuple(&types)
+        }
+        _ => {
+            if let Some(len) = ty.strip_prefix("bytes") {
+                return Ok(format!("[u8; {}]", len));
+            }
+            let (signed, bits) = match ty.strip_prefix("uint") {
+                Some(bits) => (false, bits),
+                None => match ty.strip_prefix("int") {
+                    Some(bits) => (true, bits),
+                    None => anyhow::bail!("unsupported ABI type `{}`", ty),
+                },
+            };
+            match bits {
+                "8" | "16" | "32" | "64" | "128" => {
+                    format!("{}{}", if signed { 'i' } else { 'u' }, bits)
+                }
+                "256" => "[u8; 32]".to_owned(),
+                _ => anyhow::bail!("unsupported ABI type `{}`", ty),
+            }
+        }
+    };
+    Ok(rust_ty)
+}
+
+fn rust_tuple(types: &[String]) -> String {
+    match types {
+        [ty] => format!("({},)", ty),
+        types => format!("({})", types.join(", ")),
+    }
+}
+
+fn nested_components(param: &Value) -> &[Value] {
+    param
+        .get("components")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+}
+
+/// Returns names and Rust types of the parameters, unnamed parameters are named by position.
+fn rust_params(entry: &Map<String, Value>, key: &str) -> Result<Vec<(String, String)>> {
+    let params = entry
+        .get(key)
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    params
+        .iter()
+        .enumerate()
+        .map(|(idx, param)| {
+            let name = match param.get("name").and_then(Value::as_str) {
+                // The generated functions use `call_data` themselves.
+                Some("call_data") => "call_data_arg".to_owned(),
+                Some(name) if !name.is_empty() => ident(name)?,
+                _ => format!("arg{}", idx),
+            };
+            let ty = param
+                .get("type")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            Ok((name, rust_type(ty, nested_components(param))?))
+        })
+        .collect()
+}
+
+/// Writes a function encoding `params` into the call data after `prefix`, which is a selector
+/// for methods and empty for the constructor.
+fn write_encoder(
+    code: &mut String,
+    doc: &str,
+    name: &str,
+    prefix: &str,
+    params: &[(String, String)],
+) -> Result<()> {
+    let args = params
+        .iter()
+        .map(|(name, ty)| format!("{}: {}", name, ty))
+        .collect::<Vec<_>>()
+        .join(", ");
+    writeln!(code, "/// {}", doc)?;
+    writeln!(code, "pub fn {}({}) -> Vec<u8> {{", name, args)?;
+    if params.is_empty() {
+        writeln!(code, "    {}\n}}\n", prefix)?;
+        return Ok(());
+    }
+    writeln!(code, "    let mut call_data = {};", prefix)?;
+    for (name, _) in params {
+        writeln!(code, "    {}.encode_to(&mut call_data);", name)?;
+    }
+    writeln!(code, "    call_data\n}}\n")?;
+    Ok(())
+}
+
+/// Records an item to be generated, fails if an item of the same name is generated already.
+fn declare(items: &mut BTreeSet<String>, item: String) -> Result<()> {
+    if !items.insert(item.clone()) {
+        anyhow::bail!("`{}` is generated more than once in the client crate", item);
+    }
+    Ok(())
+}
+
+/// Generates the source of the client crate, with a function encoding the call data of every
+/// method of the contract, and one decoding the output if the method returns anything.
+fn render_lib_rs(package_name: &str, abi: &[Value], use_gm: bool) -> Result<String> {
+    let mut code = String::new();
+    writeln!(
+        code,
+        "//! Client bindings of the `{}` contract, generated by `cargo liquid build \
+         --interface-mode`\n//! from its ABI. Do not edit, changes are overwritten by the next \
+         build.\n",
+        package_name
+    )?;
+    code.push_str(
+        "#![cfg_attr(not(feature = \"std\"), no_std)]\n\n\
+         extern crate alloc;\n\n\
+         #[allow(unused_imports)]\n\
+         use alloc::{string::String, vec::Vec};\n\
+         #[allow(unused_imports)]\n\
+         use scale::{Decode, Encode};\n\n",
+    );
+
+    let has_constructor = abi
+        .iter()
+        .any(|entry| entry.get("type").and_then(Value::as_str) == Some("constructor"));
+    // Names of all generated items, to report collisions instead of writing a crate which
+    // doesn't compile.
+    let mut items = BTreeSet::new();
+    let mut functions = BTreeSet::new();
+    for entry in abi.iter().filter_map(Value::as_object) {
+        match entry.get("type").and_then(Value::as_str) {
+            Some("constructor") => {
+                declare(&mut items, "deploy".to_owned())?;
+                let params = rust_params(entry, "inputs")?;
+                write_encoder(
+                    &mut code,
+                    "Encodes the arguments of the constructor, passed when deploying the contract.",
+                    "deploy",
+                    "Vec::new()",
+                    &params,
+                )?;
+            }
+            Some("function") => {
+                let (name, selector) = get_name_and_selector(entry, use_gm)?;
+                if name == "deploy" && has_constructor {
+                    anyhow::bail!(
+                        "function `deploy` collides with the generated `deploy` encoding the \
+                         arguments of the constructor"
+                    );
+                }
+                if !functions.insert(name.clone()) {
+                    anyhow::bail!(
+                        "function `{}` is overloaded, which the client crate can't express as \
+                         Rust functions",
+                        name
+                    );
+                }
+                let fn_name = ident(&name)?;
+                let const_name = format!("{}_SELECTOR", name.to_uppercase());
+                declare(&mut items, name.clone())?;
+                declare(&mut items, const_name.clone())?;
+                let params = rust_params(entry, "inputs")?;
+                writeln!(code, "/// Selector of `{}`.", name)?;
+                writeln!(
+                    code,
+                    "pub const {}: [u8; 4] = {:?};\n",
+                    const_name,
+                    selector.to_le_bytes()
+                )?;
+                write_encoder(
+                    &mut code,
+                    &format!("Encodes the call data of `{}`.", name),
+                    &fn_name,
+                    &format!("{}.to_vec()", const_name),
+                    &params,
+                )?;
+
+                let outputs = rust_params(entry, "outputs")?
+                    .into_iter()
+                    .map(|(_, ty)| ty)
+                    .collect::<Vec<_>>();
+                let output_ty = match outputs.as_slice() {
+                    [] => continue,
+                    [ty] => ty.clone(),
+                    types => rust_tuple(types),
+                };
+                let decoder_name = format!("decode_{}_output", name);
+                declare(&mut items, decoder_name.clone())?;
+                writeln!(code, "/// Decodes the output of `{}`.", name)?;
+                writeln!(
+                    code,
+                    "pub fn {}(mut output: &[u8]) -> Result<{}, scale::Error> {{\n    \
+                     Decode::decode(&mut output)\n}}\n",
+                    decoder_name, output_ty
+                )?;
+            }
+            _ => (),
+        }
+    }
+    Ok(code)
+}
+
+/// Generates `<package>-client` in `target_dir` from the ABI of the contract, a crate with
+/// type-safe helpers encoding calls to the contract, which can be used as a path dependency of
+/// contracts and clients calling it. Returns the directory of the crate.
+pub(crate) fn write_client_crate(
+    package_name: &str,
+    version: &str,
+    abi: &[Value],
+    use_gm: bool,
+    target_dir: &Path,
+) -> Result<PathBuf> {
+    let client_name = format!("{}-client", package_name);
+    let client_dir = target_dir.join(&client_name);
+    let src_dir = client_dir.join("src");
+    fs::create_dir_all(&src_dir).context(format!("Creating '{}'", src_dir.display()))?;
+
+    let manifest = format!(
+        "[package]\nname = \"{}\"\nversion = \"{}\"\nedition = \"2018\"\n\n\
+         [dependencies]\n{}\n\n\
+         [features]\ndefault = [\"std\"]\nstd = [\"scale/std\"]\n",
+        client_name, version, SCALE_DEPENDENCY
+    );
+    let manifest_path = client_dir.join("Cargo.toml");
+    fs::write(&manifest_path, manifest)
+        .context(format!("Writing '{}'", manifest_path.display()))?;
+    let lib_rs = src_dir.join("lib.rs");
+    fs::write(&lib_rs, render_lib_rs(package_name, abi, use_gm)?)
+        .context(format!("Writing '{}'", lib_rs.display()))?;
+    Ok(client_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn function(name: &str, inputs: Value) -> Value {
+        json!({ "type": "function", "name": name, "inputs": inputs, "outputs": [] })
+    }
+
+    #[test]
+    fn keywords_are_raw_identifiers() {
+        let abi = [function(
+            "type",
+            json!([{ "name": "move", "type": "bool" }]),
+        )];
+        let code = render_lib_rs("c", &abi, false).unwrap();
+        assert!(code.contains("pub const TYPE_SELECTOR"));
+        assert!(code.contains("pub fn r#type(r#move: bool) -> Vec<u8>"));
+        assert!(code.contains("r#move.encode_to(&mut call_data);"));
+    }
+
+    #[test]
+    fn collisions_are_reported() {
+        let constructor = json!({ "type": "constructor", "inputs": [] });
+        let err =
+            render_lib_rs("c", &[function("deploy", json!([])), constructor], false).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("collides with the generated `deploy`"));
+
+        let overloads = [
+            function("set", json!([{ "name": "value", "type": "bool" }])),
+            function("set", json!([{ "name": "value", "type": "string" }])),
+        ];
+        let err = render_lib_rs("c", &overloads, false).unwrap_err();
+        assert!(err.to_string().contains("`set` is overloaded"));
+
+        let constants = [function("get", json!([])), function("GET", json!([]))];
+        let err = render_lib_rs("c", &constants, false).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("`GET_SELECTOR` is generated more than once"));
+    }
+}
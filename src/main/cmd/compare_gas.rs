@@ -0,0 +1,227 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{build::get_name_and_selector, diff::format_delta};
+use crate::runtime::{MockEnvironment, Outcome, CALL_ENTRY_POINT, GAS_MODULE};
+use anyhow::{Context, Result};
+use parity_wasm::elements::Module;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{cmp::Reverse, fmt, fs, path::Path};
+
+/// How many zero bytes follow the selector in the call data, which is enough to decode all
+/// arguments of a function as zeros, empty strings or empty vectors.
+const ARGUMENTS_SIZE: usize = 1024;
+
+/// Gas used by a public function in both versions of the contract.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FunctionGas {
+    pub name: String,
+    pub old_gas: u64,
+    pub new_gas: u64,
+    /// Positive if the new version uses more gas.
+    pub delta: i64,
+    /// The delta relative to the gas used by the old version, in percent.
+    pub percentage: f64,
+    /// Whether the function reverted in the old version, gas is compared until then.
+    pub old_reverted: bool,
+    /// Whether the function reverted in the new version.
+    pub new_reverted: bool,
+}
+
+/// Gas costs of public functions of two versions of a contract, sorted from the worst regression
+/// to the best improvement.
+#[derive(Serialize)]
+pub(crate) struct GasComparison {
+    pub functions: Vec<FunctionGas>,
+}
+
+impl fmt::Display for GasComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let width = self
+            .functions
+            .iter()
+            .map(|function| function.name.len())
+            .chain(Some("Function".len()))
+            .max()
+            .unwrap_or_default();
+        writeln!(
+            f,
+            "{: <width$}  {: >10}  {: >10}  {: >10}  {: >8}",
+            "Function",
+            "Old",
+            "New",
+            "Delta",
+            "Change",
+            width = width
+        )?;
+        for function in &self.functions {
+            let note = match (function.old_reverted, function.new_reverted) {
+                (false, false) => "",
+                (true, false) => "  old reverted",
+                (false, true) => "  new reverted",
+                (true, true) => "  both reverted",
+            };
+            writeln!(
+                f,
+                "{: <width$}  {: >10}  {: >10}  {}  {: >+7.1}%{}",
+                function.name,
+                function.old_gas,
+                function.new_gas,
+                format_delta(function.delta, 10),
+                function.percentage,
+                note,
+                width = width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Loads the Wasm bytecode and injects the gas counter of the mocked environment into it.
+pub(super) fn load_metered(wasm_path: &Path) -> Result<Module> {
+    let module = parity_wasm::deserialize_file(wasm_path)
+        .context(format!("Loading Wasm file '{}'", wasm_path.display()))?;
+    pwasm_utils::inject_gas_counter(module, &pwasm_utils::rules::Set::default(), GAS_MODULE)
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "unable to inject gas counter into the Wasm module '{}'",
+                wasm_path.display()
+            )
+        })
+}
+
+/// Calls the contract with `call_data` once, returns how the call finished and the gas used.
+///
+/// Gas used until the call reverts is counted as well, as it's still charged on chain. A call
+/// which traps fails, as the gas used until then says nothing about the function.
+pub(super) fn measure(module: &Module, call_data: Vec<u8>) -> Result<(Outcome, u64)> {
+    let mut env = MockEnvironment::new(call_data);
+    let instance = env.instantiate(module.clone())?;
+    let outcome = env.invoke(&instance, CALL_ENTRY_POINT).context(format!(
+        "Calling the contract failed after using {} gas",
+        env.gas_used()
+    ))?;
+    Ok((outcome, env.gas_used()))
+}
+
+/// Measures the gas used by every public function in the ABI for two versions of a contract, by
+/// calling them with all arguments zeroed in a mocked environment. Functions reverting are
+/// marked, and the comparison fails if any function traps.
+///
+/// Selectors are calculated with SM3 if `use_gm` is set and with Keccak-256 otherwise.
+pub(crate) fn execute_compare_gas(
+    wasm_a: &Path,
+    wasm_b: &Path,
+    abi_path: &Path,
+    use_gm: bool,
+) -> Result<GasComparison> {
+    let content = fs::read_to_string(abi_path)
+        .context(format!("Reading ABI file '{}'", abi_path.display()))?;
+    let entries: Vec<Map<String, Value>> = serde_json::from_str(&content)
+        .context(format!("Parsing ABI file '{}'", abi_path.display()))?;
+    let old_module = load_metered(wasm_a)?;
+    let new_module = load_metered(wasm_b)?;
+
+    let mut functions = entries
+        .iter()
+        .filter(|entry| entry.get("type").and_then(Value::as_str) == Some("function"))
+        .map(|entry| {
+            let (name, selector) = get_name_and_selector(entry, use_gm)
+                .context(format!("Reading ABI file '{}'", abi_path.display()))?;
+            let mut call_data = selector.to_le_bytes().to_vec();
+            call_data.resize(call_data.len() + ARGUMENTS_SIZE, 0);
+            let (old_outcome, old_gas) = measure(&old_module, call_data.clone())
+                .context(format!("Measuring `{}` of '{}'", name, wasm_a.display()))?;
+            let (new_outcome, new_gas) = measure(&new_module, call_data).context(format!(
+                "Measuring `{}` of '{}'",
+                name,
+                wasm_b.display()
+            ))?;
+            let delta = new_gas as i64 - old_gas as i64;
+            let percentage = if old_gas == 0 {
+                0.0
+            } else {
+                delta as f64 * 100.0 / old_gas as f64
+            };
+            Ok(FunctionGas {
+                name,
+                old_gas,
+                new_gas,
+                delta,
+                percentage,
+                old_reverted: old_outcome == Outcome::Reverted,
+                new_reverted: new_outcome == Outcome::Reverted,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    functions.sort_by_key(|function| Reverse(function.delta));
+    Ok(GasComparison { functions })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parity_wasm::elements::{
+        CodeSection, ExportEntry, ExportSection, External, Func, FuncBody, FunctionSection,
+        FunctionType, ImportEntry, ImportSection, Instruction, Instructions, Internal,
+        MemorySection, MemoryType, Section, Type, TypeSection, ValueType,
+    };
+
+    /// A contract whose entry point runs `code`, with `revert` imported as function 0.
+    fn contract(code: Vec<Instruction>) -> Module {
+        let module = Module::new(vec![
+            Section::Type(TypeSection::with_types(vec![
+                Type::Function(FunctionType::new(vec![ValueType::I32; 2], Vec::new())),
+                Type::Function(FunctionType::new(Vec::new(), Vec::new())),
+            ])),
+            Section::Import(ImportSection::with_entries(vec![ImportEntry::new(
+                "bcos".to_owned(),
+                "revert".to_owned(),
+                External::Function(0),
+            )])),
+            Section::Function(FunctionSection::with_entries(vec![Func::new(1)])),
+            Section::Memory(MemorySection::with_entries(vec![MemoryType::new(1, None)])),
+            Section::Export(ExportSection::with_entries(vec![
+                ExportEntry::new("memory".to_owned(), Internal::Memory(0)),
+                ExportEntry::new(CALL_ENTRY_POINT.to_owned(), Internal::Function(1)),
+            ])),
+            Section::Code(CodeSection::with_bodies(vec![FuncBody::new(
+                Vec::new(),
+                Instructions::new(code),
+            )])),
+        ]);
+        pwasm_utils::inject_gas_counter(module, &pwasm_utils::rules::Set::default(), GAS_MODULE)
+            .unwrap()
+    }
+
+    #[test]
+    fn measure_reports_reverts() {
+        use Instruction::*;
+
+        let module = contract(vec![I32Const(0), I32Const(0), Call(0), End]);
+        let (outcome, gas) = measure(&module, Vec::new()).unwrap();
+        assert_eq!(outcome, Outcome::Reverted);
+        assert!(gas > 0);
+
+        let module = contract(vec![Nop, End]);
+        let (outcome, _) = measure(&module, Vec::new()).unwrap();
+        assert_eq!(outcome, Outcome::Finished);
+    }
+
+    #[test]
+    fn measure_fails_on_traps() {
+        let module = contract(vec![Instruction::Unreachable, Instruction::End]);
+        assert!(measure(&module, Vec::new()).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{utils, workspace::ManifestPath, VerbosityBehavior};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::{collections::HashMap, env, fs, path::PathBuf, process::Command};
+
+/// A function recorded in the LCOV report which is never executed by tests.
+struct UncoveredFunction {
+    file: PathBuf,
+    line: usize,
+    name: String,
+}
+
+/// Line coverage and uncovered functions extracted from a LCOV report.
+#[derive(Default)]
+struct CoverageSummary {
+    lines_found: usize,
+    lines_hit: usize,
+    uncovered_functions: Vec<UncoveredFunction>,
+}
+
+/// Parses a LCOV report, only records of source files under `src_dir` are taken into account.
+fn summarize_lcov(report: &str, src_dir: &std::path::Path) -> CoverageSummary {
+    let mut summary = CoverageSummary::default();
+    let mut current_file = None;
+    let mut fn_lines = HashMap::new();
+
+    for record in report.lines() {
+        if let Some(file) = record.strip_prefix("SF:") {
+            let file = PathBuf::from(file);
+            current_file = if file.starts_with(src_dir) {
+                Some(file)
+            } else {
+                None
+            };
+            fn_lines.clear();
+            continue;
+        }
+
+        let file = match &current_file {
+            Some(file) => file,
+            None => continue,
+        };
+        if let Some(fn_info) = record.strip_prefix("FN:") {
+            if let Some((line, name)) = fn_info.split_once(',') {
+                fn_lines.insert(name.to_owned(), line.parse::<usize>().unwrap_or_default());
+            }
+        } else if let Some(fn_data) = record.strip_prefix("FNDA:") {
+            if let Some(("0", name)) = fn_data.split_once(',') {
+                if let Some(line) = fn_lines.get(name) {
+                    summary.uncovered_functions.push(UncoveredFunction {
+                        file: file.clone(),
+                        line: *line,
+                        name: rustc_demangle::demangle(name).to_string(),
+                    });
+                }
+            }
+        } else if let Some(lines_found) = record.strip_prefix("LF:") {
+            summary.lines_found += lines_found.parse::<usize>().unwrap_or_default();
+        } else if let Some(lines_hit) = record.strip_prefix("LH:") {
+            summary.lines_hit += lines_hit.parse::<usize>().unwrap_or_default();
+        }
+    }
+
+    summary
+}
+
+/// Checks whether the function defined at `line` of `file` is declared as `pub`.
+fn is_public_function(file: &std::path::Path, line: usize) -> bool {
+    fs::read_to_string(file)
+        .ok()
+        .and_then(|content| {
+            content
+                .lines()
+                .nth(line.saturating_sub(1))
+                .map(|line| line.trim_start().starts_with("pub "))
+        })
+        .unwrap_or(false)
+}
+
+/// Runs tests of the contract on the host with coverage instrumentation enabled, and writes
+/// a LCOV report to `target/liquid-coverage/lcov.info`.
+///
+/// This step depends on `cargo-llvm-cov` being installed.
+pub(crate) fn execute_coverage(
+    manifest_path: ManifestPath,
+    verbosity_behavior: VerbosityBehavior,
+) -> Result<PathBuf> {
+    if which::which("cargo-llvm-cov").is_err() {
+        anyhow::bail!(
+            "cargo-llvm-cov is not installed. Install it by `cargo install cargo-llvm-cov` to \
+             collect coverage of your contract."
+        );
+    }
+
+    let (metadata, root_package_id) = utils::get_cargo_metadata(&manifest_path)?;
+    let root_package = metadata
+        .packages
+        .iter()
+        .find(|package| package.id == root_package_id)
+        .expect("the package is not in the `cargo metadata` output");
+
+    let output_dir = metadata.target_directory.join("liquid-coverage");
+    fs::create_dir_all(&output_dir)
+        .context(format!("Creating directory '{}'", output_dir.display()))?;
+    let report_path = output_dir.join("lcov.info");
+
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut cmd = Command::new(cargo);
+    cmd.arg("llvm-cov")
+        .arg(format!(
+            "--manifest-path={}",
+            root_package.manifest_path.to_string_lossy()
+        ))
+        .arg("--lcov")
+        .arg(format!("--output-path={}", report_path.to_string_lossy()))
+        .arg(match verbosity_behavior {
+            VerbosityBehavior::Quiet | VerbosityBehavior::Normal => "--quiet",
+            VerbosityBehavior::Verbose => "--verbose",
+        });
+    let status = cmd
+        .status()
+        .context(format!("Error executing `{:?}`", cmd))?;
+    if !status.success() {
+        anyhow::bail!("`{:?}` failed with exit code: {:?}", cmd, status.code());
+    }
+
+    let report = fs::read_to_string(&report_path).context(format!(
+        "Reading coverage report '{}'",
+        report_path.display()
+    ))?;
+    let src_dir = root_package
+        .manifest_path
+        .parent()
+        .expect("the manifest path is a file path so has a parent")
+        .join("src");
+    let summary = summarize_lcov(&report, &src_dir);
+
+    let percentage = if summary.lines_found == 0 {
+        100.0
+    } else {
+        summary.lines_hit as f64 * 100.0 / summary.lines_found as f64
+    };
+    println!(
+        "{} {:.2}% ({}/{} lines)",
+        "Line coverage:".green().bold(),
+        percentage,
+        summary.lines_hit,
+        summary.lines_found
+    );
+
+    let uncovered_public_functions = summary
+        .uncovered_functions
+        .iter()
+        .filter(|function| is_public_function(&function.file, function.line))
+        .collect::<Vec<_>>();
+    if !uncovered_public_functions.is_empty() {
+        println!("{}", "Uncovered public functions:".bright_yellow().bold());
+        for function in uncovered_public_functions {
+            println!(
+                "  {}:{} {}",
+                function.file.display(),
+                function.line,
+                function.name.bright_yellow()
+            );
+        }
+    }
+
+    Ok(report_path)
+}
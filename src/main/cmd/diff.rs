@@ -0,0 +1,285 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use colored::Colorize;
+use parity_wasm::elements::{Module, Section, Serialize};
+use std::{collections::BTreeSet, fs, path::Path};
+
+/// The name of a section in the listing, custom sections are distinguished by their names.
+fn section_name(section: &Section) -> String {
+    match section {
+        Section::Unparsed { id, .. } => format!("unknown({})", id),
+        Section::Custom(section) => format!("custom \"{}\"", section.name()),
+        Section::Type(_) => "type".to_owned(),
+        Section::Import(_) => "import".to_owned(),
+        Section::Function(_) => "function".to_owned(),
+        Section::Table(_) => "table".to_owned(),
+        Section::Memory(_) => "memory".to_owned(),
+        Section::Global(_) => "global".to_owned(),
+        Section::Export(_) => "export".to_owned(),
+        Section::Start(_) => "start".to_owned(),
+        Section::Element(_) => "element".to_owned(),
+        Section::DataCount(_) => "data count".to_owned(),
+        Section::Code(_) => "code".to_owned(),
+        Section::Data(_) => "data".to_owned(),
+        Section::Name(_) => "custom \"name\"".to_owned(),
+        Section::Reloc(section) => format!("custom \"{}\"", section.name()),
+    }
+}
+
+/// Sizes of all sections of the module in bytes, including their headers.
+pub(crate) fn section_sizes(module: &Module) -> Result<Vec<(String, usize)>> {
+    module
+        .sections()
+        .iter()
+        .map(|section| {
+            let mut bytes = Vec::new();
+            section
+                .clone()
+                .serialize(&mut bytes)
+                .context("Serializing section")?;
+            Ok((section_name(section), bytes.len()))
+        })
+        .collect()
+}
+
+/// The total size of sections named `name`, 0 if there is no such section.
+fn size_of(sections: &[(String, usize)], name: &str) -> usize {
+    sections
+        .iter()
+        .filter(|(section, _)| section == name)
+        .map(|(_, size)| size)
+        .sum()
+}
+
+/// Sizes of sections with the same name in two modules, in the order of `first` followed by
+/// sections only present in `second`. A section missing from a module has the size 0.
+pub(crate) fn compare_section_sizes(
+    first: &Module,
+    second: &Module,
+) -> Result<Vec<(String, usize, usize)>> {
+    let first_sections = section_sizes(first)?;
+    let second_sections = section_sizes(second)?;
+    let mut names = Vec::<&str>::new();
+    for (name, _) in first_sections.iter().chain(&second_sections) {
+        if !names.contains(&name.as_str()) {
+            names.push(name);
+        }
+    }
+    Ok(names
+        .into_iter()
+        .map(|name| {
+            (
+                name.to_owned(),
+                size_of(&first_sections, name),
+                size_of(&second_sections, name),
+            )
+        })
+        .collect())
+}
+
+pub(crate) fn exports(module: &Module) -> BTreeSet<String> {
+    module
+        .export_section()
+        .map(|section| {
+            section
+                .entries()
+                .iter()
+                .map(|entry| entry.field().to_owned())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub(crate) fn imports(module: &Module) -> BTreeSet<String> {
+    module
+        .import_section()
+        .map(|section| {
+            section
+                .entries()
+                .iter()
+                .map(|entry| format!("{}.{}", entry.module(), entry.field()))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Formats a size delta right-aligned in `width` columns, increases are red and decreases are
+/// green. Padding is applied before coloring, which would break the alignment otherwise.
+pub(crate) fn format_delta(delta: i64, width: usize) -> String {
+    let text = format!("{:>+width$}", delta, width = width);
+    match delta {
+        0 => format!("{:>width$}", 0, width = width),
+        delta if delta > 0 => text.bright_red().to_string(),
+        _ => text.green().to_string(),
+    }
+}
+
+/// Lists added and removed exports and imports, one line each.
+fn interface_changes(old_module: &Module, new_module: &Module) -> Vec<String> {
+    let changes = [
+        ("export", exports(old_module), exports(new_module)),
+        ("import", imports(old_module), imports(new_module)),
+    ];
+    let mut lines = Vec::new();
+    for (kind, old, new) in changes.iter() {
+        for added in new.difference(old) {
+            lines.push(format!("  {} {} `{}`", "+".green().bold(), kind, added));
+        }
+        for removed in old.difference(new) {
+            lines.push(format!(
+                "  {} {} `{}`",
+                "-".bright_red().bold(),
+                kind,
+                removed
+            ));
+        }
+    }
+    lines
+}
+
+/// Compares two builds of a contract, prints size deltas of sections, added and removed exports
+/// and imports, and the overall size change.
+pub(crate) fn execute_diff(old_path: &Path, new_path: &Path) -> Result<()> {
+    let load = |path: &Path| -> Result<(usize, Module)> {
+        let size = fs::metadata(path)
+            .context(format!("Loading Wasm file '{}'", path.display()))?
+            .len() as usize;
+        let module = parity_wasm::deserialize_file(path)
+            .context(format!("Loading Wasm file '{}'", path.display()))?;
+        Ok((size, module))
+    };
+    let (old_size, old_module) = load(old_path)?;
+    let (new_size, new_module) = load(new_path)?;
+
+    // Sections are listed in the order of the new module, followed by sections only present in
+    // the old one.
+    let sections = compare_section_sizes(&new_module, &old_module)?;
+    let width = sections
+        .iter()
+        .map(|(name, _, _)| name.len())
+        .max()
+        .unwrap_or_default();
+    println!(
+        "{: <width$}  {: >10}  {: >10}  {: >10}",
+        "Section",
+        "Old",
+        "New",
+        "Delta",
+        width = width
+    );
+    for (name, new, old) in sections {
+        println!(
+            "{: <width$}  {: >10}  {: >10}  {}",
+            name,
+            old,
+            new,
+            format_delta(new as i64 - old as i64, 10),
+            width = width
+        );
+    }
+
+    println!("{}", "Interface:".bold());
+    let changes = interface_changes(&old_module, &new_module);
+    if changes.is_empty() {
+        println!("  no export or import changed");
+    }
+    for line in changes {
+        println!("{}", line);
+    }
+
+    let delta = new_size as i64 - old_size as i64;
+    println!(
+        "{} {} -> {} bytes ({}, {:+.1}%)",
+        "Total:".bold(),
+        old_size,
+        new_size,
+        format_delta(delta, 0),
+        delta as f64 * 100.0 / old_size.max(1) as f64
+    );
+    Ok(())
+}
+
+/// The relative size increase above which a change is reported as a regression.
+const REGRESSION_THRESHOLD: f64 = 5.0;
+
+/// Formats a size change with its percentage, increases above [`REGRESSION_THRESHOLD`] percent
+/// are red.
+fn format_size_change(old: usize, new: usize) -> String {
+    let delta = new as i64 - old as i64;
+    let percentage = delta as f64 * 100.0 / old.max(1) as f64;
+    let text = format!(
+        "{} -> {} bytes ({:+}, {:+.1}%)",
+        old, new, delta, percentage
+    );
+    if percentage > REGRESSION_THRESHOLD {
+        text.bright_red().bold().to_string()
+    } else {
+        text
+    }
+}
+
+fn data_segments(module: &Module) -> (usize, usize) {
+    module
+        .data_section()
+        .map(|section| {
+            let segments = section.entries();
+            (
+                segments.len(),
+                segments.iter().map(|segment| segment.value().len()).sum(),
+            )
+        })
+        .unwrap_or_default()
+}
+
+/// Summarizes what changed between the Wasm bytecode of the last build and the one just built,
+/// i.e. the number of functions, the size of the code, exports, imports and data segments.
+pub(crate) fn summarize_changes(old: &[u8], new: &[u8]) -> Result<String> {
+    let old_module = Module::from_bytes(old).context("Loading the last Wasm bytecode")?;
+    let new_module = Module::from_bytes(new).context("Loading the new Wasm bytecode")?;
+    let functions = |module: &Module| {
+        module
+            .function_section()
+            .map(|section| section.entries().len())
+            .unwrap_or_default()
+    };
+    let code_size =
+        |module: &Module| -> Result<usize> { Ok(size_of(&section_sizes(module)?, "code")) };
+
+    let mut lines = vec![format!("{}", "Changes since the last build:".bold())];
+    let (old_functions, new_functions) = (functions(&old_module), functions(&new_module));
+    lines.push(format!(
+        "  functions: {} -> {} ({})",
+        old_functions,
+        new_functions,
+        format_delta(new_functions as i64 - old_functions as i64, 0)
+    ));
+    lines.push(format!(
+        "  code: {}",
+        format_size_change(code_size(&old_module)?, code_size(&new_module)?)
+    ));
+    let ((old_segments, old_data), (new_segments, new_data)) =
+        (data_segments(&old_module), data_segments(&new_module));
+    lines.push(format!(
+        "  data: {} -> {} segments, {}",
+        old_segments,
+        new_segments,
+        format_size_change(old_data, new_data)
+    ));
+    lines.push(format!(
+        "  total: {}",
+        format_size_change(old.len(), new.len())
+    ));
+    lines.extend(interface_changes(&old_module, &new_module));
+    Ok(lines.join("\n"))
+}
@@ -0,0 +1,323 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::prof::function_names;
+use anyhow::{Context, Result};
+use itertools::Itertools;
+use parity_wasm::elements::{
+    External, FunctionType, GlobalType, ImportCountType, InitExpr, Instruction, Internal,
+    MemoryType, Module, ResizableLimits, Section, TableType, Type,
+};
+use std::{
+    fs,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+pub(crate) fn format_signature(ty: &FunctionType) -> String {
+    format!(
+        "({}) -> ({})",
+        ty.params().iter().join(", "),
+        ty.results().iter().join(", ")
+    )
+}
+
+fn format_limits(limits: &ResizableLimits) -> String {
+    match limits.maximum() {
+        Some(maximum) => format!("initial {}, maximum {}", limits.initial(), maximum),
+        None => format!("initial {}", limits.initial()),
+    }
+}
+
+pub(crate) fn format_memory(memory: &MemoryType) -> String {
+    format!("{} pages", format_limits(memory.limits()))
+}
+
+pub(crate) fn format_table(table: &TableType) -> String {
+    format!("anyfunc, {} elements", format_limits(table.limits()))
+}
+
+pub(crate) fn format_global(global: &GlobalType) -> String {
+    if global.is_mutable() {
+        format!("mut {}", global.content_type())
+    } else {
+        global.content_type().to_string()
+    }
+}
+
+fn format_init_expr(init_expr: Option<&InitExpr>) -> String {
+    match init_expr {
+        Some(init_expr) => init_expr
+            .code()
+            .iter()
+            .filter(|instruction| **instruction != Instruction::End)
+            .join(" "),
+        None => "passive".to_owned(),
+    }
+}
+
+/// Writes a human readable listing of all sections of `module` to `writer`.
+fn disassemble(module: &Module, writer: &mut dyn Write) -> Result<()> {
+    let names = function_names(module);
+    let name = |idx: u32| {
+        names.get(&idx).map_or_else(
+            || format!("func[{}]", idx),
+            |name| format!("func[{}] <{}>", idx, name),
+        )
+    };
+    let types = module
+        .type_section()
+        .map_or(&[][..], |section| section.types());
+    let signature = |type_ref: u32| match types.get(type_ref as usize) {
+        Some(Type::Function(ty)) => format!("type[{}] {}", type_ref, format_signature(ty)),
+        None => format!("type[{}] <invalid>", type_ref),
+    };
+    let mut function_types = Vec::new();
+
+    for section in module.sections() {
+        match section {
+            Section::Type(section) => {
+                writeln!(writer, "Type section:")?;
+                for (idx, ty) in section.types().iter().enumerate() {
+                    let Type::Function(ty) = ty;
+                    writeln!(writer, "  type[{}] {}", idx, format_signature(ty))?;
+                }
+            }
+            Section::Import(section) => {
+                writeln!(writer, "Import section:")?;
+                let (mut functions, mut tables, mut memories, mut globals) = (0, 0, 0, 0);
+                for entry in section.entries() {
+                    let field = format!("{}.{}", entry.module(), entry.field());
+                    match entry.external() {
+                        External::Function(type_ref) => {
+                            writeln!(
+                                writer,
+                                "  {} = {}: {}",
+                                name(functions),
+                                field,
+                                signature(*type_ref)
+                            )?;
+                            function_types.push(*type_ref);
+                            functions += 1;
+                        }
+                        External::Table(table) => {
+                            writeln!(
+                                writer,
+                                "  table[{}] = {}: {}",
+                                tables,
+                                field,
+                                format_table(table)
+                            )?;
+                            tables += 1;
+                        }
+                        External::Memory(memory) => {
+                            writeln!(
+                                writer,
+                                "  memory[{}] = {}: {}",
+                                memories,
+                                field,
+                                format_memory(memory)
+                            )?;
+                            memories += 1;
+                        }
+                        External::Global(global) => {
+                            writeln!(
+                                writer,
+                                "  global[{}] = {}: {}",
+                                globals,
+                                field,
+                                format_global(global)
+                            )?;
+                            globals += 1;
+                        }
+                    }
+                }
+            }
+            Section::Function(section) => {
+                writeln!(writer, "Function section:")?;
+                for func in section.entries() {
+                    let idx = function_types.len() as u32;
+                    writeln!(writer, "  {}: {}", name(idx), signature(func.type_ref()))?;
+                    function_types.push(func.type_ref());
+                }
+            }
+            Section::Table(section) => {
+                writeln!(writer, "Table section:")?;
+                let base = module.import_count(ImportCountType::Table);
+                for (idx, table) in section.entries().iter().enumerate() {
+                    writeln!(writer, "  table[{}]: {}", base + idx, format_table(table))?;
+                }
+            }
+            Section::Memory(section) => {
+                writeln!(writer, "Memory section:")?;
+                let base = module.import_count(ImportCountType::Memory);
+                for (idx, memory) in section.entries().iter().enumerate() {
+                    writeln!(
+                        writer,
+                        "  memory[{}]: {}",
+                        base + idx,
+                        format_memory(memory)
+                    )?;
+                }
+            }
+            Section::Global(section) => {
+                writeln!(writer, "Global section:")?;
+                let base = module.import_count(ImportCountType::Global);
+                for (idx, global) in section.entries().iter().enumerate() {
+                    writeln!(
+                        writer,
+                        "  global[{}]: {} = {}",
+                        base + idx,
+                        format_global(global.global_type()),
+                        format_init_expr(Some(global.init_expr()))
+                    )?;
+                }
+            }
+            Section::Export(section) => {
+                writeln!(writer, "Export section:")?;
+                for entry in section.entries() {
+                    let target = match entry.internal() {
+                        Internal::Function(idx) => name(*idx),
+                        Internal::Table(idx) => format!("table[{}]", idx),
+                        Internal::Memory(idx) => format!("memory[{}]", idx),
+                        Internal::Global(idx) => format!("global[{}]", idx),
+                    };
+                    writeln!(writer, "  \"{}\" -> {}", entry.field(), target)?;
+                }
+            }
+            Section::Start(idx) => {
+                writeln!(writer, "Start section:")?;
+                writeln!(writer, "  {}", name(*idx))?;
+            }
+            Section::Element(section) => {
+                writeln!(writer, "Element section:")?;
+                for (idx, segment) in section.entries().iter().enumerate() {
+                    writeln!(
+                        writer,
+                        "  segment[{}]: table[{}] at {}",
+                        idx,
+                        segment.index(),
+                        format_init_expr(segment.offset().as_ref())
+                    )?;
+                    for (offset, member) in segment.members().iter().enumerate() {
+                        writeln!(writer, "    [{}] {}", offset, name(*member))?;
+                    }
+                }
+            }
+            Section::DataCount(count) => {
+                writeln!(writer, "Data count section:")?;
+                writeln!(writer, "  {} segments", count)?;
+            }
+            Section::Code(section) => {
+                writeln!(writer, "Code section:")?;
+                for (i, body) in section.bodies().iter().enumerate() {
+                    let idx = module.import_count(ImportCountType::Function) + i;
+                    let type_ref = function_types.get(idx).copied().unwrap_or_default();
+                    writeln!(writer, "  {}: {}", name(idx as u32), signature(type_ref))?;
+                    for local in body.locals() {
+                        writeln!(
+                            writer,
+                            "    local {} x {}",
+                            local.value_type(),
+                            local.count()
+                        )?;
+                    }
+
+                    let mut depth = 2;
+                    for instruction in body.code().elements() {
+                        if matches!(instruction, Instruction::End | Instruction::Else) {
+                            depth -= 1;
+                        }
+                        if depth > 1 {
+                            writeln!(writer, "{:indent$}{}", "", instruction, indent = depth * 2)?;
+                        }
+                        if matches!(
+                            instruction,
+                            Instruction::Block(_)
+                                | Instruction::Loop(_)
+                                | Instruction::If(_)
+                                | Instruction::Else
+                        ) {
+                            depth += 1;
+                        }
+                    }
+                }
+            }
+            Section::Data(section) => {
+                writeln!(writer, "Data section:")?;
+                for (idx, segment) in section.entries().iter().enumerate() {
+                    writeln!(
+                        writer,
+                        "  segment[{}]: memory[{}] at {}, {} bytes",
+                        idx,
+                        segment.index(),
+                        format_init_expr(segment.offset().as_ref()),
+                        segment.value().len()
+                    )?;
+                }
+            }
+            Section::Custom(section) => {
+                writeln!(writer, "Custom section \"{}\":", section.name())?;
+                writeln!(writer, "  {} bytes", section.payload().len())?;
+            }
+            Section::Name(section) => {
+                writeln!(writer, "Custom section \"name\":")?;
+                writeln!(
+                    writer,
+                    "  {} function names",
+                    section
+                        .functions()
+                        .map_or(0, |functions| functions.names().len())
+                )?;
+            }
+            Section::Reloc(section) => {
+                writeln!(writer, "Custom section \"{}\":", section.name())?;
+                writeln!(writer, "  {} relocations", section.entries().len())?;
+            }
+            Section::Unparsed { id, payload } => {
+                writeln!(writer, "Unknown section {}:", id)?;
+                writeln!(writer, "  {} bytes", payload.len())?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Disassembles the whole Wasm module into a textual listing, which is written to `output` if
+/// given, otherwise to stdout.
+///
+/// This is a fallback of `wasm2wat` and `wasm-objdump` when they are not available.
+pub(crate) fn execute_disassemble(wasm_path: &Path, output: Option<&Path>) -> Result<()> {
+    let module = parity_wasm::deserialize_file(wasm_path)
+        .context(format!("Loading Wasm file '{}'", wasm_path.display()))?;
+    let module = module.parse_names().unwrap_or_else(|(_, module)| module);
+
+    match output {
+        Some(output) => {
+            let file = fs::File::create(output)
+                .context(format!("Creating file '{}'", output.display()))?;
+            let mut writer = BufWriter::new(file);
+            disassemble(&module, &mut writer)?;
+            writer
+                .flush()
+                .context(format!("Writing file '{}'", output.display()))?;
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut stdout = stdout.lock();
+            disassemble(&module, &mut stdout)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::build::build_environment;
+use crate::{utils, workspace::ManifestPath, AnalysisBehavior};
+use anyhow::Result;
+
+/// Prints environment variables set during building the contract in shell-source format, so that
+/// the build conditions can be reproduced manually by `eval "$(cargo liquid env)"`.
+pub(crate) fn execute_env(
+    manifest_path: &ManifestPath,
+    analysis_behavior: AnalysisBehavior,
+) -> Result<()> {
+    for (key, value) in build_environment(manifest_path, analysis_behavior)? {
+        println!("export {}={}", key, utils::shell_quote(&value));
+    }
+    Ok(())
+}
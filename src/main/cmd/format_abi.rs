@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{fs, path::Path};
+
+/// A parameter of an ABI entry, only fields required by the ABI are declared.
+#[derive(Deserialize)]
+struct AbiParam {
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(default)]
+    components: Vec<AbiParam>,
+}
+
+/// An entry of the ABI, only fields required by the ABI are declared.
+#[derive(Deserialize)]
+struct AbiEntry {
+    #[serde(rename = "type")]
+    ty: String,
+    name: Option<String>,
+    #[serde(default)]
+    inputs: Vec<AbiParam>,
+    #[serde(default)]
+    outputs: Vec<AbiParam>,
+}
+
+/// The ABI of a contract, i.e. a list of entries.
+type Abi = Vec<AbiEntry>;
+
+fn validate_param(param: &AbiParam, entry: usize) -> Result<()> {
+    if param.ty.starts_with("tuple") {
+        if param.components.is_empty() {
+            anyhow::bail!("entry {}: tuple parameter has no components", entry);
+        }
+    } else if !param.components.is_empty() {
+        anyhow::bail!(
+            "entry {}: parameter of type `{}` should not have components",
+            entry,
+            param.ty
+        );
+    }
+    param
+        .components
+        .iter()
+        .try_for_each(|component| validate_param(component, entry))
+}
+
+fn validate_abi(abi: &Abi) -> Result<()> {
+    for (idx, entry) in abi.iter().enumerate() {
+        match entry.ty.as_str() {
+            "function" | "event" => {
+                if entry.name.as_deref().unwrap_or("").is_empty() {
+                    anyhow::bail!("entry {}: {} must have a name", idx, entry.ty);
+                }
+            }
+            "constructor" | "fallback" => (),
+            ty => anyhow::bail!("entry {}: unknown entry type `{}`", idx, ty),
+        }
+        entry
+            .inputs
+            .iter()
+            .chain(&entry.outputs)
+            .try_for_each(|param| validate_param(param, idx))?;
+    }
+    Ok(())
+}
+
+/// Pretty-prints the ABI file in place. If `check` is set, the file is left untouched and an error
+/// is returned if it's not pretty-printed.
+pub(crate) fn execute_format_abi(abi_path: &Path, check: bool) -> Result<()> {
+    let content = fs::read_to_string(abi_path)
+        .context(format!("Reading ABI file '{}'", abi_path.display()))?;
+    let abi: Value = serde_json::from_str(&content)
+        .context(format!("Parsing ABI file '{}'", abi_path.display()))?;
+    Abi::deserialize(&abi)
+        .map_err(anyhow::Error::from)
+        .and_then(|entries| validate_abi(&entries))
+        .context(format!("Validating ABI file '{}'", abi_path.display()))?;
+
+    let formatted = serde_json::to_string_pretty(&abi)? + "\n";
+    if formatted == content {
+        return Ok(());
+    }
+    if check {
+        anyhow::bail!("ABI file '{}' is not formatted", abi_path.display());
+    }
+    fs::write(abi_path, formatted).context(format!("Writing ABI file '{}'", abi_path.display()))
+}
@@ -0,0 +1,117 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    build::get_name_and_selector,
+    compare_gas::{load_metered, measure},
+};
+use anyhow::{Context, Result};
+use serde_json::{Map, Value};
+use std::{
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// How many random inputs are executed when a percentile is requested.
+const SAMPLES: usize = 100;
+
+/// A xorshift generator, which is good enough for producing synthetic call data.
+struct XorShift(u64);
+
+impl XorShift {
+    fn new() -> Self {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |duration| duration.as_nanos() as u64);
+        XorShift(seed | 1)
+    }
+
+    fn fill(&mut self, buf: &mut [u8]) {
+        for byte in buf {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            *byte = self.0 as u8;
+        }
+    }
+}
+
+/// Looks up the selector of `function` in the ABI, calculated with SM3 if `use_gm` is set and
+/// with Keccak-256 otherwise.
+fn function_selector(abi_path: &Path, function: &str, use_gm: bool) -> Result<u32> {
+    let content = fs::read_to_string(abi_path)
+        .context(format!("Reading ABI file '{}'", abi_path.display()))?;
+    let entries: Vec<Map<String, Value>> = serde_json::from_str(&content)
+        .context(format!("Parsing ABI file '{}'", abi_path.display()))?;
+    let entry = entries
+        .iter()
+        .find(|entry| {
+            entry.get("type").and_then(Value::as_str) == Some("function")
+                && entry.get("name").and_then(Value::as_str) == Some(function)
+                && entry.get("inputs").and_then(Value::as_array).is_some()
+        })
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "the ABI '{}' contains no function `{}`",
+                abi_path.display(),
+                function
+            )
+        })?;
+    let (_, selector) = get_name_and_selector(entry, use_gm)
+        .context(format!("Reading ABI file '{}'", abi_path.display()))?;
+    Ok(selector)
+}
+
+/// Estimates a gas limit for transactions calling `function` of the contract, by executing the
+/// contract in a mocked environment with call data made of the selector of `function` in the ABI
+/// followed by `args_size` random bytes.
+///
+/// If `percentile` is given, the contract is executed with multiple random inputs and the gas
+/// used by the specified percentile of them is taken. Gas used until an execution reverts is
+/// counted as well, as it's still charged on chain, while a trapping execution fails the
+/// estimation. A safety margin of 20% is added to the measured gas.
+pub(crate) fn execute_gas_limit(
+    wasm_path: &Path,
+    abi_path: &Path,
+    function: &str,
+    args_size: usize,
+    percentile: Option<u8>,
+    use_gm: bool,
+) -> Result<u64> {
+    if let Some(percentile) = percentile {
+        if percentile == 0 || percentile > 100 {
+            anyhow::bail!("percentile must be between 1 and 100");
+        }
+    }
+
+    let selector = function_selector(abi_path, function, use_gm)?;
+    let module = load_metered(wasm_path)?;
+
+    let mut rng = XorShift::new();
+    let samples = if percentile.is_some() { SAMPLES } else { 1 };
+    let mut gas = (0..samples)
+        .map(|_| {
+            let mut args = vec![0u8; args_size];
+            rng.fill(&mut args);
+            let call_data = selector.to_le_bytes().iter().copied().chain(args).collect();
+            measure(&module, call_data).map(|(_, gas)| gas)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    gas.sort_unstable();
+
+    let rank = percentile.map_or(1, |percentile| {
+        (samples * percentile as usize).div_ceil(100)
+    });
+    let measured = gas[rank.max(1) - 1];
+    Ok(measured + measured / 5)
+}
@@ -0,0 +1,208 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{utils, workspace::ManifestPath};
+use anyhow::{Context, Error, Result};
+use cargo_metadata::{DependencyKind, PackageId};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    path::Path,
+    str::FromStr,
+};
+
+/// Formats of the dependency graph.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum GraphFormat {
+    /// The DOT language of Graphviz.
+    Dot,
+    Json,
+    /// The flowchart syntax of Mermaid, which is rendered by e.g. GitHub in Markdown.
+    Mermaid,
+}
+
+impl FromStr for GraphFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "dot" => Ok(GraphFormat::Dot),
+            "json" => Ok(GraphFormat::Json),
+            "mermaid" => Ok(GraphFormat::Mermaid),
+            _ => anyhow::bail!(
+                "unknown graph format `{}`, must be `dot`, `json` or `mermaid`",
+                s
+            ),
+        }
+    }
+}
+
+/// A package in the dependency graph, annotated with the size of its sources.
+#[derive(Serialize)]
+struct DependencyNode {
+    id: String,
+    name: String,
+    version: String,
+    /// The number of `*.rs` files of the package.
+    files: usize,
+    /// The total number of lines of `*.rs` files of the package.
+    lines: usize,
+}
+
+impl DependencyNode {
+    fn label(&self) -> String {
+        format!("{} {}", self.name, self.version)
+    }
+
+    fn summary(&self) -> String {
+        format!("{} files, {} lines", self.files, self.lines)
+    }
+}
+
+#[derive(Serialize)]
+struct DependencyGraph {
+    nodes: Vec<DependencyNode>,
+    /// Edges from dependents to dependencies, by indices in `nodes`.
+    edges: Vec<(usize, usize)>,
+}
+
+/// Counts `*.rs` files under the directory of the package and their lines.
+fn count_sources(package_dir: &Path) -> Result<(usize, usize)> {
+    let files = utils::rust_source_files(package_dir)?;
+    let mut lines = 0;
+    for file in &files {
+        let content = fs::read(file).context(format!("Reading '{}'", file.display()))?;
+        lines += count_lines(&content);
+    }
+    Ok((files.len(), lines))
+}
+
+fn count_lines(content: &[u8]) -> usize {
+    let newlines = content.iter().filter(|byte| **byte == b'\n').count();
+    // The last line may not be terminated.
+    match content.last() {
+        Some(b'\n') | None => newlines,
+        Some(_) => newlines + 1,
+    }
+}
+
+/// Builds the graph of dependencies reachable from the root package. Only normal dependencies
+/// are followed, since build and dev dependencies don't end up in the Wasm bytecode.
+fn build_graph(manifest_path: &ManifestPath) -> Result<DependencyGraph> {
+    let (metadata, root_package_id) = utils::get_cargo_metadata(manifest_path)?;
+    let resolve = metadata
+        .resolve
+        .as_ref()
+        .context("Cannot resolve the dependency graph")?;
+    let resolved = resolve
+        .nodes
+        .iter()
+        .map(|node| (&node.id, node))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut indices = BTreeMap::<&PackageId, usize>::new();
+    let mut graph = DependencyGraph {
+        nodes: Vec::new(),
+        edges: Vec::new(),
+    };
+    let mut queue = VecDeque::new();
+    queue.push_back(&root_package_id);
+    indices.insert(&root_package_id, 0);
+    while let Some(id) = queue.pop_front() {
+        let package = &metadata[id];
+        let package_dir = package
+            .manifest_path
+            .parent()
+            .expect("the manifest path is a file path so has a parent");
+        let (files, lines) = count_sources(package_dir)?;
+        graph.nodes.push(DependencyNode {
+            id: id.repr.clone(),
+            name: package.name.clone(),
+            version: package.version.to_string(),
+            files,
+            lines,
+        });
+
+        let from = indices[id];
+        let deps = resolved.get(id).map(|node| node.deps.as_slice());
+        for dep in deps.unwrap_or_default() {
+            // `dep_kinds` is empty if cargo is older than 1.41, when all kinds were mixed.
+            let is_normal = dep.dep_kinds.is_empty()
+                || dep
+                    .dep_kinds
+                    .iter()
+                    .any(|info| info.kind == DependencyKind::Normal);
+            if !is_normal {
+                continue;
+            }
+            let next = indices.len();
+            let to = *indices.entry(&dep.pkg).or_insert_with(|| {
+                queue.push_back(&dep.pkg);
+                next
+            });
+            graph.edges.push((from, to));
+        }
+    }
+    Ok(graph)
+}
+
+fn to_dot(graph: &DependencyGraph) -> String {
+    let mut dot = String::from("digraph dependencies {\n    node [shape=box];\n");
+    for (idx, node) in graph.nodes.iter().enumerate() {
+        dot.push_str(&format!(
+            "    n{} [label=\"{}\\n{}\"];\n",
+            idx,
+            node.label(),
+            node.summary()
+        ));
+    }
+    for (from, to) in &graph.edges {
+        dot.push_str(&format!("    n{} -> n{};\n", from, to));
+    }
+    dot.push('}');
+    dot
+}
+
+fn to_mermaid(graph: &DependencyGraph) -> String {
+    let mut mermaid = String::from("graph TD\n");
+    for (idx, node) in graph.nodes.iter().enumerate() {
+        mermaid.push_str(&format!(
+            "    n{}[\"{}<br/>{}\"]\n",
+            idx,
+            node.label(),
+            node.summary()
+        ));
+    }
+    for (from, to) in &graph.edges {
+        mermaid.push_str(&format!("    n{} --> n{}\n", from, to));
+    }
+    mermaid.pop();
+    mermaid
+}
+
+/// Prints the graph of transitive dependencies of the contract, where every package is annotated
+/// with the number of its source files and lines, to find dependencies contributing
+/// disproportionate code to the contract.
+pub(crate) fn execute_graph_deps(
+    manifest_path: ManifestPath,
+    output_format: GraphFormat,
+) -> Result<()> {
+    let graph = build_graph(&manifest_path)?;
+    let output = match output_format {
+        GraphFormat::Dot => to_dot(&graph),
+        GraphFormat::Json => serde_json::to_string_pretty(&graph)?,
+        GraphFormat::Mermaid => to_mermaid(&graph),
+    };
+    println!("{}", output);
+    Ok(())
+}
@@ -0,0 +1,64 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::utils;
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::{fs, path::Path};
+
+/// Queries the bytecode of the contract deployed at `contract_address` via the `getCode` JSON-RPC
+/// method of the node.
+pub(crate) fn get_code(rpc_url: &str, contract_address: &str) -> Result<Vec<u8>> {
+    let result = utils::call_rpc(
+        rpc_url,
+        "getCode",
+        serde_json::json!([utils::DEFAULT_GROUP, "", contract_address]),
+    )?;
+    let code = result
+        .as_str()
+        .ok_or_else(|| anyhow::anyhow!("unexpected result of `getCode`: {}", result))?;
+    let code = utils::decode_hex(code)?;
+    if code.is_empty() {
+        anyhow::bail!("no contract is deployed at `{}`", contract_address);
+    }
+    Ok(code)
+}
+
+/// Checks whether the contract deployed at `contract_address` has the same bytecode as the local
+/// Wasm file, by comparing SHA-256 digests of them.
+pub(crate) fn execute_hash_check(
+    wasm_path: &Path,
+    rpc_url: &str,
+    contract_address: &str,
+) -> Result<()> {
+    let local_code =
+        fs::read(wasm_path).context(format!("Reading Wasm file '{}'", wasm_path.display()))?;
+    let local_hash = utils::sha256_hex(&local_code);
+    let remote_hash = utils::sha256_hex(&get_code(rpc_url, contract_address)?);
+
+    if local_hash == remote_hash {
+        println!("{}", "MATCH".green().bold());
+    } else {
+        println!("{}", "MISMATCH".bright_red().bold());
+    }
+    println!("{: >6}: {}", "Local", local_hash);
+    println!("{: >6}: {}", "Remote", remote_hash);
+
+    if local_hash != remote_hash {
+        anyhow::bail!(
+            "the contract deployed at `{}` doesn't match '{}'",
+            contract_address,
+            wasm_path.display()
+        );
+    }
+    Ok(())
+}
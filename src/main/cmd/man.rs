@@ -0,0 +1,143 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use std::{
+    fmt::Write,
+    fs, iter,
+    path::{Path, PathBuf},
+};
+use structopt::clap::{App, ErrorKind};
+
+/// Examples listed in the `EXAMPLES` section, as pairs of a description and a command line.
+const EXAMPLES: [(&str, &str); 5] = [
+    (
+        "Create a new contract project:",
+        "cargo liquid new contract my_contract",
+    ),
+    (
+        "Build the contract in the current directory:",
+        "cargo liquid build",
+    ),
+    (
+        "Build the contract with the Chinese national cryptography standards:",
+        "cargo liquid build -g",
+    ),
+    (
+        "Post-process Wasm bytecode built elsewhere:",
+        "cargo liquid optimize prebuilt.wasm -o optimized.wasm",
+    ),
+    (
+        "Show the imports of the bytecode and check them against a node:",
+        "cargo liquid show-imports my_contract.wasm --compare-with-node http://127.0.0.1:8545",
+    ),
+];
+
+/// Escapes text for roff, so that backslashes, hyphens and leading control characters are
+/// printed literally.
+fn escape(text: &str) -> String {
+    let escaped = text.replace('\\', "\\e").replace('-', "\\-");
+    if escaped.starts_with(['.', '\''].as_ref()) {
+        format!("\\&{}", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// Writes `help` as a preformatted block, clap already lays it out for a terminal.
+fn write_help(page: &mut String, help: &str) {
+    page.push_str(".nf\n");
+    for line in help.trim_end().lines() {
+        page.push_str(&escape(line));
+        page.push('\n');
+    }
+    page.push_str(".fi\n");
+}
+
+/// Width the help is wrapped at, so that the page doesn't depend on the terminal it's generated
+/// in.
+const TERM_WIDTH: usize = 100;
+
+/// Gets the long help of the subcommand `args` by parsing `args` followed by `--help`, clap 2
+/// provides no public way to look up subcommands of an `App` otherwise.
+fn long_help(app: &App<'_, '_>, args: &[&str]) -> Result<String> {
+    let argv = iter::once("cargo")
+        .chain(args.iter().copied())
+        .chain(iter::once("--help"));
+    match app
+        .clone()
+        .set_term_width(TERM_WIDTH)
+        .get_matches_from_safe(argv)
+    {
+        Err(e) if e.kind == ErrorKind::HelpDisplayed => Ok(e.message),
+        Err(e) => anyhow::bail!("{}", e),
+        Ok(_) => anyhow::bail!("`cargo {} --help` printed no help", args.join(" ")),
+    }
+}
+
+/// Names of the subcommands listed in the `SUBCOMMANDS` section of `help`. Every entry starts
+/// with the name indented by 4 spaces, wrapped descriptions are indented further.
+fn subcommand_names(help: &str) -> Vec<&str> {
+    help.lines()
+        .skip_while(|line| *line != "SUBCOMMANDS:")
+        .skip(1)
+        .take_while(|line| !line.is_empty())
+        .filter_map(|line| line.strip_prefix("    "))
+        .filter(|line| !line.starts_with(' '))
+        .filter_map(|line| line.split_whitespace().next())
+        .filter(|name| *name != "help")
+        .collect()
+}
+
+/// Renders the man page of `cargo liquid` from the definition of its command line, so that it
+/// never gets out of sync with `--help`.
+fn render(app: &App<'_, '_>) -> Result<String> {
+    let help = long_help(app, &["liquid"])?;
+    // The help starts with the name and version, followed by the description of the command.
+    let about = help.lines().nth(1).unwrap_or_default();
+
+    let mut page = String::new();
+    writeln!(
+        page,
+        ".TH CARGO\\-LIQUID 1 \"\" \"cargo\\-liquid {}\" \"User Commands\"",
+        escape(env!("CARGO_PKG_VERSION"))
+    )?;
+    page.push_str(".SH NAME\n");
+    writeln!(page, "cargo\\-liquid \\- {}", escape(about))?;
+    page.push_str(".SH SYNOPSIS\n.B cargo liquid\n\\fICOMMAND\\fR [\\fIOPTIONS\\fR]\n");
+    page.push_str(".SH DESCRIPTION\n");
+    write_help(&mut page, &help);
+
+    page.push_str(".SH COMMANDS\n");
+    for name in subcommand_names(&help) {
+        writeln!(page, ".SS \"cargo liquid {}\"", escape(name))?;
+        write_help(&mut page, &long_help(app, &["liquid", name])?);
+    }
+
+    page.push_str(".SH EXAMPLES\n");
+    for (description, command) in EXAMPLES.iter() {
+        writeln!(page, ".TP\n{}\n.B {}", escape(description), escape(command))?;
+    }
+    page.push_str(".SH SEE ALSO\n.BR cargo (1)\n");
+    Ok(page)
+}
+
+/// Generates the man page of `cargo liquid` from `app` and writes it to `cargo-liquid.1` in
+/// `output_dir`, returns the path of the page.
+pub(crate) fn execute_man(app: &App<'_, '_>, output_dir: &Path) -> Result<PathBuf> {
+    let page = render(app)?;
+    fs::create_dir_all(output_dir)
+        .context(format!("Creating directory '{}'", output_dir.display()))?;
+    let path = output_dir.join("cargo-liquid.1");
+    fs::write(&path, page).context(format!("Writing man page '{}'", path.display()))?;
+    Ok(path)
+}
@@ -10,8 +10,73 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod bench_size;
 mod build;
+mod build_cache;
+mod build_inputs;
+mod build_report;
+mod call_graph;
+mod check_abi_compat;
+mod client_crate;
+mod compare_gas;
+mod coverage;
+mod diff;
+mod disassemble;
+mod env;
+mod format_abi;
+mod gas_limit;
+mod graph_deps;
+mod hash_check;
+mod man;
 mod new;
+mod optimize;
+mod process_tree;
+mod prof;
+mod publish;
+mod query_node;
 mod rename;
+mod report_floats;
+mod run_local;
+mod semver_check;
+mod show_imports;
+mod size_profile;
+mod strip_names;
+mod strip_unreachable;
+mod test;
+mod validate_manifest;
+mod verify_bytecode;
 
-pub(crate) use self::{build::execute_build, new::execute_new, rename::execute_rename};
+pub(crate) use self::{
+    bench_size::execute_bench_size,
+    build::{
+        execute_build, Artifact, BuildOptions, CargoConfig, NameTemplate, OptimizationLevel,
+        OutputFormat, PanicStrategy, XargoArgs,
+    },
+    check_abi_compat::execute_check_abi_compat,
+    compare_gas::execute_compare_gas,
+    coverage::execute_coverage,
+    diff::execute_diff,
+    disassemble::execute_disassemble,
+    env::execute_env,
+    format_abi::execute_format_abi,
+    gas_limit::execute_gas_limit,
+    graph_deps::{execute_graph_deps, GraphFormat},
+    hash_check::execute_hash_check,
+    man::execute_man,
+    new::execute_new,
+    optimize::{execute_optimize, OptimizeOptions},
+    prof::execute_prof,
+    publish::execute_publish,
+    query_node::execute_query_node,
+    rename::execute_rename,
+    report_floats::execute_report_floats,
+    run_local::{execute_run_local, Value},
+    semver_check::{execute_semver_check, Compatibility},
+    show_imports::{execute_show_imports, print_imports},
+    size_profile::execute_size_profile,
+    strip_names::execute_strip_names,
+    strip_unreachable::execute_strip_unreachable,
+    test::execute_test,
+    validate_manifest::execute_validate_manifest,
+    verify_bytecode::execute_verify_bytecode,
+};
@@ -0,0 +1,98 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::build::{
+    missing_exports, process_module, run_wasm_opt, write_wasm, OptimizationLevel, ProcessOptions,
+    DEFAULT_ENTRY_POINTS, REQUIRED_EXPORTS,
+};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::{fs, path::Path};
+
+/// Sizes of the Wasm bytecode in bytes after every step of [`execute_optimize`].
+pub(crate) struct OptimizedSizes {
+    pub original: u64,
+    pub processed: u64,
+    /// `None` if wasm-opt is skipped or not installed.
+    pub optimized: Option<u64>,
+}
+
+/// Options of [`execute_optimize`].
+pub(crate) struct OptimizeOptions<'a> {
+    /// Exports kept by tree-shaking, the default entry points are used if empty.
+    pub entry_points: &'a [String],
+    pub keep_custom_sections: bool,
+    pub legacy_wasm: bool,
+    pub skip_wasm_opt: bool,
+    pub optimization_level: Option<OptimizationLevel>,
+    pub wasm_opt_path: Option<&'a Path>,
+}
+
+/// Post-processes the Wasm bytecode at `input` the same way as `cargo liquid build` does, i.e.
+/// tree-shaking, stripping sections not required by the chain and running wasm-opt, and writes
+/// the result to `output`. This is meant for bytecode which is built elsewhere.
+pub(crate) fn execute_optimize(
+    input: &Path,
+    output: &Path,
+    opts: OptimizeOptions<'_>,
+) -> Result<OptimizedSizes> {
+    let original = fs::metadata(input)
+        .context(format!("Reading Wasm file '{}'", input.display()))?
+        .len();
+    let module = parity_wasm::deserialize_file(input)
+        .context(format!("Loading Wasm file '{}'", input.display()))?;
+    let used_exports = if opts.entry_points.is_empty() {
+        DEFAULT_ENTRY_POINTS
+            .iter()
+            .map(|export| export.to_string())
+            .collect()
+    } else {
+        opts.entry_points.to_vec()
+    };
+    let names = used_exports.iter().map(String::as_str).collect::<Vec<_>>();
+    for name in missing_exports(&module, &names) {
+        eprintln!(
+            "{}",
+            format!(
+                "entry point `{}` is not found in the Wasm module, it's ignored",
+                name
+            )
+            .bright_yellow()
+        );
+    }
+    let module = process_module(
+        module,
+        ProcessOptions {
+            used_exports: used_exports
+                .into_iter()
+                .chain(REQUIRED_EXPORTS.iter().map(|export| export.to_string()))
+                .collect(),
+            tree_shaking: true,
+            keep_custom_sections: opts.keep_custom_sections,
+            strip_data_count: opts.legacy_wasm,
+        },
+    )?;
+    write_wasm(module, output)?;
+    let processed = fs::metadata(output)?.len();
+
+    let optimized = if opts.skip_wasm_opt {
+        None
+    } else {
+        run_wasm_opt(output, opts.optimization_level, opts.wasm_opt_path, false)?
+            .map(|(_, optimized)| optimized)
+    };
+    Ok(OptimizedSizes {
+        original,
+        processed,
+        optimized,
+    })
+}
@@ -0,0 +1,165 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    process::{Command, Stdio},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+/// How often the memory usage of the process tree is sampled.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Lists the current process and all its descendants, e.g. cargo and rustc spawned by xargo or
+/// wasm-opt. Only the current process is listed on platforms other than Linux.
+fn process_tree() -> Vec<u32> {
+    let mut processes = vec![std::process::id()];
+    if !cfg!(target_os = "linux") {
+        return processes;
+    }
+
+    let parents = std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.ok()?.file_name().to_str()?.parse::<u32>().ok()?;
+            let stat = std::fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+            // The command name in parentheses may contain spaces, the parent pid is the second
+            // field after it.
+            let (_, fields) = stat.rsplit_once(')')?;
+            let ppid = fields.split_whitespace().nth(1)?.parse::<u32>().ok()?;
+            Some((pid, ppid))
+        })
+        .collect::<Vec<_>>();
+    let mut idx = 0;
+    while idx < processes.len() {
+        let parent = processes[idx];
+        processes.extend(
+            parents
+                .iter()
+                .filter(|(_, ppid)| *ppid == parent)
+                .map(|(pid, _)| *pid),
+        );
+        idx += 1;
+    }
+    processes
+}
+
+/// Whether `kill_child_processes` is supported on the current platform.
+pub(crate) const CAN_KILL_CHILD_PROCESSES: bool = cfg!(any(target_os = "linux", windows));
+
+/// Lists direct children of the current process on Windows, which has no `/proc` to read them
+/// from.
+fn windows_child_processes() -> Vec<u32> {
+    let output = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command"])
+        .arg(format!(
+            "(Get-CimInstance Win32_Process -Filter 'ParentProcessId={}').ProcessId",
+            std::process::id()
+        ))
+        .stderr(Stdio::null())
+        .output();
+    output
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter_map(|line| line.trim().parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Kills all descendants of the current process. Nothing is killed on platforms other than
+/// Linux and Windows, see [`CAN_KILL_CHILD_PROCESSES`].
+pub(crate) fn kill_child_processes() {
+    if cfg!(windows) {
+        // `/T` kills the whole tree below each child, e.g. rustc spawned by cargo.
+        for pid in windows_child_processes() {
+            let _ = Command::new("taskkill")
+                .args(["/T", "/F", "/PID", &pid.to_string()])
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status();
+        }
+        return;
+    }
+    for pid in &process_tree()[1..] {
+        let _ = Command::new("kill")
+            .arg("-KILL")
+            .arg(pid.to_string())
+            .stderr(Stdio::null())
+            .status();
+    }
+}
+
+/// The resident set size of a process in bytes, read from `VmRSS` of `/proc/<pid>/status`.
+fn resident_size(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kilobytes = line.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+    Some(kilobytes * 1024)
+}
+
+/// Samples the total resident memory of the process tree on a background thread, and keeps
+/// the peak of it.
+pub(crate) struct MemorySampler {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<u64>>,
+}
+
+impl MemorySampler {
+    /// Starts sampling, returns `None` on platforms where the memory usage can't be sampled.
+    pub fn start() -> Option<Self> {
+        if !cfg!(target_os = "linux") {
+            return None;
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = thread::spawn({
+            let stop = stop.clone();
+            move || {
+                let mut peak = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    let total = process_tree()
+                        .into_iter()
+                        .filter_map(resident_size)
+                        .sum::<u64>();
+                    peak = peak.max(total);
+                    thread::sleep(SAMPLE_INTERVAL);
+                }
+                peak
+            }
+        });
+        Some(MemorySampler {
+            stop,
+            handle: Some(handle),
+        })
+    }
+
+    /// Stops sampling, returns the peak resident memory in bytes.
+    pub fn finish(mut self) -> u64 {
+        self.stop.store(true, Ordering::Relaxed);
+        self.handle
+            .take()
+            .and_then(|handle| handle.join().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MemorySampler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
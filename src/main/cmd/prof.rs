@@ -0,0 +1,215 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    runtime::{MockEnvironment, CALL_ENTRY_POINT},
+    utils,
+};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use parity_wasm::elements::{
+    External, GlobalEntry, GlobalSection, GlobalType, ImportCountType, InitExpr, Instruction,
+    Internal, Module, Section, ValueType,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{BufWriter, Write},
+    path::Path,
+};
+use wasmi::RuntimeValue;
+
+/// A counter injected into the module, which is stored in a mutable global.
+struct Counter {
+    caller: u32,
+    /// The function called at the call site, or `None` if the counter records how many times
+    /// `caller` itself is entered.
+    callee: Option<u32>,
+    global: u32,
+}
+
+/// Instructions which increase the counter stored in `global` by one.
+fn increment(global: u32) -> [Instruction; 4] {
+    [
+        Instruction::GetGlobal(global),
+        Instruction::I64Const(1),
+        Instruction::I64Add,
+        Instruction::SetGlobal(global),
+    ]
+}
+
+/// Injects a counter at the entry of every function and before every direct call site.
+///
+/// Counters are appended to the end of the global index space, so that no existing index in the
+/// module needs to be remapped.
+fn instrument(mut module: Module) -> Result<(Module, Vec<Counter>)> {
+    let imported_functions = module.import_count(ImportCountType::Function) as u32;
+    let first_global = module.import_count(ImportCountType::Global) as u32
+        + module
+            .global_section()
+            .map_or(0, |section| section.entries().len() as u32);
+    let mut counters: Vec<Counter> = Vec::new();
+    let mut add_counter = |caller, callee| {
+        let global = first_global + counters.len() as u32;
+        counters.push(Counter {
+            caller,
+            callee,
+            global,
+        });
+        global
+    };
+
+    let bodies = match module.code_section_mut() {
+        Some(section) => section.bodies_mut(),
+        None => anyhow::bail!("the Wasm module contains no code"),
+    };
+    for (i, body) in bodies.iter_mut().enumerate() {
+        let caller = imported_functions + i as u32;
+        let instructions = body.code_mut().elements_mut();
+        let original = std::mem::take(instructions);
+        instructions.extend_from_slice(&increment(add_counter(caller, None)));
+
+        let mut call_sites = HashMap::new();
+        for instruction in original {
+            if let Instruction::Call(callee) = instruction {
+                let global = *call_sites
+                    .entry(callee)
+                    .or_insert_with(|| add_counter(caller, Some(callee)));
+                instructions.extend_from_slice(&increment(global));
+            }
+            instructions.push(instruction);
+        }
+    }
+
+    let globals = counters
+        .iter()
+        .map(|_| {
+            GlobalEntry::new(
+                GlobalType::new(ValueType::I64, true),
+                InitExpr::new(vec![Instruction::I64Const(0), Instruction::End]),
+            )
+        })
+        .collect::<Vec<_>>();
+    match module.global_section_mut() {
+        Some(section) => section.entries_mut().extend(globals),
+        None => module
+            .insert_section(Section::Global(GlobalSection::with_entries(globals)))
+            .context("Inserting global section")?,
+    }
+
+    Ok((module, counters))
+}
+
+/// Collects readable names of functions from the name section, exports and imports.
+pub(crate) fn function_names(module: &Module) -> BTreeMap<u32, String> {
+    let mut names = BTreeMap::new();
+
+    if let Some(section) = module.import_section() {
+        let imported_functions = section
+            .entries()
+            .iter()
+            .filter(|entry| matches!(entry.external(), External::Function(_)));
+        for (idx, entry) in imported_functions.enumerate() {
+            names.insert(idx as u32, format!("{}.{}", entry.module(), entry.field()));
+        }
+    }
+
+    if let Some(section) = module.export_section() {
+        for entry in section.entries() {
+            if let Internal::Function(idx) = entry.internal() {
+                names.insert(*idx, entry.field().to_owned());
+            }
+        }
+    }
+
+    if let Some(functions) = module
+        .names_section()
+        .and_then(|section| section.functions())
+    {
+        for (idx, name) in functions.names() {
+            names.insert(idx, rustc_demangle::demangle(name).to_string());
+        }
+    }
+
+    names
+}
+
+/// Runs the `main` entry point of the contract with the given call data in a mocked environment,
+/// and writes how many times every function is called to `output` in callgrind format.
+///
+/// Instrumentation is performed on an in-memory copy of the module, the Wasm file itself is left
+/// untouched.
+pub(crate) fn execute_prof(wasm_path: &Path, call_data: &[u8], output: &Path) -> Result<()> {
+    let module = parity_wasm::deserialize_file(wasm_path)
+        .context(format!("Loading Wasm file '{}'", wasm_path.display()))?;
+    let module = module.parse_names().unwrap_or_else(|(_, module)| module);
+    let names = function_names(&module);
+    let (instrumented, counters) = instrument(module)?;
+
+    let mut env = MockEnvironment::new(call_data.to_vec());
+    let instance = env.instantiate(instrumented)?;
+    let outcome = env.invoke(&instance, CALL_ENTRY_POINT)?;
+    println!(
+        "{} {}, output: 0x{}",
+        "Execution".green().bold(),
+        outcome,
+        utils::encode_hex(env.output())
+    );
+
+    let globals = instance.globals();
+    let count = |counter: &Counter| match globals[counter.global as usize].get() {
+        RuntimeValue::I64(count) => count as u64,
+        _ => unreachable!("counters are always of type i64"),
+    };
+    let name = |idx: u32| {
+        names
+            .get(&idx)
+            .cloned()
+            .unwrap_or_else(|| format!("func[{}]", idx))
+    };
+
+    let file = fs::File::create(output).context(format!("Creating file '{}'", output.display()))?;
+    let mut writer = BufWriter::new(file);
+    let total_calls = counters
+        .iter()
+        .filter(|counter| counter.callee.is_none())
+        .map(count)
+        .sum::<u64>();
+    writeln!(writer, "# callgrind format")?;
+    writeln!(writer, "version: 1")?;
+    writeln!(writer, "creator: cargo-liquid")?;
+    writeln!(writer, "cmd: {} {}", wasm_path.display(), CALL_ENTRY_POINT)?;
+    writeln!(writer, "positions: line")?;
+    writeln!(writer, "events: Calls")?;
+    writeln!(writer, "summary: {}", total_calls)?;
+
+    // Counters of the same function are injected consecutively, with the entry counter first.
+    for counter in counters.iter().filter(|counter| count(counter) > 0) {
+        match counter.callee {
+            None => {
+                writeln!(writer)?;
+                writeln!(writer, "fn={}", name(counter.caller))?;
+                writeln!(writer, "0 {}", count(counter))?;
+            }
+            Some(callee) => {
+                writeln!(writer, "cfn={}", name(callee))?;
+                writeln!(writer, "calls={} 0", count(counter))?;
+                writeln!(writer, "0 {}", count(counter))?;
+            }
+        }
+    }
+    writer
+        .flush()
+        .context(format!("Writing file '{}'", output.display()))?;
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::utils;
+use anyhow::{Context, Result};
+use serde_json::Value;
+use std::{fs, io::Cursor, path::Path};
+
+/// The endpoint of the registry which accepts new contract packages, analogous to
+/// `/api/v1/crates/new` of crates.io.
+const PUBLISH_ENDPOINT: &str = "api/v1/contracts/new";
+
+/// Lists the files in the package, fails if it's not a zip archive containing the Wasm bytecode
+/// and the ABI of the contract.
+fn read_package_entries(package: &[u8], package_path: &Path) -> Result<Vec<String>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(package))
+        .context(format!("Reading package '{}'", package_path.display()))?;
+    let entries = (0..archive.len())
+        .map(|idx| Ok(archive.by_index(idx)?.name().to_owned()))
+        .collect::<Result<Vec<_>>>()
+        .context(format!("Reading package '{}'", package_path.display()))?;
+    for extension in [".wasm", ".abi"].iter() {
+        if !entries.iter().any(|entry| entry.ends_with(extension)) {
+            anyhow::bail!(
+                "package '{}' contains no `*{}` file",
+                package_path.display(),
+                extension
+            );
+        }
+    }
+    Ok(entries)
+}
+
+/// Builds a `multipart/form-data` body with the package as the only part, returns the content
+/// type and the body.
+fn multipart_body(file_name: &str, package: &[u8], digest: &str) -> (String, Vec<u8>) {
+    let boundary = format!("cargo-liquid-{}", &digest[..16]);
+    let mut body = format!(
+        "--{}\r\nContent-Disposition: form-data; name=\"package\"; filename=\"{}\"\r\n\
+         Content-Type: application/octet-stream\r\n\r\n",
+        boundary, file_name
+    )
+    .into_bytes();
+    body.extend_from_slice(package);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
+
+/// Uploads a `.lqpkg` contract package to the contract registry at `registry_url`, returns the
+/// URL of the published contract reported by the registry.
+///
+/// If `dry_run` is set, the package is only validated and what would be uploaded is printed, no
+/// request is sent to the registry.
+pub(crate) fn execute_publish(
+    package_path: &Path,
+    registry_url: &str,
+    api_key: &str,
+    dry_run: bool,
+) -> Result<String> {
+    if package_path.extension() != Some("lqpkg".as_ref()) {
+        anyhow::bail!(
+            "'{}' is not a contract package, the extension must be `.lqpkg`",
+            package_path.display()
+        );
+    }
+    let package =
+        fs::read(package_path).context(format!("Reading package '{}'", package_path.display()))?;
+    let entries = read_package_entries(&package, package_path)?;
+    let digest = utils::sha256_hex(&package);
+    let endpoint = format!(
+        "{}/{}",
+        registry_url.trim_end_matches('/'),
+        PUBLISH_ENDPOINT
+    );
+
+    if dry_run {
+        println!("{: >8}: {}", "Package", package_path.display());
+        println!("{: >8}: {} bytes", "Size", package.len());
+        println!("{: >8}: {}", "SHA-256", digest);
+        println!("{: >8}: {}", "Files", entries.join(", "));
+        println!("{: >8}: {}", "Upload", endpoint);
+        return Ok("Dry run finished, nothing is uploaded".to_owned());
+    }
+
+    let file_name = package_path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let (content_type, body) = multipart_body(&file_name, &package, &digest);
+    let response: Value = ureq::post(&endpoint)
+        .set("Authorization", &format!("Bearer {}", api_key))
+        .set("Content-Type", &content_type)
+        .send_bytes(&body)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .context(format!("Uploading package to '{}'", endpoint))?
+        .into_json()
+        .context("Parsing the response of the registry")?;
+
+    if let Some(error) = response.get("error").or_else(|| response.get("errors")) {
+        anyhow::bail!("the registry rejected the package: {}", error);
+    }
+    let url = response
+        .get("url")
+        .and_then(Value::as_str)
+        .ok_or_else(|| anyhow::anyhow!("unexpected response of the registry: {}", response))?;
+    Ok(url.to_owned())
+}
@@ -0,0 +1,87 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::utils;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// The file in the current directory where the information of the node is stored, so that other
+/// commands can check the contract against the node without querying it again.
+const NODE_INFO_FILE: &str = ".liquid-node-info.json";
+
+/// The runtime version and capabilities of a node, as reported by `getNodeInfo`.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NodeInfo {
+    #[serde(alias = "version")]
+    pub node_version: String,
+    #[serde(alias = "isWasm", default)]
+    pub wasm_support: bool,
+    #[serde(default)]
+    pub supported_entry_points: Vec<String>,
+    #[serde(default)]
+    pub max_contract_size: u64,
+    /// Host functions the node provides to contracts, as `<module>.<name>` or `<name>`.
+    #[serde(default)]
+    pub supported_host_functions: Vec<String>,
+}
+
+/// Queries the version and capabilities of the node at `rpc_url` via the `getNodeInfo` JSON-RPC
+/// method.
+pub(crate) fn fetch_node_info(rpc_url: &str) -> Result<NodeInfo> {
+    let result = utils::call_rpc(
+        rpc_url,
+        "getNodeInfo",
+        serde_json::json!([utils::DEFAULT_GROUP, ""]),
+    )?;
+    NodeInfo::deserialize(&result)
+        .context(format!("unexpected result of `getNodeInfo`: {}", result))
+}
+
+/// Queries the version and capabilities of the node at `rpc_url`, prints them and stores them in
+/// `.liquid-node-info.json`.
+pub(crate) fn execute_query_node(rpc_url: &str) -> Result<NodeInfo> {
+    let node_info = fetch_node_info(rpc_url)?;
+
+    let entry_points = if node_info.supported_entry_points.is_empty() {
+        "-".to_owned()
+    } else {
+        node_info.supported_entry_points.join(", ")
+    };
+    let max_contract_size = if node_info.max_contract_size == 0 {
+        "unlimited".to_owned()
+    } else {
+        format!("{} bytes", node_info.max_contract_size)
+    };
+    println!("{: >17}: {}", "Node version", node_info.node_version);
+    println!(
+        "{: >17}: {}",
+        "Wasm support",
+        if node_info.wasm_support { "yes" } else { "no" }
+    );
+    println!("{: >17}: {}", "Entry points", entry_points);
+    println!("{: >17}: {}", "Max contract size", max_contract_size);
+    println!(
+        "{: >17}: {}",
+        "Host functions",
+        if node_info.supported_host_functions.is_empty() {
+            "-".to_owned()
+        } else {
+            node_info.supported_host_functions.len().to_string()
+        }
+    );
+
+    fs::write(NODE_INFO_FILE, serde_json::to_string_pretty(&node_info)?)
+        .context(format!("Writing '{}'", NODE_INFO_FILE))?;
+    Ok(node_info)
+}
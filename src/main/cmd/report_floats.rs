@@ -0,0 +1,55 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use parity_wasm::elements::{ImportCountType, Module};
+use std::path::Path;
+
+/// A floating-point instruction found in a function body.
+pub(crate) struct FloatInstruction {
+    /// The index of the function in the function index space, i.e. imported functions included.
+    pub function: u32,
+    /// The position of the instruction in the function body, counted in instructions.
+    pub offset: usize,
+    /// The mnemonic of the instruction, e.g. `f64.add`.
+    pub opcode: String,
+}
+
+/// Finds all floating-point instructions in the Wasm bytecode at `wasm_path`, including loads,
+/// stores and conversions from or to floats.
+///
+/// Floating-point arithmetic isn't deterministic across platforms, so contracts using it may
+/// produce different results on different nodes of the chain.
+pub(crate) fn execute_report_floats(wasm_path: &Path) -> Result<Vec<FloatInstruction>> {
+    let module: Module = parity_wasm::deserialize_file(wasm_path)
+        .context(format!("Loading Wasm file '{}'", wasm_path.display()))?;
+    let imported = module.import_count(ImportCountType::Function) as u32;
+    let bodies = module
+        .code_section()
+        .map_or(&[][..], |code_section| code_section.bodies());
+
+    let mut floats = Vec::new();
+    for (idx, body) in bodies.iter().enumerate() {
+        for (offset, instruction) in body.code().elements().iter().enumerate() {
+            let instruction = instruction.to_string();
+            let opcode = instruction.split_whitespace().next().unwrap_or_default();
+            if opcode.contains("f32") || opcode.contains("f64") {
+                floats.push(FloatInstruction {
+                    function: imported + idx as u32,
+                    offset,
+                    opcode: opcode.to_owned(),
+                });
+            }
+        }
+    }
+    Ok(floats)
+}
@@ -0,0 +1,233 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{
+    runtime::{MockEnvironment, Outcome},
+    utils,
+};
+use anyhow::{Context, Result};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt, fs,
+    path::Path,
+    str::FromStr,
+};
+use wasmi::{RuntimeValue, ValueType};
+
+/// A Wasm value passed to or returned by a function, written as `<type>:<value>` on the command
+/// line, e.g. `i64:42`. Values without a type are `i32`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub(crate) enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+impl FromStr for Value {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (ty, value) = match s.find(':') {
+            Some(idx) => (&s[..idx], &s[idx + 1..]),
+            None => ("i32", s),
+        };
+        let invalid = || format!("`{}` is not a valid `{}` value", value, ty);
+        Ok(match ty {
+            "i32" => Value::I32(value.parse().context(invalid())?),
+            "i64" => Value::I64(value.parse().context(invalid())?),
+            "f32" => Value::F32(value.parse().context(invalid())?),
+            "f64" => Value::F64(value.parse().context(invalid())?),
+            _ => anyhow::bail!(
+                "unknown value type `{}`, must be one of `i32`, `i64`, `f32` or `f64`",
+                ty
+            ),
+        })
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::I32(value) => write!(f, "i32:{}", value),
+            Value::I64(value) => write!(f, "i64:{}", value),
+            Value::F32(value) => write!(f, "f32:{}", value),
+            Value::F64(value) => write!(f, "f64:{}", value),
+        }
+    }
+}
+
+impl Value {
+    fn value_type(&self) -> ValueType {
+        match self {
+            Value::I32(_) => ValueType::I32,
+            Value::I64(_) => ValueType::I64,
+            Value::F32(_) => ValueType::F32,
+            Value::F64(_) => ValueType::F64,
+        }
+    }
+}
+
+impl From<Value> for RuntimeValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::I32(value) => RuntimeValue::I32(value),
+            Value::I64(value) => RuntimeValue::I64(value),
+            Value::F32(value) => RuntimeValue::F32(value.into()),
+            Value::F64(value) => RuntimeValue::F64(value.into()),
+        }
+    }
+}
+
+impl From<RuntimeValue> for Value {
+    fn from(value: RuntimeValue) -> Self {
+        match value {
+            RuntimeValue::I32(value) => Value::I32(value),
+            RuntimeValue::I64(value) => Value::I64(value),
+            RuntimeValue::F32(value) => Value::F32(value.to_float()),
+            RuntimeValue::F64(value) => Value::F64(value.to_float()),
+        }
+    }
+}
+
+fn format_types(types: &[ValueType]) -> String {
+    types
+        .iter()
+        .map(|ty| format!("{:?}", ty).to_lowercase())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    format!("0x{}", utils::encode_hex(bytes))
+}
+
+/// Reads the storage from a JSON object mapping hex encoded keys to hex encoded values, a file
+/// which doesn't exist yet is an empty storage.
+fn read_storage(path: &Path) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
+    if !path.exists() {
+        return Ok(BTreeMap::new());
+    }
+    let content =
+        fs::read_to_string(path).context(format!("Reading storage '{}'", path.display()))?;
+    let entries: BTreeMap<String, String> = serde_json::from_str(&content).context(format!(
+        "Parsing storage '{}', it should be an object of hex strings",
+        path.display()
+    ))?;
+    entries
+        .iter()
+        .map(|(key, value)| Ok((utils::decode_hex(key)?, utils::decode_hex(value)?)))
+        .collect::<Result<_>>()
+        .context(format!("Parsing storage '{}'", path.display()))
+}
+
+fn write_storage(path: &Path, storage: &BTreeMap<Vec<u8>, Vec<u8>>) -> Result<()> {
+    let entries = storage
+        .iter()
+        .map(|(key, value)| (encode_hex(key), encode_hex(value)))
+        .collect::<BTreeMap<_, _>>();
+    fs::write(path, serde_json::to_string_pretty(&entries)? + "\n")
+        .context(format!("Writing storage '{}'", path.display()))
+}
+
+/// Prints keys of the storage whose values are changed by the execution, including keys which
+/// are removed.
+fn print_storage_changes(old: &BTreeMap<Vec<u8>, Vec<u8>>, new: &BTreeMap<Vec<u8>, Vec<u8>>) {
+    let keys = old
+        .keys()
+        .chain(new.keys())
+        .filter(|key| old.get(*key) != new.get(*key))
+        .collect::<BTreeSet<_>>();
+    if keys.is_empty() {
+        println!("Storage is not changed");
+        return;
+    }
+    println!("Storage changes:");
+    for key in keys {
+        match (old.get(key), new.get(key)) {
+            (Some(old_value), Some(value)) => println!(
+                "  {}: {} -> {}",
+                encode_hex(key),
+                encode_hex(old_value),
+                encode_hex(value)
+            ),
+            (None, Some(value)) => println!("  {}: {} (new)", encode_hex(key), encode_hex(value)),
+            (Some(old_value), None) => {
+                println!("  {}: {} (removed)", encode_hex(key), encode_hex(old_value))
+            }
+            (None, None) => unreachable!("keys are taken from either storage"),
+        }
+    }
+}
+
+/// Executes the exported `function` of the contract with `args` in a mocked environment, returns
+/// the values returned by the function.
+///
+/// The contract reads `call_data` as the input of the transaction. If `storage` is given, the
+/// storage is loaded from the JSON file before the execution and written back afterwards unless
+/// the contract reverts, so that state persists across runs.
+pub(crate) fn execute_run_local(
+    wasm_path: &Path,
+    function: &str,
+    args: &[Value],
+    storage: Option<&Path>,
+    call_data: Vec<u8>,
+) -> Result<Vec<Value>> {
+    let module = parity_wasm::deserialize_file(wasm_path)
+        .context(format!("Loading Wasm file '{}'", wasm_path.display()))?;
+    let mut env = MockEnvironment::new(call_data);
+    let instance = env.instantiate(module)?;
+
+    let params = instance
+        .export_by_name(function)
+        .and_then(|export| {
+            export
+                .as_func()
+                .map(|func| func.signature().params().to_vec())
+        })
+        .ok_or_else(|| anyhow::anyhow!("the contract exports no function `{}`", function))?;
+    let arg_types = args.iter().map(Value::value_type).collect::<Vec<_>>();
+    if arg_types != params {
+        anyhow::bail!(
+            "`{}` expects arguments of types ({}), but ({}) are given",
+            function,
+            format_types(&params),
+            format_types(&arg_types)
+        );
+    }
+
+    let old_storage = match storage {
+        Some(path) => read_storage(path)?,
+        None => BTreeMap::new(),
+    };
+    env.set_storage(old_storage.clone());
+    let args = args
+        .iter()
+        .copied()
+        .map(RuntimeValue::from)
+        .collect::<Vec<_>>();
+    let (outcome, value) = env.invoke_with_args(&instance, function, &args)?;
+
+    println!("Execution {}", outcome);
+    if !env.output().is_empty() {
+        println!("Output: {}", encode_hex(env.output()));
+    }
+    if outcome == Outcome::Reverted {
+        println!("Storage changes are discarded");
+    } else {
+        print_storage_changes(&old_storage, env.storage());
+        if let Some(path) = storage {
+            write_storage(path, env.storage())?;
+        }
+    }
+    Ok(value.into_iter().map(Value::from).collect())
+}
@@ -0,0 +1,205 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::build::parse_ty;
+use anyhow::{Context, Result};
+use colored::Colorize;
+use itertools::Itertools;
+use serde_json::{Map, Value};
+use std::{collections::BTreeMap, fmt, fs, path::Path};
+
+/// How a change of the ABI affects existing callers of the contract.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub(crate) enum Compatibility {
+    /// Existing callers are not affected.
+    BackwardCompatible,
+    /// Existing transactions still work, but callers need to be updated when rebuilt.
+    SourceBreaking,
+    /// Existing callers will fail to invoke the contract.
+    BinaryBreaking,
+}
+
+pub(crate) struct CompatibilityIssue {
+    pub compatibility: Compatibility,
+    pub description: String,
+}
+
+impl fmt::Display for CompatibilityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match self.compatibility {
+            Compatibility::BackwardCompatible => "compatible".green(),
+            Compatibility::SourceBreaking => "source breaking".yellow(),
+            Compatibility::BinaryBreaking => "binary breaking".bright_red(),
+        };
+        write!(f, "[{}] {}", label.bold(), self.description)
+    }
+}
+
+/// An entry of the ABI, i.e. a function, the constructor or an event.
+struct AbiEntry {
+    inputs: Vec<(String, String)>,
+    outputs: Vec<String>,
+    constant: bool,
+}
+
+fn load_abi(abi_path: &Path) -> Result<BTreeMap<(String, String), AbiEntry>> {
+    let content = fs::read_to_string(abi_path)
+        .context(format!("Reading ABI file '{}'", abi_path.display()))?;
+    let entries: Vec<Map<String, Value>> = serde_json::from_str(&content)
+        .context(format!("Parsing ABI file '{}'", abi_path.display()))?;
+
+    let params = |entry: &Map<String, Value>, key: &str| -> Result<Vec<(String, String)>> {
+        entry
+            .get(key)
+            .and_then(Value::as_array)
+            .map(|params| {
+                params
+                    .iter()
+                    .filter_map(Value::as_object)
+                    .map(|param| {
+                        let name = param.get("name").and_then(Value::as_str).unwrap_or("");
+                        Ok((name.to_owned(), parse_ty(param)?))
+                    })
+                    .collect()
+            })
+            .unwrap_or_else(|| Ok(Vec::new()))
+    };
+
+    entries
+        .iter()
+        .map(|entry| {
+            let ty = entry.get("type").and_then(Value::as_str).unwrap_or("");
+            let name = entry.get("name").and_then(Value::as_str).unwrap_or("");
+            let abi_entry = AbiEntry {
+                inputs: params(entry, "inputs")?,
+                outputs: params(entry, "outputs")?
+                    .into_iter()
+                    .map(|(_, ty)| ty)
+                    .collect(),
+                constant: entry
+                    .get("constant")
+                    .and_then(Value::as_bool)
+                    .unwrap_or(false),
+            };
+            Ok(((ty.to_owned(), name.to_owned()), abi_entry))
+        })
+        .collect::<Result<_>>()
+        .context(format!("Reading ABI file '{}'", abi_path.display()))
+}
+
+fn signature(params: &[(String, String)]) -> String {
+    params.iter().map(|(_, ty)| ty).join(",")
+}
+
+/// Compares two ABI files of the same contract and reports every difference between them.
+pub(crate) fn execute_semver_check(
+    old_abi: &Path,
+    new_abi: &Path,
+) -> Result<Vec<CompatibilityIssue>> {
+    let old_entries = load_abi(old_abi)?;
+    let new_entries = load_abi(new_abi)?;
+    let mut issues = Vec::new();
+    let mut report = |compatibility, description| {
+        issues.push(CompatibilityIssue {
+            compatibility,
+            description,
+        })
+    };
+
+    for ((ty, name), old_entry) in &old_entries {
+        let is_function = ty == "function";
+        let desc = if is_function {
+            format!("function `{}`", name)
+        } else {
+            format!("{} `{}`", ty, name)
+        };
+        let new_entry = match new_entries.get(&(ty.clone(), name.clone())) {
+            Some(new_entry) => new_entry,
+            None => {
+                report(
+                    if is_function {
+                        Compatibility::BinaryBreaking
+                    } else {
+                        Compatibility::SourceBreaking
+                    },
+                    format!("{} is removed", desc),
+                );
+                continue;
+            }
+        };
+
+        let old_sig = signature(&old_entry.inputs);
+        let new_sig = signature(&new_entry.inputs);
+        if old_sig != new_sig {
+            // The selector of a function is derived from its signature.
+            report(
+                if is_function {
+                    Compatibility::BinaryBreaking
+                } else {
+                    Compatibility::SourceBreaking
+                },
+                format!(
+                    "inputs of {} are changed from `({})` to `({})`",
+                    desc, old_sig, new_sig
+                ),
+            );
+        } else if old_entry.inputs != new_entry.inputs {
+            report(
+                Compatibility::BackwardCompatible,
+                format!("parameters of {} are renamed", desc),
+            );
+        }
+
+        if old_entry.outputs != new_entry.outputs {
+            report(
+                if is_function {
+                    Compatibility::BinaryBreaking
+                } else {
+                    Compatibility::SourceBreaking
+                },
+                format!(
+                    "outputs of {} are changed from `({})` to `({})`",
+                    desc,
+                    old_entry.outputs.join(","),
+                    new_entry.outputs.join(",")
+                ),
+            );
+        }
+
+        if old_entry.constant != new_entry.constant {
+            report(
+                Compatibility::SourceBreaking,
+                format!(
+                    "{} becomes {}",
+                    desc,
+                    if new_entry.constant {
+                        "constant"
+                    } else {
+                        "mutable"
+                    }
+                ),
+            );
+        }
+    }
+
+    for (ty, name) in new_entries.keys() {
+        if !old_entries.contains_key(&(ty.clone(), name.clone())) {
+            report(
+                Compatibility::BackwardCompatible,
+                format!("{} `{}` is added", ty, name),
+            );
+        }
+    }
+
+    issues.sort_by_key(|issue| std::cmp::Reverse(issue.compatibility));
+    Ok(issues)
+}
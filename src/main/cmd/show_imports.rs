@@ -0,0 +1,154 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    disassemble::{format_global, format_memory, format_signature, format_table},
+    query_node::fetch_node_info,
+};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use parity_wasm::elements::{External, Type};
+use serde::Serialize;
+use std::{fmt, path::Path};
+
+/// The kind and type of an import.
+#[derive(Serialize)]
+#[serde(tag = "kind", content = "type", rename_all = "camelCase")]
+pub(crate) enum ExternType {
+    Function(String),
+    Table(String),
+    Memory(String),
+    Global(String),
+}
+
+impl fmt::Display for ExternType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExternType::Function(ty) => write!(f, "func {}", ty),
+            ExternType::Table(ty) => write!(f, "table {}", ty),
+            ExternType::Memory(ty) => write!(f, "memory {}", ty),
+            ExternType::Global(ty) => write!(f, "global {}", ty),
+        }
+    }
+}
+
+/// An import of the Wasm bytecode, which must be provided by the node.
+#[derive(Serialize)]
+pub(crate) struct WasmImport {
+    pub module: String,
+    pub name: String,
+    pub ty: ExternType,
+}
+
+/// Lists imports of the Wasm bytecode, i.e. host functions and other items it requires from the
+/// node.
+pub(crate) fn execute_show_imports(wasm_path: &Path) -> Result<Vec<WasmImport>> {
+    let module = parity_wasm::deserialize_file(wasm_path)
+        .context(format!("Loading Wasm file '{}'", wasm_path.display()))?;
+    let types = module
+        .type_section()
+        .map(|section| section.types())
+        .unwrap_or_default();
+    let entries = module
+        .import_section()
+        .map(|section| section.entries())
+        .unwrap_or_default();
+    entries
+        .iter()
+        .map(|entry| {
+            let ty = match entry.external() {
+                External::Function(idx) => match types.get(*idx as usize) {
+                    Some(Type::Function(ty)) => ExternType::Function(format_signature(ty)),
+                    None => anyhow::bail!(
+                        "import `{}.{}` refers to type[{}], which doesn't exist",
+                        entry.module(),
+                        entry.field(),
+                        idx
+                    ),
+                },
+                External::Table(table) => ExternType::Table(format_table(table)),
+                External::Memory(memory) => ExternType::Memory(format_memory(memory)),
+                External::Global(global) => ExternType::Global(format_global(global)),
+            };
+            Ok(WasmImport {
+                module: entry.module().to_owned(),
+                name: entry.field().to_owned(),
+                ty,
+            })
+        })
+        .collect()
+}
+
+/// Prints the imports as a table. If `rpc_url` is set, imported functions are checked against
+/// host functions supported by the node, and an error is returned if any is unsupported.
+pub(crate) fn print_imports(imports: &[WasmImport], rpc_url: Option<&str>) -> Result<()> {
+    let supported = match rpc_url {
+        Some(rpc_url) => {
+            let node_info = fetch_node_info(rpc_url)?;
+            if node_info.supported_host_functions.is_empty() {
+                anyhow::bail!(
+                    "the node at '{}' doesn't report host functions it supports",
+                    rpc_url
+                );
+            }
+            Some(node_info.supported_host_functions)
+        }
+        None => None,
+    };
+    let width = imports
+        .iter()
+        .map(|import| import.module.len() + import.name.len() + 1)
+        .chain(Some("Import".len()))
+        .max()
+        .unwrap_or_default();
+    let types = imports
+        .iter()
+        .map(|import| import.ty.to_string())
+        .collect::<Vec<_>>();
+    let ty_width = types.iter().map(String::len).max().unwrap_or_default();
+    println!("{: <width$}  Type", "Import", width = width);
+    let mut unsupported = 0;
+    for (import, ty) in imports.iter().zip(&types) {
+        let name = format!("{}.{}", import.module, import.name);
+        let status = match (&supported, &import.ty) {
+            (Some(supported), ExternType::Function(_)) => {
+                if supported.contains(&name) || supported.contains(&import.name) {
+                    format!("  {}", "supported".green())
+                } else {
+                    unsupported += 1;
+                    format!("  {}", "unsupported".bright_red().bold())
+                }
+            }
+            _ => String::new(),
+        };
+        if status.is_empty() {
+            println!("{: <width$}  {}", name, ty, width = width);
+        } else {
+            println!(
+                "{: <width$}  {: <ty_width$}{}",
+                name,
+                ty,
+                status,
+                width = width,
+                ty_width = ty_width
+            );
+        }
+    }
+    if unsupported > 0 {
+        anyhow::bail!(
+            "{} imported host functions are not supported by the node, deploying the contract \
+             will fail",
+            unsupported
+        );
+    }
+    Ok(())
+}
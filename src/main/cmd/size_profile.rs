@@ -0,0 +1,135 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use parity_wasm::elements::{ImportCountType, Module, Serialize};
+use std::{cmp::Reverse, collections::BTreeMap, path::Path};
+
+/// The module which functions without a name, or with a name not from Rust, are attributed to.
+const UNKNOWN_MODULE: &str = "<unknown>";
+
+/// The code size attributed to a source module of the contract or its dependencies.
+pub(crate) struct ModuleSizeEntry {
+    pub module: String,
+    pub code_bytes: usize,
+    pub percentage: f64,
+}
+
+/// Splits a demangled path at `::` outside of angle brackets, e.g. `<T as Trait>::f` is split
+/// into `<T as Trait>` and `f`.
+fn split_path(path: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let bytes = path.as_bytes();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'<' => depth += 1,
+            b'>' if depth > 0 => depth -= 1,
+            b':' if depth == 0 && bytes.get(idx + 1) == Some(&b':') => {
+                segments.push(&path[start..idx]);
+                idx += 1;
+                start = idx + 1;
+            }
+            _ => (),
+        }
+        idx += 1;
+    }
+    segments.push(&path[start..]);
+    segments
+}
+
+/// Derives the source module of a function from its demangled name, i.e. the path without the
+/// function itself, closures, types and generic arguments.
+///
+/// Methods of trait implementations such as `<crate::m::T as Trait>::f` are attributed to the
+/// module of the implementing type.
+fn source_module(name: &str) -> String {
+    let mut segments = split_path(name);
+    if let Some(first) = segments.first().copied() {
+        if first.starts_with('<') && first.ends_with('>') {
+            let inner = &first[1..first.len() - 1];
+            let self_type = inner.split(" as ").next().unwrap_or(inner);
+            let self_type = self_type.trim_start_matches('&').trim_start_matches("mut ");
+            segments = split_path(self_type);
+            // The method is not part of the path of the implementing type.
+            segments.push("");
+        }
+    }
+    segments.pop();
+    let segments = segments
+        .into_iter()
+        .map(|segment| segment.split('<').next().unwrap_or(segment))
+        .take_while(|segment| {
+            !segment.is_empty()
+                && !segment.starts_with('{')
+                && !segment.starts_with(|c: char| c.is_ascii_uppercase())
+        })
+        .collect::<Vec<_>>();
+    if segments.is_empty() {
+        UNKNOWN_MODULE.to_owned()
+    } else {
+        segments.join("::")
+    }
+}
+
+/// Attributes the code size of every function of the Wasm bytecode to the source module it's
+/// defined in, returns the modules sorted by code size in descending order.
+///
+/// Modules are derived from function names in the name section, so the bytecode must be built
+/// with `--preserve-names`.
+pub(crate) fn execute_size_profile(wasm_path: &Path) -> Result<Vec<ModuleSizeEntry>> {
+    let module: Module = parity_wasm::deserialize_file(wasm_path)
+        .context(format!("Loading Wasm file '{}'", wasm_path.display()))?;
+    let module = module.parse_names().unwrap_or_else(|(_, module)| module);
+    let names = module
+        .names_section()
+        .and_then(|section| section.functions())
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "'{}' has no function names, build it with `--preserve-names`",
+                wasm_path.display()
+            )
+        })?
+        .names();
+
+    let imported_functions = module.import_count(ImportCountType::Function) as u32;
+    let bodies = module
+        .code_section()
+        .map(|section| section.bodies())
+        .unwrap_or_default();
+    let mut sizes = BTreeMap::<String, usize>::new();
+    for (idx, body) in bodies.iter().enumerate() {
+        let mut bytes = Vec::new();
+        body.clone()
+            .serialize(&mut bytes)
+            .context("Serializing function body")?;
+        let module = match names.get(imported_functions + idx as u32) {
+            Some(name) => source_module(&format!("{:#}", rustc_demangle::demangle(name))),
+            None => UNKNOWN_MODULE.to_owned(),
+        };
+        *sizes.entry(module).or_default() += bytes.len();
+    }
+
+    let total = sizes.values().sum::<usize>().max(1);
+    let mut entries = sizes
+        .into_iter()
+        .map(|(module, code_bytes)| ModuleSizeEntry {
+            module,
+            code_bytes,
+            percentage: code_bytes as f64 * 100.0 / total as f64,
+        })
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| Reverse(entry.code_bytes));
+    Ok(entries)
+}
@@ -0,0 +1,56 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use parity_wasm::elements::{Module, Section};
+use std::path::Path;
+
+/// Whether the section maps indices of the module to symbol names, i.e. the name section or
+/// DWARF debug info.
+///
+/// DWARF sections are removed as a whole instead of only their `DW_AT_name` attributes, since
+/// names are referenced across sections and the rest of the debug info is useless without them.
+fn is_naming_section(section: &Section) -> bool {
+    match section {
+        Section::Name(_) => true,
+        Section::Custom(section) => {
+            section.name() == "name" || section.name().starts_with(".debug_")
+        }
+        _ => false,
+    }
+}
+
+/// Removes function, local and label names of the Wasm bytecode at `input` and writes the result
+/// to `output`, returns the names of the sections removed.
+///
+/// This is irreversible, the names can't be recovered from the output.
+pub(crate) fn execute_strip_names(input: &Path, output: &Path) -> Result<Vec<String>> {
+    let mut module: Module = parity_wasm::deserialize_file(input)
+        .context(format!("Loading Wasm file '{}'", input.display()))?;
+
+    let removed = module
+        .sections()
+        .iter()
+        .filter(|section| is_naming_section(section))
+        .map(|section| match section {
+            Section::Custom(section) => section.name().to_owned(),
+            _ => "name".to_owned(),
+        })
+        .collect::<Vec<_>>();
+    module
+        .sections_mut()
+        .retain(|section| !is_naming_section(section));
+
+    parity_wasm::serialize_to_file(output, module)
+        .context(format!("Writing Wasm file '{}'", output.display()))?;
+    Ok(removed)
+}
@@ -0,0 +1,43 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    build::{DEFAULT_ENTRY_POINTS, REQUIRED_EXPORTS},
+    call_graph::{strip_unreachable_exports, StrippedItems},
+};
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Removes exports of the Wasm bytecode at `input` which are unreachable from `entry_points`,
+/// along with the functions only they use, and writes the result to `output`. The default entry
+/// points are used if `entry_points` is empty, exports required by the chain are always kept.
+pub(crate) fn execute_strip_unreachable(
+    input: &Path,
+    output: &Path,
+    entry_points: &[String],
+) -> Result<StrippedItems> {
+    let entry_points = if entry_points.is_empty() {
+        DEFAULT_ENTRY_POINTS.to_vec()
+    } else {
+        entry_points.iter().map(String::as_str).collect()
+    };
+    let entry_points = entry_points
+        .into_iter()
+        .chain(REQUIRED_EXPORTS.iter().copied())
+        .collect::<Vec<_>>();
+    let mut module = parity_wasm::deserialize_file(input)
+        .context(format!("Loading Wasm file '{}'", input.display()))?;
+    let stripped = strip_unreachable_exports(&mut module, &entry_points);
+    parity_wasm::serialize_to_file(output, module)
+        .context(format!("Writing Wasm file '{}'", output.display()))?;
+    Ok(stripped)
+}
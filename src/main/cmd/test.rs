@@ -0,0 +1,148 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{workspace::ManifestPath, VerbosityBehavior};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::{
+    env,
+    io::{BufRead, BufReader},
+    process::{Command, Stdio},
+};
+
+/// An error reported by Miri, e.g. undefined behavior or an operation Miri doesn't support.
+struct MiriError {
+    message: String,
+    location: Option<String>,
+}
+
+/// Collects errors reported by Miri from its diagnostics, which look like the ones of rustc:
+///
+/// ```text
+/// error: Undefined Behavior: dereferencing pointer failed: null pointer is a dangling pointer
+///   --> src/lib.rs:10:5
+/// ```
+fn parse_miri_errors(diagnostics: &[String]) -> Vec<MiriError> {
+    let mut errors = Vec::<MiriError>::new();
+    let mut in_error = false;
+    for line in diagnostics {
+        if let Some(message) = line
+            .strip_prefix("error: Undefined Behavior: ")
+            .or_else(|| line.strip_prefix("error: unsupported operation: "))
+        {
+            errors.push(MiriError {
+                message: message.to_owned(),
+                location: None,
+            });
+            in_error = true;
+        } else if in_error {
+            if let Some(location) = line.trim_start().strip_prefix("--> ") {
+                if let Some(error) = errors.last_mut() {
+                    error.location = Some(location.to_owned());
+                }
+                in_error = false;
+            }
+        }
+    }
+    errors
+}
+
+/// Runs tests of the contract with Miri, which detects undefined behavior, e.g. in unsafe code
+/// of the contract or its dependencies. Diagnostics are passed through, and errors found are
+/// summarized with their locations at the end.
+fn run_miri(mut cmd: Command) -> Result<()> {
+    which::which("cargo-miri").context(
+        "`cargo-miri` is not installed, install it by `rustup component add miri` with a \
+         nightly toolchain",
+    )?;
+    let mut child = cmd
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!("Error executing `{:?}`", cmd))?;
+    let mut diagnostics = Vec::new();
+    if let Some(stderr) = child.stderr.take() {
+        for line in BufReader::new(stderr).lines() {
+            let line = line?;
+            eprintln!("{}", line);
+            diagnostics.push(line);
+        }
+    }
+    let status = child
+        .wait()
+        .context(format!("Error executing `{:?}`", cmd))?;
+
+    let errors = parse_miri_errors(&diagnostics);
+    if !errors.is_empty() {
+        eprintln!(
+            "{}",
+            "Miri found the following problems:".bright_red().bold()
+        );
+        for error in &errors {
+            eprintln!(
+                "  {} {}",
+                error
+                    .location
+                    .as_deref()
+                    .unwrap_or("<unknown location>")
+                    .bold(),
+                error.message
+            );
+        }
+    }
+    if !status.success() {
+        anyhow::bail!("`{:?}` failed with exit code: {:?}", cmd, status.code());
+    }
+    Ok(())
+}
+
+/// Runs tests of the contract on the host.
+///
+/// Unlike building Wasm bytecode, which always disables default features of the contract, tests
+/// are built with default features enabled unless `no_default_features` is set, so that features
+/// for the native target (e.g. the mocked environment) can be enabled by `default`.
+///
+/// If `miri` is set, tests are run by `cargo miri test` instead.
+pub(crate) fn execute_test(
+    manifest_path: ManifestPath,
+    verbosity_behavior: VerbosityBehavior,
+    no_default_features: bool,
+    miri: bool,
+) -> Result<()> {
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    let mut cmd = Command::new(cargo);
+    if miri {
+        cmd.arg("miri");
+    }
+    cmd.arg("test")
+        .arg(format!(
+            "--manifest-path={}",
+            manifest_path.as_ref().to_string_lossy()
+        ))
+        .arg(match verbosity_behavior {
+            VerbosityBehavior::Quiet | VerbosityBehavior::Normal => "--quiet",
+            VerbosityBehavior::Verbose => "--verbose",
+        });
+    if no_default_features {
+        cmd.arg("--no-default-features");
+    }
+    if miri {
+        return run_miri(cmd);
+    }
+
+    let status = cmd
+        .status()
+        .context(format!("Error executing `{:?}`", cmd))?;
+    if !status.success() {
+        anyhow::bail!("`{:?}` failed with exit code: {:?}", cmd, status.code());
+    }
+    Ok(())
+}
@@ -0,0 +1,153 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::workspace::ManifestPath;
+use anyhow::{Context, Result};
+use std::fs;
+use toml::value::{Table, Value};
+
+/// A violation of Liquid best practices found in the manifest.
+pub(crate) struct ManifestWarning {
+    pub message: String,
+    pub suggestion: String,
+}
+
+impl ManifestWarning {
+    fn new<M: Into<String>, S: Into<String>>(message: M, suggestion: S) -> Self {
+        ManifestWarning {
+            message: message.into(),
+            suggestion: suggestion.into(),
+        }
+    }
+}
+
+fn table<'a>(toml: &'a Table, key: &str) -> Option<&'a Table> {
+    toml.get(key).and_then(Value::as_table)
+}
+
+fn check_lto(toml: &Table, warnings: &mut Vec<ManifestWarning>) {
+    let lto = table(toml, "profile")
+        .and_then(|profile| table(profile, "release"))
+        .and_then(|release| release.get("lto"));
+    let disabled =
+        matches!(lto, Some(Value::Boolean(false))) || lto.and_then(Value::as_str) == Some("off");
+    if disabled {
+        warnings.push(ManifestWarning::new(
+            "LTO is disabled in `[profile.release]`",
+            "set `lto = true` to reduce the size of the Wasm bytecode",
+        ));
+    }
+}
+
+fn check_crate_type(toml: &Table, warnings: &mut Vec<ManifestWarning>) {
+    let has_cdylib = table(toml, "lib")
+        .and_then(|lib| lib.get("crate-type"))
+        .and_then(Value::as_array)
+        .map(|crate_types| {
+            crate_types
+                .iter()
+                .any(|crate_type| crate_type.as_str() == Some("cdylib"))
+        })
+        .unwrap_or(false);
+    if !has_cdylib {
+        warnings.push(ManifestWarning::new(
+            "`[lib] crate-type` doesn't include `cdylib`",
+            "set `crate-type = [\"cdylib\", \"rlib\"]`, which is required to produce Wasm \
+             bytecode and generate ABI",
+        ));
+    }
+}
+
+fn check_edition(toml: &Table, warnings: &mut Vec<ManifestWarning>) {
+    let edition = table(toml, "package")
+        .and_then(|package| package.get("edition"))
+        .and_then(Value::as_str);
+    match edition {
+        Some(edition) if edition.parse::<u32>().map(|year| year >= 2018) == Ok(true) => (),
+        Some(edition) => warnings.push(ManifestWarning::new(
+            format!("edition `{}` is too old", edition),
+            "set `edition = \"2018\"` or later in `[package]`",
+        )),
+        None => warnings.push(ManifestWarning::new(
+            "edition is not set, so edition 2015 is used",
+            "set `edition = \"2018\"` or later in `[package]`",
+        )),
+    }
+}
+
+fn check_liquid_version(toml: &Table, warnings: &mut Vec<ManifestWarning>) {
+    let dependencies = match table(toml, "dependencies") {
+        Some(dependencies) => dependencies,
+        None => return,
+    };
+    for name in ["liquid-lang", "liquid_lang"].iter() {
+        let version = match dependencies.get(*name) {
+            Some(Value::String(version)) => Some(version.as_str()),
+            Some(Value::Table(dependency)) => dependency.get("version").and_then(Value::as_str),
+            _ => None,
+        };
+        if version.map(str::trim) == Some("*") {
+            warnings.push(ManifestWarning::new(
+                format!("`{}` uses a wildcard version", name),
+                format!(
+                    "pin `{}` to a specific version, otherwise the contract may be built with \
+                     an incompatible release",
+                    name
+                ),
+            ));
+        }
+    }
+}
+
+/// `std` is exempted, as `cargo liquid test` relies on it being a default feature, while it's
+/// disabled explicitly when building Wasm bytecode.
+fn check_default_features(toml: &Table, warnings: &mut Vec<ManifestWarning>) {
+    let default = table(toml, "features")
+        .and_then(|features| features.get("default"))
+        .and_then(Value::as_array);
+    if let Some(default) = default {
+        let features = default
+            .iter()
+            .filter_map(Value::as_str)
+            .filter(|feature| *feature != "std")
+            .map(|feature| format!("`{}`", feature))
+            .collect::<Vec<_>>();
+        if !features.is_empty() {
+            warnings.push(ManifestWarning::new(
+                format!(
+                    "`[features] default` enables {} besides `std`",
+                    features.join(", ")
+                ),
+                "set `default = [\"std\"]` and enable other features explicitly, since default \
+                 features are always disabled when building Wasm bytecode",
+            ));
+        }
+    }
+}
+
+/// Checks the manifest of the contract against Liquid best practices, returns all violations
+/// found.
+pub(crate) fn execute_validate_manifest(
+    manifest_path: &ManifestPath,
+) -> Result<Vec<ManifestWarning>> {
+    let content =
+        fs::read_to_string(manifest_path).context(format!("Reading '{}'", manifest_path))?;
+    let toml: Table = toml::from_str(&content).context(format!("Parsing '{}'", manifest_path))?;
+
+    let mut warnings = Vec::new();
+    check_lto(&toml, &mut warnings);
+    check_crate_type(&toml, &mut warnings);
+    check_edition(&toml, &mut warnings);
+    check_liquid_version(&toml, &mut warnings);
+    check_default_features(&toml, &mut warnings);
+    Ok(warnings)
+}
@@ -0,0 +1,106 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    build::{dest_wasm_path, execute_build, BuildOptions},
+    diff::compare_section_sizes,
+    hash_check::get_code,
+};
+use crate::{utils, workspace::ManifestPath, AnalysisBehavior, VerbosityBehavior};
+use anyhow::{Context, Result};
+use parity_wasm::elements::Module;
+use std::{env, fs};
+
+/// Sizes in bytes of a section which differs between the local build and the deployed bytecode,
+/// 0 if the section is missing.
+pub(crate) struct SectionMismatch {
+    pub name: String,
+    pub local: usize,
+    pub onchain: usize,
+}
+
+pub(crate) struct BytecodeMatchResult {
+    pub matched: bool,
+    pub local_sha256: String,
+    pub onchain_sha256: String,
+    /// Sections whose sizes differ, `None` if the deployed bytecode is not a Wasm module.
+    pub section_mismatches: Option<Vec<SectionMismatch>>,
+}
+
+/// Sections whose sizes differ, in the order of the local module followed by sections only
+/// deployed on chain.
+fn section_mismatches(local: &Module, onchain: &Module) -> Result<Vec<SectionMismatch>> {
+    Ok(compare_section_sizes(local, onchain)?
+        .into_iter()
+        .filter(|(_, local, onchain)| local != onchain)
+        .map(|(name, local, onchain)| SectionMismatch {
+            name,
+            local,
+            onchain,
+        })
+        .collect())
+}
+
+/// Builds the contract at `manifest_path` and checks whether the result is byte-for-byte the same
+/// as the bytecode deployed at `address`, which is queried via the `getCode` JSON-RPC method.
+///
+/// The build skips the cache, and `SOURCE_DATE_EPOCH` is set to 0 unless it's already set, so
+/// that tools embedding timestamps produce the same output as the build which was deployed.
+/// `use_gm` must match the mode of that build.
+pub(crate) fn execute_verify_bytecode(
+    manifest_path: ManifestPath,
+    use_gm: bool,
+    rpc_url: &str,
+    address: &str,
+) -> Result<BytecodeMatchResult> {
+    if env::var_os("SOURCE_DATE_EPOCH").is_none() {
+        env::set_var("SOURCE_DATE_EPOCH", "0");
+    }
+    let build_options = BuildOptions {
+        no_cache: true,
+        ..Default::default()
+    };
+    execute_build(
+        manifest_path.clone(),
+        use_gm,
+        VerbosityBehavior::Quiet,
+        AnalysisBehavior::Skip,
+        &None,
+        build_options,
+    )
+    .context("Building the contract")?;
+    let dest_wasm = dest_wasm_path(&manifest_path, None)?;
+    let local_code =
+        fs::read(&dest_wasm).context(format!("Reading Wasm file '{}'", dest_wasm.display()))?;
+    let onchain_code = get_code(rpc_url, address)?;
+
+    let matched = local_code == onchain_code;
+    let section_mismatches = if matched {
+        Some(Vec::new())
+    } else {
+        match Module::from_bytes(&onchain_code) {
+            Ok(onchain) => {
+                let local = Module::from_bytes(&local_code).map_err(|e| {
+                    anyhow::anyhow!("unable to load '{}': {}", dest_wasm.display(), e)
+                })?;
+                Some(section_mismatches(&local, &onchain)?)
+            }
+            Err(_) => None,
+        }
+    };
+    Ok(BytecodeMatchResult {
+        matched,
+        local_sha256: utils::sha256_hex(&local_code),
+        onchain_sha256: utils::sha256_hex(&onchain_code),
+        section_mismatches,
+    })
+}
@@ -0,0 +1,63 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::{env, fs, path::PathBuf};
+
+/// Name of the user-global config file, which is looked up in the config directory of the user.
+const CONFIG_FILE: &str = ".cargo-liquid.toml";
+
+/// User-global defaults, which are overridden by `[package.metadata.liquid]` of the contract and
+/// by options on the command line.
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct GlobalConfig {
+    /// The optimization level passed to wasm-opt.
+    pub optimization_level: Option<String>,
+    /// Whether to colorize the output, must be one of `auto`, `always` or `never`.
+    pub color: Option<String>,
+    /// The wasm-opt binary to use instead of the one found in `PATH`.
+    pub wasm_opt_path: Option<PathBuf>,
+}
+
+/// The config directory of the user, i.e. `%APPDATA%` on Windows, `~/Library/Application Support`
+/// on macOS and `$XDG_CONFIG_HOME` or `~/.config` on other platforms.
+fn config_dir() -> Option<PathBuf> {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home.map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".config")))
+    }
+}
+
+impl GlobalConfig {
+    /// The path of the config file, if the config directory of the user is known.
+    pub fn path() -> Option<PathBuf> {
+        config_dir().map(|dir| dir.join(CONFIG_FILE))
+    }
+
+    /// Loads the config file, all settings are unset if it doesn't exist.
+    pub fn load() -> Result<Self> {
+        let path = match Self::path() {
+            Some(path) if path.exists() => path,
+            _ => return Ok(Default::default()),
+        };
+        let content = fs::read_to_string(&path).context(format!("Reading '{}'", path.display()))?;
+        toml::from_str(&content).context(format!("Parsing '{}'", path.display()))
+    }
+}
@@ -11,6 +11,8 @@
 // limitations under the License.
 
 mod cmd;
+mod config;
+mod runtime;
 mod utils;
 mod workspace;
 
@@ -18,10 +20,12 @@ use anyhow::{Error, Result};
 use colored::Colorize;
 use std::{
     convert::{TryFrom, TryInto},
+    io::IsTerminal,
     path::PathBuf,
+    time::Duration,
 };
 use structopt::{clap, StructOpt};
-use workspace::ManifestPath;
+use workspace::{ManifestOverride, ManifestPath, WorkspaceDependencyOverride};
 
 #[derive(StructOpt)]
 #[structopt(bin_name = "cargo")]
@@ -53,6 +57,7 @@ struct VerbosityFlags {
 #[derive(Copy, Clone)]
 enum VerbosityBehavior {
     Quiet,
+    Normal,
     Verbose,
 }
 
@@ -63,7 +68,7 @@ struct AnalysisFlags {
     skip_analysis: bool,
 }
 
-#[derive(PartialEq, Eq, Copy, Clone)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 enum AnalysisBehavior {
     Enforce,
     Skip,
@@ -73,7 +78,7 @@ impl From<VerbosityBehavior> for xargo_lib::Verbosity {
     fn from(behavior: VerbosityBehavior) -> Self {
         match behavior {
             VerbosityBehavior::Verbose => Self::Verbose,
-            VerbosityBehavior::Quiet => Self::Quiet,
+            VerbosityBehavior::Quiet | VerbosityBehavior::Normal => Self::Quiet,
         }
     }
 }
@@ -84,7 +89,7 @@ impl TryFrom<&VerbosityFlags> for VerbosityBehavior {
     fn try_from(value: &VerbosityFlags) -> Result<Self, Self::Error> {
         match (value.quiet, value.verbose) {
             (true, false) => Ok(VerbosityBehavior::Quiet),
-            (false, false) => Ok(VerbosityBehavior::Quiet),
+            (false, false) => Ok(VerbosityBehavior::Normal),
             (false, true) => Ok(VerbosityBehavior::Verbose),
             (true, true) => anyhow::bail!("Cannot pass both --quiet and --verbose flags"),
         }
@@ -117,6 +122,9 @@ impl TryFrom<&AnalysisFlags> for AnalysisBehavior {
 //
 // By default, we will force cargo to start a new compiling session. If you don't want
 // to compile again, you can turn`enforce_analysis` flag on.
+//
+// The `Build` variant is much larger than others, but the command is parsed only once.
+#[allow(clippy::large_enum_variant)]
 #[derive(StructOpt)]
 enum Command {
     /// Sets up and creates a new liquid project.
@@ -132,6 +140,11 @@ enum Command {
     },
     /// Builds the project.
     #[structopt(name = "build")]
+    #[structopt(
+        after_help = "Options marked with [env: ...] can also be set by the environment \
+                              variable. Values passed on the command line take precedence over \
+                              environment variables, which take precedence over default values."
+    )]
     Build {
         #[structopt(flatten)]
         verbosity_flags: VerbosityFlags,
@@ -146,6 +159,650 @@ enum Command {
         /// If this flag is set, the analysis process will produce the whole call graph in dot format.
         #[structopt(short, long)]
         dump_cfg: Option<PathBuf>,
+        /// Uses the specified Cargo.lock to resolve dependencies, the build will fail if the
+        /// lockfile needs to be updated.
+        #[structopt(long, parse(from_os_str))]
+        lockfile: Option<PathBuf>,
+        /// Builds the project against the local `liquid-lang` package at the specified directory
+        /// instead of the one declared in Cargo.toml, by patching it in the temporary workspace.
+        #[structopt(long, parse(from_os_str))]
+        liquid_path: Option<PathBuf>,
+        /// Overrides a field of Cargo.toml in TOML dot notation for this build only, e.g.
+        /// `--manifest-override 'profile.release.codegen-units = 1'`, the file itself is left
+        /// untouched. Can be specified multiple times, `lib.crate-type` and `package.name` can't
+        /// be overridden.
+        #[structopt(long, number_of_values = 1, value_name = "key = value")]
+        manifest_override: Vec<ManifestOverride>,
+        /// Overrides the version of a dependency in `[workspace.dependencies]` of the workspace
+        /// root for this build only, e.g. `--workspace-dependency serde=1.0.100`, which helps to
+        /// test compatibility with other versions. Can be specified multiple times.
+        #[structopt(long, number_of_values = 1, value_name = "NAME=VERSION")]
+        workspace_dependency: Vec<WorkspaceDependencyOverride>,
+        /// Overrides a cargo configuration value for this build only, which is forwarded to
+        /// `cargo build` as `--config KEY=VALUE`, e.g. `--config 'profile.release.lto=true'`.
+        /// Values are in TOML, so strings must be quoted. Can be specified multiple times.
+        #[structopt(long, number_of_values = 1, value_name = "KEY=VALUE")]
+        config: Vec<cmd::CargoConfig>,
+        /// Raw arguments appended to the underlying `xargo build` invocation, separated by
+        /// whitespaces, e.g. `--xargo-args '--offline -Z unstable-options'`. Flags conflicting
+        /// with the ones set by cargo-liquid, e.g. `--target` or `--release`, make xargo fail, and
+        /// `--target-dir` is rejected.
+        #[structopt(long, allow_hyphen_values = true, value_name = "ARGS")]
+        xargo_args: Option<cmd::XargoArgs>,
+        /// Treats all warnings as errors, Clippy will be run with its default lints against the
+        /// project before building.
+        #[structopt(long)]
+        pedantic: bool,
+        /// Comma separated list of artifacts to produce, available artifacts are `wasm`, `abi`,
+        /// `hash` and `manifest`. By default `wasm` and `abi` are produced.
+        #[structopt(long, use_delimiter = true)]
+        emit: Vec<cmd::Artifact>,
+        /// Removes data segments of the Wasm bytecode which rewrite the same bytes at the same
+        /// offset, and trims zero bytes at both ends of data segments. Identical constants at
+        /// different addresses are not merged.
+        #[structopt(long)]
+        trim_data: bool,
+        /// Removes exports which are unreachable from the entry points, along with the functions
+        /// only they use, before optimizing with wasm-opt. Memories and tables are kept.
+        #[structopt(long)]
+        strip_unreachable: bool,
+        /// Prints what changed since the last build, i.e. the number of functions, the size of the
+        /// code and data segments, and exports and imports. Size increases above 5% are red.
+        #[structopt(long)]
+        diff_artifacts: bool,
+        /// Writes a Markdown report of the build to the path, including sizes of the Wasm bytecode
+        /// and its sections, exports, imports, the hash and the git commit, e.g. to attach to a
+        /// pull request.
+        #[structopt(long, parse(from_os_str), value_name = "PATH")]
+        report: Option<PathBuf>,
+        /// Writes metrics of the final Wasm bytecode in JSON to the path: the numbers of
+        /// functions, types, imports, exports, globals and data segments, and the size in bytes.
+        /// This is meant for dashboards tracking growth of the contract across commits.
+        #[structopt(long, parse(from_os_str), value_name = "PATH")]
+        metrics: Option<PathBuf>,
+        /// Hashes all source files of the contract and its dependencies with SHA-256 before
+        /// building and writes them to `<package>.inputs.json` in the target directory. After
+        /// the build, `<package>.build.lock` records the hashes of that file and of the Wasm
+        /// bytecode, which makes a tamper-evident record of the build inputs.
+        #[structopt(long)]
+        report_build_inputs: bool,
+        /// Writes the Wasm bytecode to stdout, all other output will be written to stderr.
+        #[structopt(long)]
+        stdout: bool,
+        /// Records the specified version in the artifact instead of the version in Cargo.toml.
+        #[structopt(long)]
+        version_override: Option<String>,
+        /// Names the Wasm bytecode after a template instead of the package name, e.g.
+        /// `{package}-{version}-{hash}`. Supported placeholders are `{package}`, `{version}`,
+        /// `{hash}`, which is the first 8 hex digits of the hash of the Wasm bytecode, and
+        /// `{profile}`. The hash and the manifest are named after the Wasm bytecode.
+        #[structopt(long, value_name = "TEMPLATE")]
+        name_template: Option<cmd::NameTemplate>,
+        /// Prints the build result in JSON format, all other output will be written to stderr.
+        #[structopt(long)]
+        output_json: bool,
+        /// Collects compiler errors and warnings into the `diagnostics` array of the JSON build
+        /// result, in the format of cargo's `--message-format=json`. If the build fails, only
+        /// the diagnostics are printed.
+        #[structopt(long, requires = "output-json")]
+        json_diagnostics: bool,
+        /// Prints compiler errors and warnings to stdout as a JSON array once cargo finishes,
+        /// in the format of cargo's `--message-format=json`, all other output will be written
+        /// to stderr. This is meant for IDEs, which can then locate errors without parsing the
+        /// human readable output.
+        #[structopt(long, conflicts_with_all = &["stdout", "output-json", "matrix", "workspace"])]
+        emit_json_diagnostics: bool,
+        /// Checks the generated ABI against the specified ABI file, the build will fail if
+        /// any function is removed or has its signature changed.
+        #[structopt(long, parse(from_os_str))]
+        abi_baseline: Option<PathBuf>,
+        /// Don't fail even if the ABI is incompatible with the baseline.
+        #[structopt(long, requires = "abi-baseline")]
+        allow_abi_break: bool,
+        /// The layout of the output directory, must be `liquid` or `cargo-contract`. The latter
+        /// additionally places artifacts in `target/ink/<package>/` as `cargo-contract` does.
+        #[structopt(long, default_value = "liquid")]
+        output_format: cmd::OutputFormat,
+        /// How panics are handled, must be one of `abort`, `immediate-abort` or `unwind`.
+        /// `immediate-abort` produces the smallest bytecode but drops panic messages entirely.
+        /// `abort` keeps panic messages for debugging at the cost of the formatting machinery
+        /// of the core library, which usually adds several KiB. `unwind` is the largest and only
+        /// works on chains whose runtime supports Wasm exceptions.
+        #[structopt(long, default_value = "immediate-abort")]
+        panic: cmd::PanicStrategy,
+        /// Leaves the temporary workspaces used for building on disk and prints their paths.
+        #[structopt(long)]
+        keep_temp: bool,
+        /// The optimization level passed to wasm-opt, must be one of `0`, `1`, `2`, `3`, `4`,
+        /// `s` or `z`, which may also be written as wasm-opt flags, e.g. `Oz`. Falls back to
+        /// `optimization-level` in `[package.metadata.liquid]`, then in the global
+        /// `.cargo-liquid.toml`, then `3`.
+        #[structopt(long, env = "LIQUID_OPTIMIZATION_LEVEL")]
+        optimization_level: Option<cmd::OptimizationLevel>,
+        /// Skips optimizing the Wasm bytecode with wasm-opt. Setting `LIQUID_SKIP_WASM_OPT` to
+        /// `1` or `true` has the same effect.
+        #[structopt(long)]
+        skip_wasm_opt: bool,
+        /// Uses the specified wasm-opt binary instead of the one found in `PATH`. Falls back to
+        /// `wasm-opt-path` in `[package.metadata.liquid]`, then in the global
+        /// `.cargo-liquid.toml`.
+        #[structopt(long, env = "LIQUID_WASM_OPT", parse(from_os_str))]
+        wasm_opt_path: Option<PathBuf>,
+        /// Reports the deepest call chains and the estimated peak stack usage of the contract.
+        #[structopt(long)]
+        emit_stack_usage: bool,
+        /// Writes the artifact manifest, a JSON descriptor of the artifacts for deployment tools,
+        /// to the specified path instead of `<name>.json` in the target directory. Implies
+        /// `--emit manifest`.
+        #[structopt(long, parse(from_os_str))]
+        manifest_out: Option<PathBuf>,
+        /// Describes what every phase of the build does and why, for newcomers to Liquid.
+        #[structopt(long)]
+        explain: bool,
+        /// Reports exports of the contract which are neither entry points nor required by the
+        /// chain, they are candidates to be removed from the source.
+        #[structopt(long)]
+        report_unused_exports: bool,
+        /// Skips the internal tree-shaking of the Wasm bytecode, custom sections are still
+        /// stripped and wasm-opt is still run. This is an escape hatch in case the internal
+        /// optimizer removes something in use, e.g. a function only reachable via a table.
+        #[structopt(long)]
+        no_optimize_internal: bool,
+        /// Copies the Wasm bytecode produced by cargo as is, i.e. skips tree-shaking, section
+        /// stripping and all other processing before wasm-opt is run.
+        #[structopt(
+            long,
+            conflicts_with_all = &[
+                "no-optimize-internal", "keep-exports", "report-unused-exports", "strip-unreachable",
+                "legacy-wasm", "const-propagation", "emit-stack-usage", "strip-panic-messages",
+                "trim-data", "report-data-segments", "inject-gas", "stack-limit",
+            ],
+        )]
+        skip_post_process: bool,
+        /// Keeps the name section and other debug info in the Wasm bytecode through all
+        /// optimizations, which makes the bytecode larger. Use `--split-debug` instead for
+        /// bytecode to be deployed.
+        #[structopt(long, conflicts_with = "split-debug")]
+        preserve_names: bool,
+        /// Keeps all custom sections in the Wasm bytecode, e.g. `producers` and
+        /// `target_features`, in addition to what `--preserve-names` keeps. This is meant for
+        /// diagnosing the raw output of the compiler, not for bytecode to be deployed.
+        #[structopt(long, conflicts_with = "split-debug")]
+        no_strip_sections: bool,
+        /// Injects gas metering into the Wasm bytecode, which charges gas by calling the
+        /// imported `env.gas` function at the start of every block. Only for chains which expect
+        /// metered bytecode.
+        #[structopt(long)]
+        inject_gas: bool,
+        /// Injects a limiter of the stack height into the Wasm bytecode, which traps once the
+        /// height, counted in values on the Wasm stack, exceeds the specified limit. Only for
+        /// chains which expect height-limited bytecode.
+        #[structopt(long, value_name = "N")]
+        stack_limit: Option<u32>,
+        /// Replaces reads of globals which are never written, such as `__heap_base`, with their
+        /// constant values before running wasm-opt, which can then fold them further.
+        #[structopt(long)]
+        const_propagation: bool,
+        /// Skips building the Wasm bytecode, and generates `<name>-client` in the target
+        /// directory from the ABI instead: a crate with type-safe helpers encoding calls to the
+        /// contract, to be used as a path dependency of contracts calling it.
+        #[structopt(long)]
+        interface_mode: bool,
+        /// Prints plain output without colors, emojis or progress bars, e.g. in Docker build
+        /// steps. Enabled automatically if stdin is not a terminal, setting
+        /// `LIQUID_NON_INTERACTIVE` to `1` or `true` has the same effect.
+        #[structopt(long)]
+        non_interactive: bool,
+        /// Keeps the export in the Wasm bytecode in addition to the entry points, can be
+        /// specified multiple times. Exports not found in the bytecode are warned about.
+        #[structopt(long = "keep-export", value_name = "NAME", number_of_values = 1)]
+        keep_exports: Vec<String>,
+        /// Removes the data count section emitted by newer LLVM, which is rejected by some
+        /// conservative runtimes. The section is only needed by bulk memory operations, which
+        /// are never used by bytecode post-processed by cargo-liquid.
+        #[structopt(long)]
+        legacy_wasm: bool,
+        /// Builds the contract once for every named feature set in
+        /// `[package.metadata.liquid.matrix]`, artifacts are written to `<name>-<variant>.wasm`
+        /// and so on.
+        #[structopt(long, conflicts_with_all = &["stdout", "output-json", "manifest-out"])]
+        matrix: bool,
+        /// Builds every member of the workspace which depends on `liquid_lang`, the manifest may
+        /// be a virtual manifest.
+        #[structopt(long, conflicts_with_all = &["stdout", "output-json", "manifest-out"])]
+        workspace: bool,
+        /// Continues building remaining members of the workspace after a failure, then reports
+        /// which contracts succeeded and which failed. Exits with an error if any failed.
+        #[structopt(long, requires = "workspace")]
+        keep_going: bool,
+        /// Kills the build of the cargo project if it doesn't finish within the specified number
+        /// of seconds, which fails fast instead of hanging forever, e.g. on a broken toolchain. Only
+        /// supported on Linux and Windows.
+        #[structopt(long, value_name = "seconds", env = "LIQUID_BUILD_TIMEOUT")]
+        build_timeout: Option<u64>,
+        /// Reports the peak resident memory of the build at the end, including cargo, rustc and
+        /// wasm-opt spawned by it, which helps to size CI runners. Only supported on Linux.
+        #[structopt(long)]
+        report_memory: bool,
+        /// Prints the `RUSTFLAGS` which rustc receives when building the contract, i.e. the
+        /// user's `RUSTFLAGS` merged with flags required by Wasm bytecode for the chain, then
+        /// exits without building.
+        #[structopt(long, conflicts_with_all = &["matrix", "workspace"])]
+        print_rustflags: bool,
+        /// Builds the contract even if nothing changed since the last build with the same
+        /// options, and runs abi-gen even if its cached output is up to date.
+        #[structopt(long)]
+        no_cache: bool,
+        /// Fails the build if the Wasm bytecode is larger than the specified number of bytes,
+        /// overrides `max-size` in `[package.metadata.liquid]`.
+        #[structopt(long, env = "LIQUID_MAX_SIZE")]
+        max_size: Option<usize>,
+        /// Fails the build if the Wasm bytecode is smaller than the specified number of bytes,
+        /// which catches broken builds where the optimization stripped almost everything.
+        #[structopt(long, env = "LIQUID_MIN_SIZE")]
+        min_size: Option<usize>,
+        /// Prints the offset, size and content of every data segment of the Wasm bytecode.
+        #[structopt(long)]
+        report_data_segments: bool,
+        /// Fails the build if the Wasm bytecode contains any floating-point instruction, whose
+        /// results may differ across nodes of the chain. Use `report-floats` to find them.
+        #[structopt(long)]
+        deny_floats: bool,
+        /// Only warns instead of failing if the Wasm bytecode has a start function.
+        #[structopt(long)]
+        allow_start_function: bool,
+        /// Writes debug info (e.g. the name section) to `<name>.debug.wasm` instead of
+        /// discarding it, the Wasm bytecode to be deployed stays stripped.
+        #[structopt(long)]
+        split_debug: bool,
+        /// Strips source file paths of panics from the data section of the Wasm bytecode, which
+        /// reduces its size, but makes panics harder to debug.
+        #[structopt(long)]
+        strip_panic_messages: bool,
+    },
+
+    /// Runs tests of the project on the host.
+    #[structopt(name = "test")]
+    Test {
+        #[structopt(flatten)]
+        verbosity_flags: VerbosityFlags,
+        /// Indicates the manifest to use, must be a Cargo.toml file.
+        #[structopt(short, long)]
+        manifest_path: Option<PathBuf>,
+        /// Disables the `default` feature of the project. Building Wasm bytecode always disables
+        /// default features, while tests are built with them by default, so that `default` can
+        /// enable features only used on the host, e.g. the mocked environment.
+        #[structopt(long)]
+        no_default_features: bool,
+        /// Runs tests with Miri to detect undefined behavior, which requires `cargo-miri` of a
+        /// nightly toolchain. Problems found are summarized with their source locations.
+        #[structopt(long)]
+        miri: bool,
+    },
+
+    /// Builds the project without wasm-opt, with `-O3` and with `-Oz`, then compares sizes of the
+    /// Wasm bytecode and durations of the builds.
+    #[structopt(name = "bench-size")]
+    BenchSize {
+        #[structopt(flatten)]
+        verbosity_flags: VerbosityFlags,
+        /// Indicates the manifest to use, must be a Cargo.toml file.
+        #[structopt(short, long)]
+        manifest_path: Option<PathBuf>,
+    },
+
+    /// Runs tests of the project and generates a LCOV coverage report.
+    #[structopt(name = "coverage")]
+    Coverage {
+        #[structopt(flatten)]
+        verbosity_flags: VerbosityFlags,
+        /// Indicates the manifest to use, must be a Cargo.toml file.
+        #[structopt(short, long)]
+        manifest_path: Option<PathBuf>,
+    },
+
+    /// Checks whether the new ABI of the project is backward compatible with the old one.
+    #[structopt(name = "semver-check")]
+    SemverCheck {
+        /// The ABI file of the previous version.
+        #[structopt(parse(from_os_str))]
+        old_abi: PathBuf,
+        /// The ABI file of the new version.
+        #[structopt(parse(from_os_str))]
+        new_abi: PathBuf,
+        /// Don't fail even if there are binary breaking changes.
+        #[structopt(long)]
+        allow_breaking: bool,
+    },
+
+    /// Profiles the execution of the contract and writes a callgrind-compatible trace file.
+    #[structopt(name = "prof")]
+    Prof {
+        /// The Wasm bytecode of the contract.
+        #[structopt(parse(from_os_str))]
+        wasm: PathBuf,
+        /// Hex encoded call data passed to the contract.
+        #[structopt(long, default_value = "")]
+        call_data: String,
+        /// The path of the trace file.
+        #[structopt(short, long, parse(from_os_str), default_value = "callgrind.out")]
+        output: PathBuf,
+    },
+
+    /// Prints the graph of transitive dependencies of the contract, annotated with the number of
+    /// source files and lines of every package.
+    #[structopt(name = "graph-deps")]
+    GraphDeps {
+        /// Indicates the manifest to use, must be a Cargo.toml file.
+        #[structopt(short, long)]
+        manifest_path: Option<PathBuf>,
+        /// The format of the graph, `dot`, `json` or `mermaid`.
+        #[structopt(long, default_value = "dot")]
+        format: cmd::GraphFormat,
+    },
+
+    /// Lists host functions and other items imported by the Wasm bytecode, which the node must
+    /// provide.
+    #[structopt(name = "show-imports")]
+    ShowImports {
+        /// The Wasm bytecode of the contract.
+        #[structopt(parse(from_os_str))]
+        wasm: PathBuf,
+        /// Checks the imported functions against host functions supported by the node at the
+        /// JSON-RPC endpoint, fails if any is unsupported.
+        #[structopt(long, value_name = "RPC_URL")]
+        compare_with_node: Option<String>,
+        /// Prints the imports in JSON format.
+        #[structopt(long, conflicts_with = "compare-with-node")]
+        json: bool,
+    },
+
+    /// Attributes the code size of the Wasm bytecode to the source modules of the contract and
+    /// its dependencies.
+    #[structopt(name = "size-profile")]
+    SizeProfile {
+        /// The Wasm bytecode of the contract, which must be built with `--preserve-names`.
+        #[structopt(parse(from_os_str))]
+        wasm: PathBuf,
+    },
+
+    /// Reports floating-point instructions in the Wasm bytecode, which should not be used by
+    /// contracts since their results may differ across platforms.
+    #[structopt(name = "report-floats")]
+    ReportFloats {
+        /// The Wasm bytecode of the contract.
+        #[structopt(parse(from_os_str))]
+        wasm: PathBuf,
+    },
+
+    /// Compares two builds of a contract at the binary level, i.e. sizes of sections, exports
+    /// and imports.
+    #[structopt(name = "diff")]
+    Diff {
+        /// The Wasm bytecode of the old build.
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+        /// The Wasm bytecode of the new build.
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+    },
+
+    /// Removes function, local and label names and DWARF debug info from the Wasm bytecode,
+    /// which hides the Rust symbols of the contract. This is irreversible.
+    #[structopt(name = "strip-names")]
+    StripNames {
+        /// The Wasm bytecode to strip.
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Where to write the stripped bytecode, defaults to overwriting the input.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Removes exports which are unreachable from the entry points, along with the functions only
+    /// they use.
+    #[structopt(name = "strip-unreachable")]
+    StripUnreachable {
+        /// The Wasm bytecode to strip.
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Where to write the stripped bytecode, defaults to overwriting the input.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// Exports kept as entry points, can be specified multiple times, defaults to `main` and
+        /// `deploy`. Exports required by the chain are always kept.
+        #[structopt(long = "entry-point", number_of_values = 1)]
+        entry_points: Vec<String>,
+    },
+
+    /// Post-processes existing Wasm bytecode like `build` does, without building, i.e.
+    /// tree-shaking, stripping sections not required by the chain and running wasm-opt.
+    #[structopt(name = "optimize")]
+    Optimize {
+        /// The Wasm bytecode to optimize.
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+        /// Where to write the optimized bytecode, defaults to overwriting the input.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// Exports kept as entry points, can be specified multiple times, defaults to `main` and
+        /// `deploy`. Exports required by the chain are always kept.
+        #[structopt(long = "entry-point", number_of_values = 1)]
+        entry_points: Vec<String>,
+        /// Keeps custom sections, e.g. the name section, instead of stripping them.
+        #[structopt(long)]
+        keep_custom_sections: bool,
+        /// Removes the data count section, which is rejected by some conservative runtimes.
+        #[structopt(long)]
+        legacy_wasm: bool,
+        /// Skips optimizing the Wasm bytecode with wasm-opt.
+        #[structopt(long)]
+        skip_wasm_opt: bool,
+        /// The optimization level passed to wasm-opt, falls back to `optimization-level` in the
+        /// global `.cargo-liquid.toml`, then `3`.
+        #[structopt(long, env = "LIQUID_OPTIMIZATION_LEVEL")]
+        optimization_level: Option<cmd::OptimizationLevel>,
+        /// Uses the specified wasm-opt binary instead of the one found in `PATH`.
+        #[structopt(long, env = "LIQUID_WASM_OPT", parse(from_os_str))]
+        wasm_opt_path: Option<PathBuf>,
+    },
+
+    /// Generates the man page of cargo-liquid describing all commands and flags.
+    #[structopt(name = "man")]
+    Man {
+        /// The directory where `cargo-liquid.1` is written, defaults to the current directory.
+        #[structopt(long, parse(from_os_str), default_value = ".")]
+        output_dir: PathBuf,
+    },
+
+    /// Pretty-prints an ABI file in place.
+    #[structopt(name = "format-abi")]
+    FormatAbi {
+        /// The ABI file to be formatted.
+        #[structopt(parse(from_os_str))]
+        abi: PathBuf,
+        /// Checks whether the ABI file is formatted without modifying it, fails if it's not.
+        #[structopt(long)]
+        check: bool,
+    },
+
+    /// Disassembles the Wasm bytecode into a human readable listing.
+    #[structopt(name = "disassemble")]
+    Disassemble {
+        /// The Wasm bytecode to be disassembled.
+        #[structopt(parse(from_os_str))]
+        wasm: PathBuf,
+        /// Writes the listing to the specified file instead of stdout.
+        #[structopt(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Estimates a safe gas limit for transactions calling the contract.
+    #[structopt(name = "gas-limit")]
+    GasLimit {
+        /// The Wasm bytecode of the contract.
+        #[structopt(parse(from_os_str))]
+        wasm: PathBuf,
+        /// The ABI of the contract, used to calculate the selector of the function.
+        #[structopt(long, parse(from_os_str))]
+        abi: PathBuf,
+        /// The public function to be called.
+        #[structopt(long)]
+        function: String,
+        /// Size of the random arguments following the selector in the call data, in bytes.
+        #[structopt(long, default_value = "32")]
+        args_size: usize,
+        /// Executes the contract with multiple random inputs and takes the gas used by the
+        /// specified percentile of them.
+        #[structopt(long)]
+        percentile: Option<u8>,
+        /// Calculates the selector as in GM builds.
+        #[structopt(short, long)]
+        gm: bool,
+    },
+
+    /// Executes a function of the contract in a mocked environment, without deploying it to a
+    /// node.
+    #[structopt(name = "run-local")]
+    RunLocal {
+        /// The Wasm bytecode of the contract.
+        #[structopt(parse(from_os_str))]
+        wasm: PathBuf,
+        /// The exported function to call.
+        #[structopt(default_value = "main")]
+        function: String,
+        /// Arguments of the function, written as `<type>:<value>`, e.g. `i64:42`. Values
+        /// without a type are `i32`.
+        #[structopt(allow_hyphen_values = true)]
+        args: Vec<cmd::Value>,
+        /// The JSON file persisting the storage of the contract across runs, which maps hex
+        /// encoded keys to hex encoded values. It's created if it doesn't exist.
+        #[structopt(long, parse(from_os_str))]
+        storage: Option<PathBuf>,
+        /// The hex encoded call data read by the contract, e.g. the selector of a method
+        /// followed by its SCALE encoded arguments.
+        #[structopt(long, default_value = "")]
+        call_data: String,
+    },
+
+    /// Compares gas used by every public function of two versions of a contract, by calling
+    /// them with zeroed arguments in a mocked environment.
+    #[structopt(name = "compare-gas")]
+    CompareGas {
+        /// The Wasm bytecode of the old version.
+        #[structopt(parse(from_os_str))]
+        old: PathBuf,
+        /// The Wasm bytecode of the new version.
+        #[structopt(parse(from_os_str))]
+        new: PathBuf,
+        /// The ABI listing public functions to be measured.
+        #[structopt(long, parse(from_os_str))]
+        abi: PathBuf,
+        /// Prints the comparison in JSON format.
+        #[structopt(long)]
+        json: bool,
+        /// Calculates selectors as in GM builds.
+        #[structopt(short, long)]
+        gm: bool,
+    },
+
+    /// Checks whether call data encodes a valid call of a function in the ABI, and prints the
+    /// decoded arguments.
+    #[structopt(name = "check-abi-compatibility")]
+    CheckAbiCompat {
+        /// The ABI of the contract.
+        #[structopt(long, parse(from_os_str))]
+        abi: PathBuf,
+        /// Hex encoded call data, i.e. the selector followed by the arguments.
+        call_data: String,
+        /// Prints the decoded call in JSON format.
+        #[structopt(long)]
+        json: bool,
+        /// Calculates selectors as in GM builds.
+        #[structopt(short, long)]
+        gm: bool,
+    },
+
+    /// Checks whether the contract deployed on chain matches the local Wasm bytecode.
+    #[structopt(name = "hash-check")]
+    HashCheck {
+        /// The local Wasm bytecode of the contract.
+        #[structopt(parse(from_os_str))]
+        wasm: PathBuf,
+        /// The JSON-RPC endpoint of the node.
+        #[structopt(long, default_value = "http://127.0.0.1:20200")]
+        rpc_url: String,
+        /// The address of the deployed contract.
+        #[structopt(long)]
+        address: String,
+    },
+
+    /// Builds the contract and checks whether the bytecode deployed on chain is byte-for-byte
+    /// the same, e.g. to verify that a deployed contract was built from the local sources.
+    #[structopt(name = "verify-bytecode")]
+    VerifyBytecode {
+        /// Indicates the manifest to use, must be a Cargo.toml file.
+        #[structopt(short, long)]
+        manifest_path: Option<PathBuf>,
+        /// Builds the contract in GM mode, which must match the deployed build.
+        #[structopt(short, long)]
+        gm: bool,
+        /// The JSON-RPC endpoint of the node.
+        #[structopt(long, default_value = "http://127.0.0.1:20200")]
+        rpc_url: String,
+        /// The address of the deployed contract.
+        #[structopt(long)]
+        address: String,
+    },
+
+    /// Queries the runtime version and capabilities of a node, and stores them in
+    /// `.liquid-node-info.json` of the current directory.
+    #[structopt(name = "query-node")]
+    QueryNode {
+        /// The JSON-RPC endpoint of the node.
+        #[structopt(long, default_value = "http://127.0.0.1:20200")]
+        rpc_url: String,
+    },
+
+    /// Uploads a contract package to a contract registry.
+    #[structopt(name = "publish")]
+    Publish {
+        /// The `.lqpkg` contract package to be uploaded.
+        #[structopt(parse(from_os_str))]
+        package: PathBuf,
+        /// The URL of the contract registry.
+        #[structopt(long, env = "LIQUID_REGISTRY_URL")]
+        registry_url: String,
+        /// The API key to authenticate with the registry.
+        #[structopt(
+            long,
+            env = "LIQUID_REGISTRY_API_KEY",
+            hide_env_values = true,
+            required_unless = "dry-run"
+        )]
+        api_key: Option<String>,
+        /// Validates the package and prints what would be uploaded without uploading it.
+        #[structopt(long)]
+        dry_run: bool,
+    },
+
+    /// Checks `Cargo.toml` of the project against Liquid best practices.
+    #[structopt(name = "validate-manifest")]
+    ValidateManifest {
+        /// Indicates the manifest to use, must be a Cargo.toml file.
+        #[structopt(short, long)]
+        manifest_path: Option<PathBuf>,
+    },
+
+    /// Prints environment variables set during the build in shell-source format, which can be
+    /// evaluated to reproduce the build conditions manually.
+    #[structopt(name = "env")]
+    Env {
+        /// Indicates the manifest to use, must be a Cargo.toml file.
+        #[structopt(short, long)]
+        manifest_path: Option<PathBuf>,
+        #[structopt(flatten)]
+        analysis_flags: AnalysisFlags,
     },
 
     ///Rename the project , you need to update name in the cargo-liquid dir and promise the project exits in that.
@@ -158,11 +815,38 @@ enum Command {
     },
 }
 
+/// Checks whether a boolean flag is turned on by the environment variable.
+fn env_flag(name: &str) -> bool {
+    std::env::var(name)
+        .map(|value| value == "1" || value.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+/// Applies `color` of the global config.
+fn configure_color() -> Result<()> {
+    let config = config::GlobalConfig::load()?;
+    match config.color.as_deref() {
+        None | Some("auto") => (),
+        Some("always") => colored::control::set_override(true),
+        Some("never") => colored::control::set_override(false),
+        Some(color) => anyhow::bail!(
+            "unknown color `{}` in '{}', must be one of `auto`, `always` or `never`",
+            color,
+            config::GlobalConfig::path().unwrap_or_default().display()
+        ),
+    }
+    Ok(())
+}
+
 fn main() {
     let Opts::Args(args) = Opts::from_args();
-    match exec(args.cmd) {
+    match configure_color().and_then(|_| exec(args.cmd)) {
+        Ok(msg) if msg.is_empty() => (),
         Ok(msg) => println!("{}", msg.bold()),
-        Err(err) => eprintln!("{} {}", "ERROR:".bright_red().bold(), format!("{:?}", err)),
+        Err(err) => {
+            eprintln!("{} {}", "ERROR:".bright_red().bold(), format!("{:?}", err));
+            std::process::exit(1);
+        }
     }
 }
 
@@ -179,6 +863,63 @@ fn exec(cmd: Command) -> Result<String> {
             manifest_path,
             dump_cfg,
             analysis_flags,
+            lockfile,
+            liquid_path,
+            manifest_override,
+            workspace_dependency,
+            config,
+            xargo_args,
+            pedantic,
+            emit,
+            trim_data,
+            strip_unreachable,
+            diff_artifacts,
+            report,
+            metrics,
+            report_build_inputs,
+            stdout,
+            version_override,
+            name_template,
+            output_json,
+            json_diagnostics,
+            emit_json_diagnostics,
+            abi_baseline,
+            allow_abi_break,
+            output_format,
+            panic,
+            keep_temp,
+            optimization_level,
+            skip_wasm_opt,
+            wasm_opt_path,
+            emit_stack_usage,
+            manifest_out,
+            explain,
+            report_unused_exports,
+            no_optimize_internal,
+            skip_post_process,
+            preserve_names,
+            no_strip_sections,
+            inject_gas,
+            stack_limit,
+            const_propagation,
+            interface_mode,
+            non_interactive,
+            keep_exports,
+            legacy_wasm,
+            matrix,
+            workspace,
+            keep_going,
+            build_timeout,
+            report_memory,
+            print_rustflags,
+            no_cache,
+            max_size,
+            min_size,
+            report_data_segments,
+            deny_floats,
+            allow_start_function,
+            split_debug,
+            strip_panic_messages,
         } => cmd::execute_build(
             manifest_path
                 .as_ref()
@@ -189,7 +930,472 @@ fn exec(cmd: Command) -> Result<String> {
             verbosity_flags.try_into()?,
             analysis_flags.try_into()?,
             dump_cfg,
+            cmd::BuildOptions {
+                lockfile: lockfile.clone(),
+                liquid_path: liquid_path.clone(),
+                manifest_overrides: manifest_override.clone(),
+                workspace_dependency_overrides: workspace_dependency.clone(),
+                cargo_configs: config.clone(),
+                xargo_args: xargo_args.clone(),
+                pedantic: *pedantic,
+                emit: emit.clone(),
+                trim_data: *trim_data,
+                strip_unreachable: *strip_unreachable,
+                diff_artifacts: *diff_artifacts,
+                report: report.clone(),
+                metrics: metrics.clone(),
+                report_build_inputs: *report_build_inputs,
+                stdout: *stdout,
+                version_override: version_override.clone(),
+                output_json: *output_json,
+                json_diagnostics: *json_diagnostics,
+                emit_json_diagnostics: *emit_json_diagnostics,
+                abi_baseline: abi_baseline.clone(),
+                allow_abi_break: *allow_abi_break,
+                output_format: *output_format,
+                panic: *panic,
+                keep_temp: *keep_temp,
+                optimization_level: *optimization_level,
+                skip_wasm_opt: *skip_wasm_opt || env_flag("LIQUID_SKIP_WASM_OPT"),
+                wasm_opt_path: wasm_opt_path.clone(),
+                emit_stack_usage: *emit_stack_usage,
+                manifest_out: manifest_out.clone(),
+                explain: *explain,
+                report_unused_exports: *report_unused_exports,
+                no_optimize_internal: *no_optimize_internal,
+                skip_post_process: *skip_post_process,
+                preserve_names: *preserve_names,
+                no_strip_sections: *no_strip_sections,
+                inject_gas: *inject_gas,
+                stack_limit: *stack_limit,
+                const_propagation: *const_propagation,
+                interface_mode: *interface_mode,
+                non_interactive: *non_interactive
+                    || env_flag("LIQUID_NON_INTERACTIVE")
+                    || !std::io::stdin().is_terminal(),
+                keep_exports: keep_exports.clone(),
+                legacy_wasm: *legacy_wasm,
+                matrix: *matrix,
+                workspace: *workspace,
+                keep_going: *keep_going,
+                build_timeout: build_timeout.map(Duration::from_secs),
+                report_memory: *report_memory,
+                print_rustflags: *print_rustflags,
+                variant: None,
+                no_cache: *no_cache,
+                quiet: false,
+                name_template: name_template.clone(),
+                max_size: *max_size,
+                min_size: *min_size,
+                report_data_segments: *report_data_segments,
+                deny_floats: *deny_floats,
+                allow_start_function: *allow_start_function,
+                split_debug: *split_debug,
+                strip_panic_messages: *strip_panic_messages,
+            },
         ),
+        Command::Test {
+            verbosity_flags,
+            manifest_path,
+            no_default_features,
+            miri,
+        } => {
+            cmd::execute_test(
+                manifest_path
+                    .as_ref()
+                    .map_or(Default::default(), |manifest_path| {
+                        ManifestPath::new(manifest_path).expect("invalid manifest path")
+                    }),
+                verbosity_flags.try_into()?,
+                *no_default_features,
+                *miri,
+            )?;
+            Ok(String::new())
+        }
+        Command::BenchSize {
+            verbosity_flags,
+            manifest_path,
+        } => {
+            let benchmark = cmd::execute_bench_size(
+                manifest_path
+                    .as_ref()
+                    .map_or(Default::default(), |manifest_path| {
+                        ManifestPath::new(manifest_path).expect("invalid manifest path")
+                    }),
+                verbosity_flags.try_into()?,
+            )?;
+            println!("{: <12}  {: >10}  {: >8}", "Pass", "Size", "Time");
+            for measurement in &benchmark.measurements {
+                println!(
+                    "{: <12}  {: >10}  {: >7.1}s",
+                    measurement.pass,
+                    measurement.size,
+                    measurement.duration.as_secs_f64()
+                );
+            }
+            Ok(String::new())
+        }
+        Command::Coverage {
+            verbosity_flags,
+            manifest_path,
+        } => {
+            let report_path = cmd::execute_coverage(
+                manifest_path
+                    .as_ref()
+                    .map_or(Default::default(), |manifest_path| {
+                        ManifestPath::new(manifest_path).expect("invalid manifest path")
+                    }),
+                verbosity_flags.try_into()?,
+            )?;
+            Ok(format!("Coverage report: {}", report_path.display()))
+        }
+        Command::SemverCheck {
+            old_abi,
+            new_abi,
+            allow_breaking,
+        } => {
+            let issues = cmd::execute_semver_check(old_abi, new_abi)?;
+            for issue in &issues {
+                println!("{}", issue);
+            }
+            let breaking = issues
+                .iter()
+                .any(|issue| issue.compatibility == cmd::Compatibility::BinaryBreaking);
+            if breaking && !*allow_breaking {
+                anyhow::bail!("the new ABI contains binary breaking changes");
+            }
+            Ok(format!("{} changes found in the ABI", issues.len()))
+        }
+        Command::Prof {
+            wasm,
+            call_data,
+            output,
+        } => {
+            cmd::execute_prof(wasm, &utils::decode_hex(call_data)?, output)?;
+            Ok(format!("Profiling trace: {}", output.display()))
+        }
+        Command::GraphDeps {
+            manifest_path,
+            format,
+        } => {
+            cmd::execute_graph_deps(
+                manifest_path
+                    .as_ref()
+                    .map_or(Default::default(), |manifest_path| {
+                        ManifestPath::new(manifest_path).expect("invalid manifest path")
+                    }),
+                *format,
+            )?;
+            Ok(String::new())
+        }
+        Command::ShowImports {
+            wasm,
+            compare_with_node,
+            json,
+        } => {
+            let imports = cmd::execute_show_imports(wasm)?;
+            if *json {
+                return Ok(serde_json::to_string_pretty(&imports)?);
+            }
+            cmd::print_imports(&imports, compare_with_node.as_deref())?;
+            Ok(String::new())
+        }
+        Command::SizeProfile { wasm } => {
+            let entries = cmd::execute_size_profile(wasm)?;
+            let width = entries
+                .iter()
+                .map(|entry| entry.module.len())
+                .max()
+                .unwrap_or_default();
+            for entry in &entries {
+                println!(
+                    "{: <width$}  {: >8} bytes  {: >5.1}%",
+                    entry.module,
+                    entry.code_bytes,
+                    entry.percentage,
+                    width = width
+                );
+            }
+            Ok(String::new())
+        }
+        Command::ReportFloats { wasm } => {
+            let floats = cmd::execute_report_floats(wasm)?;
+            for float in &floats {
+                println!(
+                    "func[{}] +{}: {}",
+                    float.function, float.offset, float.opcode
+                );
+            }
+            Ok(format!(
+                "{} floating-point instructions found in '{}'",
+                floats.len(),
+                wasm.display()
+            ))
+        }
+        Command::Diff { old, new } => {
+            cmd::execute_diff(old, new)?;
+            Ok(String::new())
+        }
+        Command::StripNames { input, output } => {
+            let output = output.as_ref().unwrap_or(input);
+            let removed = cmd::execute_strip_names(input, output)?;
+            if removed.is_empty() {
+                Ok(format!(
+                    "No name found, '{}' is written as it is",
+                    output.display()
+                ))
+            } else {
+                Ok(format!(
+                    "Removed {} from '{}'",
+                    removed
+                        .iter()
+                        .map(|name| format!("`{}`", name))
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    output.display()
+                ))
+            }
+        }
+        Command::StripUnreachable {
+            input,
+            output,
+            entry_points,
+        } => {
+            let output = output.as_ref().unwrap_or(input);
+            let stripped = cmd::execute_strip_unreachable(input, output, entry_points)?;
+            for export in &stripped.exports {
+                println!("  removed export `{}`", export);
+            }
+            Ok(format!(
+                "Removed {} exports and {} functions from '{}'",
+                stripped.exports.len(),
+                stripped.functions,
+                output.display()
+            ))
+        }
+        Command::Optimize {
+            input,
+            output,
+            entry_points,
+            keep_custom_sections,
+            legacy_wasm,
+            skip_wasm_opt,
+            optimization_level,
+            wasm_opt_path,
+        } => {
+            let output = output.as_ref().unwrap_or(input);
+            let sizes = cmd::execute_optimize(
+                input,
+                output,
+                cmd::OptimizeOptions {
+                    entry_points,
+                    keep_custom_sections: *keep_custom_sections,
+                    legacy_wasm: *legacy_wasm,
+                    skip_wasm_opt: *skip_wasm_opt || env_flag("LIQUID_SKIP_WASM_OPT"),
+                    optimization_level: *optimization_level,
+                    wasm_opt_path: wasm_opt_path.as_deref(),
+                },
+            )?;
+            println!("{: >9}: {} bytes", "Original", sizes.original);
+            println!("{: >9}: {} bytes", "Processed", sizes.processed);
+            if let Some(optimized) = sizes.optimized {
+                println!("{: >9}: {} bytes", "wasm-opt", optimized);
+            }
+            Ok(format!(
+                "Optimized Wasm bytecode written to '{}'",
+                output.display()
+            ))
+        }
+        Command::Man { output_dir } => {
+            let path = cmd::execute_man(&Opts::clap(), output_dir)?;
+            Ok(format!("Man page written to '{}'", path.display()))
+        }
+        Command::FormatAbi { abi, check } => {
+            cmd::execute_format_abi(abi, *check)?;
+            Ok(String::new())
+        }
+        Command::Disassemble { wasm, output } => {
+            cmd::execute_disassemble(wasm, output.as_deref())?;
+            Ok(String::new())
+        }
+        Command::RunLocal {
+            wasm,
+            function,
+            args,
+            storage,
+            call_data,
+        } => {
+            let call_data = utils::decode_hex(call_data)?;
+            let values =
+                cmd::execute_run_local(wasm, function, args, storage.as_deref(), call_data)?;
+            if values.is_empty() {
+                return Ok(String::new());
+            }
+            Ok(format!(
+                "Returned: {}",
+                values
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ))
+        }
+        Command::GasLimit {
+            wasm,
+            abi,
+            function,
+            args_size,
+            percentile,
+            gm,
+        } => {
+            let gas_limit =
+                cmd::execute_gas_limit(wasm, abi, function, *args_size, *percentile, *gm)?;
+            Ok(format!("Suggested gas limit: {}", gas_limit))
+        }
+        Command::CompareGas {
+            old,
+            new,
+            abi,
+            json,
+            gm,
+        } => {
+            let comparison = cmd::execute_compare_gas(old, new, abi, *gm)?;
+            if *json {
+                Ok(serde_json::to_string_pretty(&comparison)?)
+            } else {
+                print!("{}", comparison);
+                Ok(String::new())
+            }
+        }
+        Command::CheckAbiCompat {
+            abi,
+            call_data,
+            json,
+            gm,
+        } => {
+            let call = cmd::execute_check_abi_compat(abi, &utils::decode_hex(call_data)?, *gm)?;
+            if *json {
+                return Ok(serde_json::to_string_pretty(&call)?);
+            }
+            println!("{}", call.function_name.bold());
+            for arg in &call.decoded_args {
+                println!("  {}: {} = {}", arg.name, arg.ty, arg.value);
+            }
+            Ok(String::new())
+        }
+        Command::HashCheck {
+            wasm,
+            rpc_url,
+            address,
+        } => {
+            cmd::execute_hash_check(wasm, rpc_url, address)?;
+            Ok(String::new())
+        }
+        Command::VerifyBytecode {
+            manifest_path,
+            gm,
+            rpc_url,
+            address,
+        } => {
+            let result = cmd::execute_verify_bytecode(
+                manifest_path
+                    .as_ref()
+                    .map_or(Default::default(), |manifest_path| {
+                        ManifestPath::new(manifest_path).expect("invalid manifest path")
+                    }),
+                *gm,
+                rpc_url,
+                address,
+            )?;
+            if result.matched {
+                println!("{}", "MATCH".green().bold());
+            } else {
+                println!("{}", "MISMATCH".bright_red().bold());
+            }
+            println!("{: >8}: {}", "Local", result.local_sha256);
+            println!("{: >8}: {}", "On chain", result.onchain_sha256);
+            if result.matched {
+                return Ok(String::new());
+            }
+            match &result.section_mismatches {
+                Some(sections) => {
+                    let width = sections
+                        .iter()
+                        .map(|section| section.name.len())
+                        .chain(Some("Section".len()))
+                        .max()
+                        .unwrap_or_default();
+                    println!(
+                        "{: <width$}  {: >10}  {: >10}",
+                        "Section",
+                        "Local",
+                        "On chain",
+                        width = width
+                    );
+                    for section in sections {
+                        println!(
+                            "{: <width$}  {: >10}  {: >10}",
+                            section.name,
+                            section.local,
+                            section.onchain,
+                            width = width
+                        );
+                    }
+                }
+                None => println!("The bytecode deployed on chain is not a Wasm module"),
+            }
+            anyhow::bail!(
+                "the contract deployed at `{}` doesn't match the local build",
+                address
+            )
+        }
+        Command::QueryNode { rpc_url } => {
+            cmd::execute_query_node(rpc_url)?;
+            Ok(String::new())
+        }
+        Command::Publish {
+            package,
+            registry_url,
+            api_key,
+            dry_run,
+        } => cmd::execute_publish(
+            package,
+            registry_url,
+            api_key.as_deref().unwrap_or_default(),
+            *dry_run,
+        ),
+        Command::ValidateManifest { manifest_path } => {
+            let manifest_path = manifest_path
+                .as_ref()
+                .map_or(Default::default(), |manifest_path| {
+                    ManifestPath::new(manifest_path).expect("invalid manifest path")
+                });
+            let warnings = cmd::execute_validate_manifest(&manifest_path)?;
+            for warning in &warnings {
+                eprintln!("{}: {}", "warning".yellow().bold(), warning.message);
+                eprintln!("  {} {}", "help:".bold(), warning.suggestion);
+            }
+            if warnings.is_empty() {
+                Ok("No problem found in the manifest".to_owned())
+            } else {
+                Ok(format!(
+                    "{} problem(s) found in the manifest",
+                    warnings.len()
+                ))
+            }
+        }
+        Command::Env {
+            manifest_path,
+            analysis_flags,
+        } => {
+            let manifest_path = manifest_path
+                .as_ref()
+                .map_or(Default::default(), |manifest_path| {
+                    ManifestPath::new(manifest_path).expect("invalid manifest path")
+                });
+            cmd::execute_env(&manifest_path, analysis_flags.try_into()?)?;
+            Ok(String::new())
+        }
         Command::Rename { old_name, new_name } => cmd::execute_rename(old_name, new_name),
     }
 }
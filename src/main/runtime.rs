@@ -0,0 +1,337 @@
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use parity_wasm::elements::Module;
+use std::{cell::RefCell, collections::BTreeMap, fmt};
+use wasmi::{
+    Externals, FuncInstance, FuncRef, GlobalDescriptor, GlobalRef, HostError, ImportResolver,
+    MemoryDescriptor, MemoryRef, ModuleInstance, ModuleRef, RuntimeArgs, RuntimeValue, Signature,
+    TableDescriptor, TableRef, Trap, TrapKind, ValueType,
+};
+
+/// The entry point invoked by transactions calling the contract.
+pub const CALL_ENTRY_POINT: &str = "main";
+
+/// The module from which the gas counter injected by `pwasm_utils::inject_gas_counter` is
+/// imported.
+pub const GAS_MODULE: &str = "liquid-gas";
+
+/// The way a contract finishes its execution.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Outcome {
+    /// The contract calls `finish` or simply returns from the entry point.
+    Finished,
+    /// The contract calls `revert`.
+    Reverted,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Outcome::Finished => write!(f, "finished"),
+            Outcome::Reverted => write!(f, "reverted"),
+        }
+    }
+}
+
+impl HostError for Outcome {}
+
+/// Resolves all imported functions of a contract as host functions of `MockEnvironment`.
+///
+/// Every imported function is accepted here, functions unknown to `MockEnvironment` do nothing
+/// and return zero.
+#[derive(Default)]
+struct Resolver {
+    host_functions: RefCell<Vec<(String, String, Signature)>>,
+}
+
+impl ImportResolver for Resolver {
+    fn resolve_func(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        signature: &Signature,
+    ) -> Result<FuncRef, wasmi::Error> {
+        let mut host_functions = self.host_functions.borrow_mut();
+        host_functions.push((
+            module_name.to_owned(),
+            field_name.to_owned(),
+            signature.clone(),
+        ));
+        Ok(FuncInstance::alloc_host(
+            signature.clone(),
+            host_functions.len() - 1,
+        ))
+    }
+
+    fn resolve_global(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _global_type: &GlobalDescriptor,
+    ) -> Result<GlobalRef, wasmi::Error> {
+        Err(wasmi::Error::Instantiation(format!(
+            "importing global `{}.{}` is not supported",
+            module_name, field_name
+        )))
+    }
+
+    fn resolve_memory(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _memory_type: &MemoryDescriptor,
+    ) -> Result<MemoryRef, wasmi::Error> {
+        Err(wasmi::Error::Instantiation(format!(
+            "importing memory `{}.{}` is not supported, contract should export its memory",
+            module_name, field_name
+        )))
+    }
+
+    fn resolve_table(
+        &self,
+        module_name: &str,
+        field_name: &str,
+        _table_type: &TableDescriptor,
+    ) -> Result<TableRef, wasmi::Error> {
+        Err(wasmi::Error::Instantiation(format!(
+            "importing table `{}.{}` is not supported",
+            module_name, field_name
+        )))
+    }
+}
+
+/// A minimal mock of the host environment provided by FISCO BCOS, which allows contracts to be
+/// executed locally without a node.
+pub struct MockEnvironment {
+    call_data: Vec<u8>,
+    storage: BTreeMap<Vec<u8>, Vec<u8>>,
+    output: Vec<u8>,
+    gas_used: u64,
+    host_functions: Vec<(String, String, Signature)>,
+    memory: Option<MemoryRef>,
+}
+
+impl MockEnvironment {
+    pub fn new(call_data: Vec<u8>) -> Self {
+        MockEnvironment {
+            call_data,
+            storage: BTreeMap::new(),
+            output: Vec::new(),
+            gas_used: 0,
+            host_functions: Vec::new(),
+            memory: None,
+        }
+    }
+
+    /// Instantiates the contract with all its imports resolved by this environment.
+    pub fn instantiate(&mut self, module: Module) -> Result<ModuleRef> {
+        let module = wasmi::Module::from_parity_wasm_module(module)
+            .map_err(|e| anyhow::anyhow!("invalid Wasm module: {}", e))?;
+        let resolver = Resolver::default();
+        let not_started = ModuleInstance::new(&module, &resolver)
+            .map_err(|e| anyhow::anyhow!("unable to instantiate the Wasm module: {}", e))?;
+
+        // The start function may call imported functions already, which need the resolved
+        // host functions and the memory.
+        self.host_functions = resolver.host_functions.into_inner();
+        self.memory = not_started
+            .not_started_instance()
+            .export_by_name("memory")
+            .and_then(|export| export.as_memory().cloned());
+        if self.memory.is_none() {
+            anyhow::bail!("the contract doesn't export its memory");
+        }
+        not_started
+            .run_start(self)
+            .map_err(|e| anyhow::anyhow!("start function trapped: {}", e))
+    }
+
+    /// Invokes the specified entry point of the contract.
+    pub fn invoke(&mut self, instance: &ModuleRef, entry_point: &str) -> Result<Outcome> {
+        self.invoke_with_args(instance, entry_point, &[])
+            .map(|(outcome, _)| outcome)
+    }
+
+    /// Invokes the specified exported function of the contract with `args`, returns the value
+    /// returned by the function if it returns normally.
+    pub fn invoke_with_args(
+        &mut self,
+        instance: &ModuleRef,
+        function: &str,
+        args: &[RuntimeValue],
+    ) -> Result<(Outcome, Option<RuntimeValue>)> {
+        self.output.clear();
+        match instance.invoke_export(function, args, self) {
+            Ok(value) => Ok((Outcome::Finished, value)),
+            Err(e) => match e.as_host_error().and_then(|e| e.downcast_ref::<Outcome>()) {
+                Some(outcome) => Ok((*outcome, None)),
+                None => Err(anyhow::anyhow!("executing `{}` failed: {}", function, e)),
+            },
+        }
+    }
+
+    /// Storage of the contract, which is empty unless set by `set_storage`.
+    pub fn storage(&self) -> &BTreeMap<Vec<u8>, Vec<u8>> {
+        &self.storage
+    }
+
+    pub fn set_storage(&mut self, storage: BTreeMap<Vec<u8>, Vec<u8>>) {
+        self.storage = storage;
+    }
+
+    /// Data passed to `finish` or `revert` by the last invocation.
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+
+    /// Gas charged by the injected gas counter so far.
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    fn memory(&self) -> &MemoryRef {
+        self.memory
+            .as_ref()
+            .expect("memory should be resolved after instantiation")
+    }
+
+    fn read_memory(&self, args: &RuntimeArgs, offset: usize, len: usize) -> Result<Vec<u8>, Trap> {
+        let offset: u32 = args.nth_checked(offset)?;
+        let len: u32 = args.nth_checked(len)?;
+        self.memory()
+            .get(offset, len as usize)
+            .map_err(|_| Trap::new(TrapKind::MemoryAccessOutOfBounds))
+    }
+
+    fn write_memory(&self, args: &RuntimeArgs, offset: usize, data: &[u8]) -> Result<(), Trap> {
+        let offset: u32 = args.nth_checked(offset)?;
+        self.memory()
+            .set(offset, data)
+            .map_err(|_| Trap::new(TrapKind::MemoryAccessOutOfBounds))
+    }
+}
+
+impl Externals for MockEnvironment {
+    fn invoke_index(
+        &mut self,
+        index: usize,
+        args: RuntimeArgs,
+    ) -> Result<Option<RuntimeValue>, Trap> {
+        let (module, name, signature) = self.host_functions[index].clone();
+        if module == GAS_MODULE && name == "gas" {
+            let gas: u32 = args.nth_checked(0)?;
+            self.gas_used += gas as u64;
+            return Ok(None);
+        }
+
+        match name.as_str() {
+            "getCallDataSize" => {
+                return Ok(Some(RuntimeValue::I32(self.call_data.len() as i32)));
+            }
+            "getCallData" => self.write_memory(&args, 0, &self.call_data)?,
+            "setStorage" => {
+                let key = self.read_memory(&args, 0, 1)?;
+                let value = self.read_memory(&args, 2, 3)?;
+                // Contracts remove a key by setting an empty value, which `getStorage` can't
+                // tell apart from a missing key.
+                if value.is_empty() {
+                    self.storage.remove(&key);
+                } else {
+                    self.storage.insert(key, value);
+                }
+            }
+            "getStorage" => {
+                let key = self.read_memory(&args, 0, 1)?;
+                let value = self.storage.get(&key).cloned().unwrap_or_default();
+                self.write_memory(&args, 2, &value)?;
+                return Ok(Some(RuntimeValue::I32(value.len() as i32)));
+            }
+            "finish" | "revert" => {
+                self.output = self.read_memory(&args, 0, 1)?;
+                let outcome = if name == "finish" {
+                    Outcome::Finished
+                } else {
+                    Outcome::Reverted
+                };
+                return Err(Trap::new(TrapKind::Host(Box::new(outcome))));
+            }
+            _ => (),
+        }
+
+        Ok(signature.return_type().map(|ty| match ty {
+            ValueType::I32 => RuntimeValue::I32(0),
+            ValueType::I64 => RuntimeValue::I64(0),
+            ValueType::F32 => RuntimeValue::F32(0f32.into()),
+            ValueType::F64 => RuntimeValue::F64(0f64.into()),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parity_wasm::elements::{
+        CodeSection, DataSection, DataSegment, ExportEntry, ExportSection, External, Func,
+        FuncBody, FunctionSection, FunctionType, ImportEntry, ImportSection, InitExpr, Instruction,
+        Instructions, Internal, MemorySection, MemoryType, Section, Type, TypeSection,
+        ValueType as WasmType,
+    };
+
+    /// A contract whose start function stores `v` at the key `k` with `setStorage`.
+    fn storing_on_start() -> Module {
+        use Instruction::*;
+
+        Module::new(vec![
+            Section::Type(TypeSection::with_types(vec![
+                Type::Function(FunctionType::new(vec![WasmType::I32; 4], Vec::new())),
+                Type::Function(FunctionType::new(Vec::new(), Vec::new())),
+            ])),
+            Section::Import(ImportSection::with_entries(vec![ImportEntry::new(
+                "bcos".to_owned(),
+                "setStorage".to_owned(),
+                External::Function(0),
+            )])),
+            Section::Function(FunctionSection::with_entries(vec![Func::new(1)])),
+            Section::Memory(MemorySection::with_entries(vec![MemoryType::new(1, None)])),
+            Section::Export(ExportSection::with_entries(vec![ExportEntry::new(
+                "memory".to_owned(),
+                Internal::Memory(0),
+            )])),
+            Section::Start(1),
+            Section::Code(CodeSection::with_bodies(vec![FuncBody::new(
+                Vec::new(),
+                Instructions::new(vec![
+                    I32Const(0),
+                    I32Const(1),
+                    I32Const(1),
+                    I32Const(1),
+                    Call(0),
+                    End,
+                ]),
+            )])),
+            Section::Data(DataSection::with_entries(vec![DataSegment::new(
+                0,
+                Some(InitExpr::new(vec![I32Const(0), End])),
+                b"kv".to_vec(),
+            )])),
+        ])
+    }
+
+    #[test]
+    fn start_function_calls_host_functions() {
+        let mut env = MockEnvironment::new(Vec::new());
+        env.instantiate(storing_on_start()).unwrap();
+        assert_eq!(env.storage().get(&b"k"[..]), Some(&b"v".to_vec()));
+    }
+}
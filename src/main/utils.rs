@@ -14,13 +14,22 @@ use crate::workspace::ManifestPath;
 use anyhow::{Context, Result};
 use cargo_metadata::{Metadata as CargoMetadata, MetadataCommand, PackageId};
 use rustc_version::Channel;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
-pub fn get_cargo_metadata(manifest_path: &ManifestPath) -> Result<(CargoMetadata, PackageId)> {
+/// Runs `cargo metadata` on the manifest, which may be a virtual manifest of a workspace.
+pub fn get_workspace_metadata(manifest_path: &ManifestPath) -> Result<CargoMetadata> {
     let mut cmd = MetadataCommand::new();
-    let metadata = cmd.manifest_path(manifest_path).exec().context(format!(
-        "Error invoking `cargo metadata` on {:#?}",
+    cmd.manifest_path(manifest_path).exec().context(format!(
+        "Error invoking `cargo metadata` on '{}'",
         manifest_path
-    ))?;
+    ))
+}
+
+pub fn get_cargo_metadata(manifest_path: &ManifestPath) -> Result<(CargoMetadata, PackageId)> {
+    let metadata = get_workspace_metadata(manifest_path)?;
     let root_packaged_id = metadata
         .resolve
         .as_ref()
@@ -31,16 +40,125 @@ pub fn get_cargo_metadata(manifest_path: &ManifestPath) -> Result<(CargoMetadata
     Ok((metadata, root_packaged_id))
 }
 
+/// The nightly toolchain pinned by `rust-toolchain.toml` of newly created projects.
+pub const REQUIRED_TOOLCHAIN: &str = "nightly-2024-02-25";
+
 pub fn check_channel() -> Result<()> {
     let meta = rustc_version::version_meta()?;
     match meta.channel {
         Channel::Dev | Channel::Nightly => Ok(()),
         _ => {
             anyhow::bail!(
-                "cargo-liquid cannot build using the {:?} channel, \
-                 switch to nightly.",
+                "cargo-liquid cannot build using the {} channel (rustc {}), because building \
+                 the sysroot for Wasm with xargo and `build-std` requires a nightly compiler.\n\
+                 Liquid projects are built with `{}`, switch to it by running the following \
+                 command in the root of your project:\n\n    rustup override set {}\n",
                 format!("{:?}", meta.channel).to_lowercase(),
+                meta.semver,
+                REQUIRED_TOOLCHAIN,
+                REQUIRED_TOOLCHAIN,
             );
         }
     }
 }
+
+/// Computes the SHA-256 digest of `data` as a lowercase hex string.
+pub fn sha256_hex(data: &[u8]) -> String {
+    encode_hex(&Sha256::digest(data))
+}
+
+/// Lists files under `dir` recursively which match `filter` in a stable order. The target
+/// directory and hidden directories, e.g. `.git`, are skipped.
+fn files_under(dir: &Path, filter: impl Fn(&Path) -> bool) -> Result<Vec<PathBuf>> {
+    let entries = WalkDir::new(dir)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            entry.depth() == 0 || !(name.starts_with('.') || name == "target")
+        });
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry.context(format!("Reading directory '{}'", dir.display()))?;
+        if entry.file_type().is_file() && filter(entry.path()) {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
+
+/// Lists `*.rs` files under the directory of a package recursively in a stable order. The target
+/// directory and hidden directories, e.g. `.git`, are skipped.
+pub fn rust_source_files(package_dir: &Path) -> Result<Vec<PathBuf>> {
+    files_under(package_dir, |path| path.extension() == Some("rs".as_ref()))
+}
+
+/// Lists `*.rs` files, manifests and toolchain files under the directory of a package recursively
+/// in a stable order, i.e. all files of the package which cargo builds from.
+pub fn package_files(package_dir: &Path) -> Result<Vec<PathBuf>> {
+    files_under(package_dir, |path| {
+        path.extension() == Some("rs".as_ref())
+            || ["Cargo.toml", "rust-toolchain", "rust-toolchain.toml"]
+                .iter()
+                .any(|file| path.file_name() == Some(file.as_ref()))
+    })
+}
+
+/// Encodes `bytes` as a lowercase hex string without the `0x` prefix.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decodes a hex string, the `0x` prefix is optional.
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    let hex = hex.trim();
+    let hex = hex.strip_prefix("0x").unwrap_or(hex);
+    if !hex.is_ascii() || hex.len() % 2 == 1 {
+        anyhow::bail!("`{}` is not a valid hex string", hex);
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&hex[i..i + 2], 16)
+                .context(format!("`{}` is not a valid hex string", hex))
+        })
+        .collect()
+}
+
+/// The group which contracts are deployed to.
+pub const DEFAULT_GROUP: &str = "group0";
+
+/// Calls `method` of the JSON-RPC endpoint of a node, returns the `result` field of the response.
+pub fn call_rpc(rpc_url: &str, method: &str, params: Value) -> Result<Value> {
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": method,
+        "params": params,
+        "id": 1,
+    });
+    let response: Value = ureq::post(rpc_url)
+        .send_json(request)
+        .map_err(|e| anyhow::anyhow!("{}", e))
+        .context(format!("Requesting `{}` from '{}'", method, rpc_url))?
+        .into_json()
+        .context(format!("Parsing the response of `{}`", method))?;
+
+    if let Some(error) = response.get("error") {
+        anyhow::bail!("`{}` failed: {}", method, error);
+    }
+    response
+        .get("result")
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("unexpected response of `{}`: {}", method, response))
+}
+
+/// Quotes `arg` for POSIX shells if it contains any special character.
+pub fn shell_quote(arg: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_=./:,+@".contains(c);
+    if !arg.is_empty() && arg.chars().all(is_plain) {
+        arg.to_owned()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
+    }
+}
@@ -15,14 +15,17 @@ use cargo_metadata::{Metadata as CargoMetadata, Package, PackageId};
 use std::{
     collections::{HashMap, HashSet},
     convert::{TryFrom, TryInto},
-    fs,
+    env, fmt, fs,
     path::{Path, PathBuf, MAIN_SEPARATOR},
+    str::FromStr,
 };
 use toml::value;
 
 const MANIFEST_FILE: &str = "Cargo.toml";
+/// Fields of the manifest which cargo-liquid relies on, so they can't be overridden.
+const PROTECTED_FIELDS: [&str; 2] = ["lib.crate-type", "package.name"];
 
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct ManifestPath {
     path: PathBuf,
 }
@@ -40,6 +43,35 @@ impl ManifestPath {
             path: manifest.into(),
         })
     }
+
+    /// The absolute path of the manifest, resolved against the current working directory if the
+    /// manifest doesn't exist.
+    fn absolute(&self) -> PathBuf {
+        self.path.canonicalize().unwrap_or_else(|_| {
+            env::current_dir().map_or_else(|_| self.path.clone(), |cwd| cwd.join(&self.path))
+        })
+    }
+}
+
+/// Displays the path relative to the current working directory for brevity, falls back to the
+/// absolute path if the manifest is outside of it.
+impl fmt::Display for ManifestPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let absolute = self.absolute();
+        let relative = env::current_dir()
+            .ok()
+            .and_then(|cwd| cwd.canonicalize().ok())
+            .and_then(|cwd| absolute.strip_prefix(cwd).ok().map(Path::to_path_buf));
+        write!(f, "{}", relative.unwrap_or(absolute).display())
+    }
+}
+
+impl fmt::Debug for ManifestPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("ManifestPath")
+            .field(&self.absolute())
+            .finish()
+    }
 }
 
 impl TryFrom<&PathBuf> for ManifestPath {
@@ -83,8 +115,10 @@ impl Manifest {
         P: TryInto<ManifestPath, Error = anyhow::Error>,
     {
         let manifest_path = path.try_into()?;
-        let toml = fs::read_to_string(&manifest_path).context("Loading Cargo.toml")?;
-        let toml: value::Table = toml::from_str(&toml)?;
+        let toml =
+            fs::read_to_string(&manifest_path).context(format!("Loading '{}'", manifest_path))?;
+        let toml: value::Table =
+            toml::from_str(&toml).context(format!("Parsing '{}'", manifest_path))?;
 
         Ok(Manifest {
             path: manifest_path,
@@ -134,6 +168,83 @@ impl Manifest {
         Ok(self)
     }
 
+    /// Set `[profile.release]` panic strategy
+    pub fn with_profile_release_panic(&mut self, strategy: &str) -> Result<&mut Self> {
+        let profile = self
+            .toml
+            .entry("profile")
+            .or_insert(value::Value::Table(Default::default()));
+        let release = profile
+            .as_table_mut()
+            .ok_or_else(|| anyhow::anyhow!("profile should be a table"))?
+            .entry("release")
+            .or_insert(value::Value::Table(Default::default()));
+        release
+            .as_table_mut()
+            .ok_or_else(|| anyhow::anyhow!("release should be a table"))?
+            .insert("panic".into(), strategy.into());
+        Ok(self)
+    }
+
+    /// Add `package_name = { path = <path> }` to the `[patch.<source>]` section, replacing the
+    /// existing patch of the package if any.
+    pub fn with_patch(
+        &mut self,
+        source: &str,
+        package_name: &str,
+        path: &Path,
+    ) -> Result<&mut Self> {
+        let patch = self
+            .toml
+            .entry("patch")
+            .or_insert(value::Value::Table(Default::default()));
+        let source = patch
+            .as_table_mut()
+            .ok_or_else(|| anyhow::anyhow!("patch should be a table"))?
+            .entry(source)
+            .or_insert(value::Value::Table(Default::default()));
+        let mut dependency = value::Table::new();
+        dependency.insert(
+            "path".into(),
+            value::Value::String(path.to_string_lossy().into()),
+        );
+        source
+            .as_table_mut()
+            .ok_or_else(|| anyhow::anyhow!("patch source should be a table"))?
+            .insert(package_name.into(), value::Value::Table(dependency));
+        Ok(self)
+    }
+
+    /// Set the version of `name` in the `[workspace.dependencies]` section, other fields of the
+    /// dependency such as `features` are kept.
+    pub fn with_workspace_dependency(&mut self, name: &str, version: &str) -> Result<&mut Self> {
+        let dependency = self
+            .toml
+            .get_mut("workspace")
+            .and_then(value::Value::as_table_mut)
+            .and_then(|workspace| workspace.get_mut("dependencies"))
+            .and_then(value::Value::as_table_mut)
+            .and_then(|dependencies| dependencies.get_mut(name))
+            .ok_or_else(|| {
+                anyhow::anyhow!("`{}` is not found in `[workspace.dependencies]`", name)
+            })?;
+        match dependency {
+            value::Value::Table(dependency) => {
+                dependency.insert("version".into(), version.into());
+            }
+            dependency => *dependency = version.into(),
+        }
+        Ok(self)
+    }
+
+    /// Apply `manifest_override` on top of the manifest. Tables are merged, other values are
+    /// replaced.
+    pub fn with_override(&mut self, manifest_override: &ManifestOverride) -> Result<&mut Self> {
+        merge_override(&mut self.toml, &manifest_override.toml, "")
+            .context(format!("Applying `{}`", manifest_override.source))?;
+        Ok(self)
+    }
+
     /// Get mutable reference to `[lib] crate-types = []` section
     fn get_crate_types_mut(&mut self) -> Result<&mut value::Array> {
         let lib = self
@@ -291,6 +402,92 @@ impl Manifest {
     }
 }
 
+/// A patch of the manifest in TOML dot notation, e.g. `profile.release.codegen-units = 1`.
+#[derive(Clone, Debug)]
+pub struct ManifestOverride {
+    source: String,
+    toml: value::Table,
+}
+
+impl FromStr for ManifestOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let toml: value::Table = toml::from_str(s).context(format!(
+            "`{}` is not a valid override, it should be like `profile.release.codegen-units = 1`",
+            s
+        ))?;
+        if toml.is_empty() {
+            anyhow::bail!("`{}` doesn't override anything", s);
+        }
+        Ok(ManifestOverride {
+            source: s.to_owned(),
+            toml,
+        })
+    }
+}
+
+/// A `NAME=VERSION` override of a dependency in `[workspace.dependencies]`.
+#[derive(Clone, Debug)]
+pub struct WorkspaceDependencyOverride {
+    pub name: String,
+    pub version: String,
+}
+
+impl FromStr for WorkspaceDependencyOverride {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            Some((name, version)) if !name.trim().is_empty() && !version.trim().is_empty() => {
+                Ok(WorkspaceDependencyOverride {
+                    name: name.trim().to_owned(),
+                    version: version.trim().to_owned(),
+                })
+            }
+            _ => anyhow::bail!(
+                "`{}` is not a valid override, it should be like `serde=1.0.100`",
+                s
+            ),
+        }
+    }
+}
+
+/// Merge `patch` into `toml` recursively, `path` is the dotted path of `toml` in the manifest.
+fn merge_override(toml: &mut value::Table, patch: &value::Table, path: &str) -> Result<()> {
+    for (key, value) in patch {
+        let key_path = if path.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        if PROTECTED_FIELDS.contains(&key_path.as_str()) {
+            anyhow::bail!(
+                "`{}` is required by cargo-liquid and can't be overridden",
+                key_path
+            );
+        }
+        match (toml.get_mut(key), value) {
+            (Some(value::Value::Table(table)), value::Value::Table(patch)) => {
+                merge_override(table, patch, &key_path)?
+            }
+            (Some(existing), value) if existing.is_table() || value.is_table() => {
+                anyhow::bail!(
+                    "`{}` is of type `{}` in the manifest, it can't be overridden with a value of \
+                     type `{}`",
+                    key_path,
+                    existing.type_str(),
+                    value.type_str()
+                )
+            }
+            _ => {
+                toml.insert(key.clone(), value.clone());
+            }
+        }
+    }
+    Ok(())
+}
+
 #[allow(clippy::ptr_arg)]
 fn crate_type_exists(crate_type: &str, crate_types: &value::Array) -> bool {
     crate_types
@@ -308,6 +505,8 @@ pub struct Workspace {
     workspace_root: PathBuf,
     root_package: PackageId,
     members: HashMap<PackageId, (Package, Manifest)>,
+    lockfile: Option<PathBuf>,
+    keep_temp: bool,
 }
 
 impl Workspace {
@@ -342,9 +541,29 @@ impl Workspace {
             workspace_root: metadata.workspace_root.clone(),
             root_package: root_package.clone(),
             members,
+            lockfile: None,
+            keep_temp: false,
         })
     }
 
+    /// Use the supplied `Cargo.lock` in the temporary workspace, so that dependencies are
+    /// resolved exactly as pinned by it.
+    pub fn with_lockfile<P: AsRef<Path>>(&mut self, lockfile: P) -> Result<&mut Self> {
+        let lockfile = lockfile.as_ref();
+        if !lockfile.is_file() {
+            anyhow::bail!("lockfile `{}` does not exist", lockfile.display());
+        }
+        self.lockfile = Some(lockfile.into());
+        Ok(self)
+    }
+
+    /// Leave the temporary workspace on disk after `using_temp` returns, so that the amended
+    /// manifests can be inspected.
+    pub fn with_keep_temp(&mut self, keep_temp: bool) -> &mut Self {
+        self.keep_temp = keep_temp;
+        self
+    }
+
     /// Amend the root package manifest using the supplied function.
     ///
     /// # Note
@@ -364,6 +583,39 @@ impl Workspace {
         Ok(self)
     }
 
+    /// Override the version of `name` in `[workspace.dependencies]` of the workspace root
+    /// manifest, so that all members inheriting the dependency use `version` instead.
+    ///
+    /// Only the copy in the temporary workspace is modified, the original manifest is never
+    /// touched, so there is nothing to revert.
+    pub fn with_workspace_dependency_override(
+        &mut self,
+        name: &str,
+        version: &str,
+    ) -> Result<&mut Self> {
+        let root_manifest_path = self.workspace_root.join("Cargo.toml");
+        let root_manifest = self
+            .members
+            .values_mut()
+            .find(|(package, _)| package.manifest_path == root_manifest_path)
+            .map(|(_, manifest)| manifest)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "the workspace root manifest '{}' doesn't belong to a package, overriding \
+                     its `[workspace.dependencies]` is not supported",
+                    root_manifest_path.display()
+                )
+            })?;
+        root_manifest
+            .with_workspace_dependency(name, version)
+            .context(format!(
+                "Overriding `{}` in '{}'",
+                name,
+                root_manifest_path.display()
+            ))?;
+        Ok(self)
+    }
+
     /// Copy the workspace with amended manifest files to a temporary directory, executing the
     /// supplied function with the root manifest path before the directory is cleaned up.
     pub fn using_temp<F, R>(&mut self, f: F) -> Result<R>
@@ -384,7 +636,21 @@ impl Workspace {
                 }
             })
             .expect("root package should be a member of the temp workspace");
-        f(root_manifest_path)
+        if let Some(lockfile) = &self.lockfile {
+            let dest = tmp_dir.path().join("Cargo.lock");
+            fs::copy(lockfile, &dest).context(format!(
+                "Copying lockfile '{}' to '{}'",
+                lockfile.display(),
+                dest.display()
+            ))?;
+        }
+
+        let result = f(root_manifest_path);
+        if self.keep_temp {
+            let tmp_path = tmp_dir.into_path();
+            eprintln!("Temporary workspace is kept at '{}'", tmp_path.display());
+        }
+        result
     }
 
     /// Writes the amended manifests to the `target` directory, retaining the workspace directory